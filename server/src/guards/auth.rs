@@ -28,7 +28,10 @@ impl FromRequest for AuthUser {
 
         let maybe_user = {
             let users = state.users.clone();
-            let list = block_on(users.list());
+            let list = match block_on(users.list()) {
+                Ok(list) => list,
+                Err(e) => return ready(Err(e.into())),
+            };
 
             list.iter().find(|u| u.1.password_hash == token).cloned()
         };
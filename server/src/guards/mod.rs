@@ -1,2 +1,32 @@
+use actix_web::{Error, HttpResponse, error::InternalError};
+use serde_json::json;
+
 pub mod auth;
+pub mod page;
+pub mod patch;
 pub mod path;
+pub mod pointer;
+pub mod precondition;
+pub mod ttl;
+
+/// A `400 Bad Request` answer with `msg` in the usual `{"ok", "message", "data"}` body.
+pub fn bad_request(msg: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "ok": false,
+        "message": msg,
+        "data": {}
+    }))
+}
+
+/// [`bad_request`] as an error, for extractors rejecting a request.
+pub fn json_bad_request(msg: &str) -> Error {
+    InternalError::from_response(msg.to_string(), bad_request(msg)).into()
+}
+
+/// Whether `name` can name an index or webhook: 1 to 64 ASCII letters, digits, `-` or `_`.
+pub fn is_valid_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
@@ -0,0 +1,76 @@
+use actix_web::{Error, FromRequest, HttpRequest, dev::Payload, web::Query};
+use futures::future::{Ready, ready};
+use serde::Deserialize;
+
+use super::json_bad_request;
+use crate::structs::encoding::hex;
+
+/// Largest page a client may ask for.
+pub const MAX_PAGE_SIZE: usize = 1000;
+/// Response header carrying the cursor of the next page, absent on the last one.
+pub const NEXT_CURSOR_HEADER: &str = "X-Next-Cursor";
+
+/// Pagination of a listing, read from `?limit=<n>&cursor=<cursor>`.
+///
+/// Without a limit everything is returned at once. Cursors are the last key of the previous
+/// page, hex encoded so they survive any URL untouched.
+#[derive(Debug, Clone, Default)]
+pub struct Page {
+    pub limit: Option<usize>,
+    /// Key the page starts after, decoded from the cursor.
+    pub after: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PageQuery {
+    limit: Option<String>,
+    cursor: Option<String>,
+}
+
+impl FromRequest for Page {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let Ok(query) = Query::<PageQuery>::from_query(req.query_string()) else {
+            return ready(Err(json_bad_request("invalid query string")));
+        };
+        let PageQuery { limit, cursor } = query.into_inner();
+
+        let limit = match limit.map(|limit| limit.trim().parse::<usize>()) {
+            None => None,
+            Some(Ok(limit)) if (1..=MAX_PAGE_SIZE).contains(&limit) => Some(limit),
+            Some(_) => {
+                return ready(Err(json_bad_request(&format!(
+                    "limit must be between 1 and {MAX_PAGE_SIZE}"
+                ))));
+            }
+        };
+
+        let after = match cursor.map(|cursor| decode_cursor(&cursor)) {
+            None => None,
+            Some(Some(after)) => Some(after),
+            Some(None) => return ready(Err(json_bad_request("invalid cursor"))),
+        };
+
+        ready(Ok(Page { limit, after }))
+    }
+}
+
+/// The cursor that continues a listing after `key`.
+pub fn cursor(key: &str) -> String {
+    hex(key.as_bytes())
+}
+
+fn decode_cursor(cursor: &str) -> Option<String> {
+    if cursor.len() % 2 != 0 {
+        return None;
+    }
+
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(cursor.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    String::from_utf8(bytes).ok()
+}
@@ -0,0 +1,48 @@
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest, dev::Payload, web::Json};
+use futures::future::LocalBoxFuture;
+use serde_json::Value;
+
+use crate::structs::patch::JsonPatch;
+
+/// Content type of a JSON Merge Patch (RFC 7396) body.
+pub const MERGE_PATCH: &str = "application/merge-patch+json";
+/// Content type of a JSON Patch (RFC 6902) body.
+pub const JSON_PATCH: &str = "application/json-patch+json";
+
+/// Body of a `PATCH`, read according to its `Content-Type`.
+///
+/// Plain JSON replaces the whole value. The body goes through the [`Json`] extractor first,
+/// so the usual size limit applies and non JSON content types are refused.
+#[derive(Debug, Clone)]
+pub enum PatchBody {
+    /// `application/json`: the new value.
+    Replace(Value),
+    /// [`MERGE_PATCH`]: merged into the current value.
+    Merge(Value),
+    /// [`JSON_PATCH`]: operations applied to the current value.
+    Operations(JsonPatch),
+}
+
+impl FromRequest for PatchBody {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let mime = req
+            .mime_type()
+            .ok()
+            .flatten()
+            .map(|mime| mime.essence_str().to_ascii_lowercase());
+        let body = Json::<Value>::from_request(req, payload);
+
+        Box::pin(async move {
+            let value = body.await?.into_inner();
+
+            Ok(match mime.as_deref() {
+                Some(MERGE_PATCH) => PatchBody::Merge(value),
+                Some(JSON_PATCH) => PatchBody::Operations(JsonPatch::from_value(value)?),
+                _ => PatchBody::Replace(value),
+            })
+        })
+    }
+}
@@ -1,5 +1,6 @@
 use actix_web::{Error, FromRequest, HttpRequest, dev::Payload};
 use futures::future::{Ready, ready};
+use serde::Deserialize;
 
 #[derive(Debug, Clone)]
 pub struct SanitizedKey(pub String);
@@ -16,3 +17,9 @@ impl FromRequest for SanitizedKey {
         }
     }
 }
+
+/// Revision number in routes addressing `{key}@{version}`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Revision {
+    pub version: u64,
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+
+use actix_web::{
+    Error, FromRequest, HttpRequest, HttpResponse, ResponseError, dev::Payload, http::StatusCode,
+    web::Query,
+};
+use futures::future::{Ready, ready};
+use serde_json::{Map, Value, json};
+
+use super::json_bad_request;
+
+/// Checks that `pointer` is a JSON Pointer: empty, or starting with `/`.
+pub fn check_pointer(pointer: &str) -> Result<(), &'static str> {
+    match pointer.is_empty() || pointer.starts_with('/') {
+        true => Ok(()),
+        false => Err("pointers must be empty or start with '/'"),
+    }
+}
+
+/// Part of a value a read asks for, as RFC 6901 JSON Pointers.
+///
+/// `?pointer=/settings/theme` returns that sub-tree alone. `?field=<pointer>`, repeated,
+/// returns an object mapping each pointer to what it points at. The two do not mix.
+#[derive(Debug, Clone, Default)]
+pub enum Selection {
+    /// The whole value.
+    #[default]
+    Whole,
+    /// The sub-tree at a pointer.
+    Pointer(String),
+    /// Several sub-trees, keyed by their pointers.
+    Fields(Vec<String>),
+}
+
+/// A selected pointer that does not exist in the value, as opposed to a missing entry.
+#[derive(Debug, Clone)]
+pub struct PathNotFound(pub String);
+
+impl Selection {
+    /// Cuts the selected parts out of `value`. Fields may overlap.
+    pub fn select(&self, mut value: Value) -> Result<Value, PathNotFound> {
+        let missing = |pointer: &str| PathNotFound(pointer.to_owned());
+
+        match self {
+            Self::Whole => Ok(value),
+            Self::Pointer(pointer) => value
+                .pointer_mut(pointer)
+                .map(Value::take)
+                .ok_or_else(|| missing(pointer)),
+            Self::Fields(pointers) => pointers
+                .iter()
+                .map(|pointer| match value.pointer(pointer) {
+                    Some(field) => Ok((pointer.clone(), field.clone())),
+                    None => Err(missing(pointer)),
+                })
+                .collect::<Result<Map<_, _>, _>>()
+                .map(Value::Object),
+        }
+    }
+}
+
+impl FromRequest for Selection {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let Ok(query) = Query::<Vec<(String, String)>>::from_query(req.query_string()) else {
+            return ready(Err(json_bad_request("invalid query string")));
+        };
+
+        let mut pointers = Vec::new();
+        let mut fields = Vec::new();
+        for (name, value) in query.into_inner() {
+            match name.as_str() {
+                "pointer" => pointers.push(value),
+                "field" => fields.push(value),
+                _ => continue,
+            }
+        }
+
+        if let Err(msg) = pointers
+            .iter()
+            .chain(&fields)
+            .try_for_each(|pointer| check_pointer(pointer))
+        {
+            return ready(Err(json_bad_request(msg)));
+        }
+
+        ready(match (pointers.len(), fields.is_empty()) {
+            (0, true) => Ok(Selection::Whole),
+            (0, false) => Ok(Selection::Fields(fields)),
+            (1, true) => Ok(Selection::Pointer(pointers.remove(0))),
+            _ => Err(json_bad_request(
+                "use either a single pointer or a list of fields",
+            )),
+        })
+    }
+}
+
+impl fmt::Display for PathNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "path {:?} does not exist in the entry", self.0)
+    }
+}
+
+impl std::error::Error for PathNotFound {}
+
+/// Answers `404` like a missing entry, but names the pointer in `data.pointer`.
+impl ResponseError for PathNotFound {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::NOT_FOUND
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::NotFound().json(json!({
+            "ok": false,
+            "message": "This path does not exist in the entry",
+            "data": { "pointer": self.0 }
+        }))
+    }
+}
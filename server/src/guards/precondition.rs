@@ -0,0 +1,58 @@
+use actix_web::{
+    Error, FromRequest, HttpRequest,
+    dev::Payload,
+    http::header::{ETag, EntityTag, Header, IfMatch, IfNoneMatch},
+};
+use futures::future::{Ready, ready};
+
+use super::json_bad_request;
+use crate::providers::{Precondition, VersionMatch};
+
+/// `If-Match` and `If-None-Match` headers of a write, as versions the entry must (not) have.
+///
+/// Entries are tagged with their version, see [`etag`]. `If-Match` compares strongly, so
+/// weak tags never satisfy it; `If-None-Match` compares weakly.
+#[derive(Debug, Clone)]
+pub struct Conditional(pub Precondition);
+
+impl FromRequest for Conditional {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let has = |name| req.headers().contains_key(name);
+
+        let if_match = match IfMatch::parse(req) {
+            _ if !has(IfMatch::name()) => None,
+            Ok(IfMatch::Any) => Some(VersionMatch::Any),
+            Ok(IfMatch::Items(tags)) => Some(versions(&tags, true)),
+            Err(_) => return ready(Err(json_bad_request("invalid If-Match header"))),
+        };
+        let if_none_match = match IfNoneMatch::parse(req) {
+            _ if !has(IfNoneMatch::name()) => None,
+            Ok(IfNoneMatch::Any) => Some(VersionMatch::Any),
+            Ok(IfNoneMatch::Items(tags)) => Some(versions(&tags, false)),
+            Err(_) => return ready(Err(json_bad_request("invalid If-None-Match header"))),
+        };
+
+        ready(Ok(Conditional(Precondition {
+            if_match,
+            if_none_match,
+        })))
+    }
+}
+
+/// The `ETag` of an entry at `version`.
+pub fn etag(version: u64) -> ETag {
+    ETag(EntityTag::new_strong(version.to_string()))
+}
+
+/// Versions named by `tags`. Tags that are not ours can never match and are dropped.
+fn versions(tags: &[EntityTag], strong_only: bool) -> VersionMatch {
+    VersionMatch::Versions(
+        tags.iter()
+            .filter(|tag| !(strong_only && tag.weak))
+            .filter_map(|tag| tag.tag().parse().ok())
+            .collect(),
+    )
+}
@@ -0,0 +1,56 @@
+use actix_web::{Error, FromRequest, HttpRequest, dev::Payload, web::Query};
+use chrono::TimeDelta;
+use futures::future::{Ready, ready};
+use serde::Deserialize;
+
+use super::json_bad_request;
+
+/// Header carrying the TTL of a write, in seconds.
+pub const TTL_HEADER: &str = "X-TTL";
+
+const NOT_POSITIVE: &str = "ttl must be a positive number of seconds";
+
+/// Optional time to live of a write, read from `?ttl=<seconds>` or the `X-TTL` header.
+///
+/// The query parameter wins when both are present.
+#[derive(Debug, Clone)]
+pub struct Ttl(pub Option<TimeDelta>);
+
+#[derive(Deserialize)]
+struct TtlQuery {
+    ttl: Option<String>,
+}
+
+impl FromRequest for Ttl {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let from_query = Query::<TtlQuery>::from_query(req.query_string())
+            .ok()
+            .and_then(|q| q.into_inner().ttl);
+        let from_header = req
+            .headers()
+            .get(TTL_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned);
+
+        let Some(raw) = from_query.or(from_header) else {
+            return ready(Ok(Ttl(None)));
+        };
+
+        let ttl = match raw.trim().parse::<i64>() {
+            Ok(secs) => parse_ttl(secs),
+            Err(_) => Err(NOT_POSITIVE),
+        };
+        ready(ttl.map(|ttl| Ttl(Some(ttl))).map_err(json_bad_request))
+    }
+}
+
+/// Checks a TTL of `secs` seconds, failing with the message clients get to see.
+pub fn parse_ttl(secs: i64) -> Result<TimeDelta, &'static str> {
+    match secs {
+        secs if secs > 0 => TimeDelta::try_seconds(secs).ok_or("ttl is out of range"),
+        _ => Err(NOT_POSITIVE),
+    }
+}
@@ -1,22 +1,30 @@
-use std::{env, sync::Arc};
+use std::{env, sync::Arc, time::Duration};
 
 use actix_web::{App, HttpServer, middleware::Logger, web::Data};
-use anyhow::Result;
-use providers::{fs::FileSystemProvider, memory::MemoryProvider};
+use anyhow::{Result, bail};
+use providers::{
+    fs::FileSystemProvider,
+    registry::{DynProvider, ProviderRegistry},
+    sweeper::spawn_sweeper,
+};
 use serde_json::Value;
 use structs::user::User;
 use tracing::info;
+use webhooks::{RetryPolicy, Webhooks, spawn_webhooks};
 
 mod guards;
 mod providers;
 mod routes;
 mod structs;
+mod webhooks;
 
 const DEFAULT_PORT: u16 = 8080;
+const DEFAULT_SWEEP_INTERVAL_SECS: u64 = 60;
 
 pub struct AppState {
     users: Arc<FileSystemProvider<User>>,
-    provider: Arc<MemoryProvider<Value>>,
+    provider: DynProvider,
+    webhooks: Arc<Webhooks>,
 }
 
 #[actix_web::main]
@@ -26,18 +34,49 @@ async fn main() -> Result<()> {
 
     info!("Initialized tracing_subscriber");
 
-    // todo: select provider from env
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|cmd| cmd == "reshard") {
+        return reshard(&args[1..]).await;
+    }
 
     let port = env::var("PORT")
         .ok()
         .and_then(|port| port.parse::<u16>().ok())
         .unwrap_or(DEFAULT_PORT);
 
+    let (provider_name, provider) = ProviderRegistry::with_defaults().build_from_env().await?;
+    info!("Using {provider_name} cache provider");
+
+    let sweep_every = env::var("SWEEP_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .filter(|&secs| secs > 0)
+        .unwrap_or(DEFAULT_SWEEP_INTERVAL_SECS);
+    spawn_sweeper(provider.clone(), Duration::from_secs(sweep_every));
+
+    let defaults = RetryPolicy::default();
+    let retry = RetryPolicy {
+        attempts: env::var("WEBHOOK_ATTEMPTS")
+            .ok()
+            .and_then(|attempts| attempts.parse::<u32>().ok())
+            .filter(|&attempts| attempts > 0)
+            .unwrap_or(defaults.attempts),
+        backoff: env::var("WEBHOOK_BACKOFF_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .map_or(defaults.backoff, Duration::from_secs),
+    };
+
+    // Registrations are saved next to users
+    let webhooks = Arc::new(Webhooks::open("./webhooks".into()).await?.with_retry(retry));
+    spawn_webhooks(provider.clone(), webhooks.clone());
+
     // Users are stored in the same way cache is
     // Warning: When using fs provider, remember to ignore the path
     let shared_data = Data::new(AppState {
         users: Arc::new(FileSystemProvider::new("./users".into()).await?),
-        provider: Arc::new(MemoryProvider::new(50)),
+        provider,
+        webhooks,
     });
 
     HttpServer::new(move || {
@@ -52,3 +91,13 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// `server reshard <dir> <depth>`: moves an fs cache directory to a new shard depth in place.
+async fn reshard(args: &[String]) -> Result<()> {
+    let [dir, depth] = args else {
+        bail!("usage: server reshard <dir> <depth>");
+    };
+
+    FileSystemProvider::<Value>::with_shard_depth(dir.into(), depth.parse()?).await?;
+    Ok(())
+}
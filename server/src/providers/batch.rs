@@ -0,0 +1,305 @@
+use std::{collections::HashMap, fmt};
+
+use serde::Serialize;
+
+use crate::structs::{entry::Entry, metadata::Metadata};
+
+use super::{Precondition, ProviderError, WriteOptions};
+
+/// One step of a transaction, see [`CacheProvider::transact`](super::CacheProvider::transact).
+///
+/// Each step sees the entries as the steps before it left them.
+#[derive(Debug, Clone)]
+pub enum BatchOperation<T> {
+    /// Reads the entry, failing if it does not exist.
+    Get { key: String },
+    /// Creates the entry, like [`CacheProvider::add`](super::CacheProvider::add).
+    Add {
+        key: String,
+        value: T,
+        options: WriteOptions,
+    },
+    /// Replaces the value, like [`CacheProvider::update`](super::CacheProvider::update).
+    Update {
+        key: String,
+        value: T,
+        options: WriteOptions,
+    },
+    /// Deletes the entry, like [`CacheProvider::remove`](super::CacheProvider::remove).
+    Remove {
+        key: String,
+        precondition: Precondition,
+    },
+    /// Writes nothing, but fails unless the entry satisfies `precondition`.
+    Check {
+        key: String,
+        precondition: Precondition,
+    },
+}
+
+/// What a step of a transaction that went through returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOutcome<T> {
+    /// The entry read by a `Get`.
+    Entry(Entry<T>),
+    /// The version an `Add` or `Update` wrote.
+    Written(u64),
+    /// The value a `Remove` deleted.
+    Removed(T),
+    /// A `Check` held.
+    Checked,
+}
+
+/// Why a transaction was refused: step `index`, counted from 0, failed with `error`.
+#[derive(Debug)]
+pub struct BatchError {
+    pub index: usize,
+    pub error: ProviderError,
+}
+
+/// An entry a transaction writes, or removes when `entry` is `None`.
+#[derive(Debug, Clone)]
+pub struct BatchWrite<T> {
+    /// Step the write comes from.
+    pub index: usize,
+    pub key: String,
+    pub entry: Option<Entry<T>>,
+}
+
+/// A transaction worked out against the entries it touches, not applied yet.
+#[derive(Debug, Clone)]
+pub struct BatchPlan<T> {
+    /// Result of every step, in order.
+    pub outcomes: Vec<BatchOutcome<T>>,
+    /// Writes to apply, in order. A key written twice shows up twice.
+    pub writes: Vec<BatchWrite<T>>,
+}
+
+impl<T> BatchOperation<T> {
+    pub fn key(&self) -> &str {
+        match self {
+            Self::Get { key }
+            | Self::Add { key, .. }
+            | Self::Update { key, .. }
+            | Self::Remove { key, .. }
+            | Self::Check { key, .. } => key,
+        }
+    }
+}
+
+impl<T: Clone + Serialize> BatchPlan<T> {
+    /// Runs `operations` for `issuer` against `current`, the live entry of every key they
+    /// touch (`None` if it does not exist), without applying anything.
+    ///
+    /// Providers hold whatever locks guard those keys from reading `current` until the writes
+    /// are applied.
+    pub fn new(
+        operations: Vec<BatchOperation<T>>,
+        issuer: &str,
+        mut current: HashMap<String, Option<Entry<T>>>,
+    ) -> Result<Self, BatchError> {
+        let mut plan = Self {
+            outcomes: Vec::with_capacity(operations.len()),
+            writes: Vec::new(),
+        };
+
+        for (index, operation) in operations.into_iter().enumerate() {
+            let state = current.entry(operation.key().to_owned()).or_default();
+            let version = state.as_ref().map(|entry| entry.metadata.version);
+
+            let outcome = match operation {
+                BatchOperation::Get { .. } => state
+                    .clone()
+                    .map(BatchOutcome::Entry)
+                    .ok_or(ProviderError::NotFound),
+                BatchOperation::Add {
+                    key,
+                    value,
+                    options,
+                } => options
+                    .precondition
+                    .check(version)
+                    .and_then(|()| match state {
+                        Some(_) => Err(ProviderError::AlreadyExists),
+                        None => Ok(Metadata::new(
+                            &value,
+                            issuer.to_owned(),
+                            options.expires_at(),
+                        )?),
+                    })
+                    .map(|metadata| plan.write(index, key, state, Entry { value, metadata })),
+                BatchOperation::Update {
+                    key,
+                    value,
+                    options,
+                } => options
+                    .precondition
+                    .check(version)
+                    .and_then(|()| {
+                        let mut metadata = state
+                            .as_ref()
+                            .map(|entry| entry.metadata.clone())
+                            .ok_or(ProviderError::NotFound)?;
+                        metadata.update(&value, issuer.to_owned(), options.expires_at())?;
+                        Ok(metadata)
+                    })
+                    .map(|metadata| plan.write(index, key, state, Entry { value, metadata })),
+                BatchOperation::Remove { key, precondition } => {
+                    precondition.check(version).and_then(|()| {
+                        let removed = state.take().ok_or(ProviderError::NotFound)?;
+                        plan.writes.push(BatchWrite {
+                            index,
+                            key,
+                            entry: None,
+                        });
+                        Ok(BatchOutcome::Removed(removed.value))
+                    })
+                }
+                BatchOperation::Check { precondition, .. } => {
+                    precondition.check(version).map(|()| BatchOutcome::Checked)
+                }
+            };
+
+            match outcome {
+                Ok(outcome) => plan.outcomes.push(outcome),
+                Err(error) => return Err(BatchError { index, error }),
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /// Records that step `index` stores `entry` under `key`, whose live entry is `state`.
+    fn write(
+        &mut self,
+        index: usize,
+        key: String,
+        state: &mut Option<Entry<T>>,
+        entry: Entry<T>,
+    ) -> BatchOutcome<T> {
+        let version = entry.metadata.version;
+        *state = Some(entry.clone());
+        self.writes.push(BatchWrite {
+            index,
+            key,
+            entry: Some(entry),
+        });
+
+        BatchOutcome::Written(version)
+    }
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "batch operation {} failed: {}", self.index, self.error)
+    }
+}
+
+impl std::error::Error for BatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::providers::VersionMatch;
+
+    fn entry(value: Value, version: u64) -> Entry<Value> {
+        let mut metadata = Metadata::new(&value, "t".into(), None).unwrap();
+        metadata.version = version;
+        Entry { value, metadata }
+    }
+
+    #[test]
+    fn steps_see_earlier_steps() {
+        let current = HashMap::from([("a".to_owned(), Some(entry(json!(1), 3)))]);
+        let plan = BatchPlan::new(
+            vec![
+                BatchOperation::Remove {
+                    key: "a".into(),
+                    precondition: Default::default(),
+                },
+                BatchOperation::Add {
+                    key: "a".into(),
+                    value: json!(2),
+                    options: Default::default(),
+                },
+                BatchOperation::Update {
+                    key: "a".into(),
+                    value: json!(3),
+                    options: Default::default(),
+                },
+                BatchOperation::Check {
+                    key: "a".into(),
+                    precondition: Precondition {
+                        if_match: Some(VersionMatch::Versions(vec![1])),
+                        if_none_match: None,
+                    },
+                },
+                BatchOperation::Get { key: "a".into() },
+            ],
+            "u",
+            current,
+        )
+        .unwrap();
+
+        assert_eq!(plan.outcomes[0], BatchOutcome::Removed(json!(1)));
+        assert_eq!(plan.outcomes[1], BatchOutcome::Written(0));
+        assert_eq!(plan.outcomes[2], BatchOutcome::Written(1));
+        assert_eq!(plan.outcomes[3], BatchOutcome::Checked);
+        let BatchOutcome::Entry(read) = &plan.outcomes[4] else {
+            panic!("expected an entry, got {:?}", plan.outcomes[4]);
+        };
+        assert_eq!(
+            (&read.value, read.metadata.created_by.as_str()),
+            (&json!(3), "u")
+        );
+
+        let writes: Vec<_> = plan
+            .writes
+            .iter()
+            .map(|write| (write.index, write.entry.as_ref().map(|entry| &entry.value)))
+            .collect();
+        assert_eq!(
+            writes,
+            vec![(0, None), (1, Some(&json!(2))), (2, Some(&json!(3)))]
+        );
+    }
+
+    #[test]
+    fn reports_the_failing_step() {
+        let failed = BatchPlan::<Value>::new(
+            vec![
+                BatchOperation::Add {
+                    key: "a".into(),
+                    value: json!(1),
+                    options: Default::default(),
+                },
+                BatchOperation::Get { key: "b".into() },
+            ],
+            "t",
+            HashMap::new(),
+        )
+        .unwrap_err();
+        assert_eq!(failed.index, 1);
+        assert!(matches!(failed.error, ProviderError::NotFound));
+
+        let failed = BatchPlan::<Value>::new(
+            vec![BatchOperation::Check {
+                key: "a".into(),
+                precondition: Precondition {
+                    if_match: Some(VersionMatch::Any),
+                    if_none_match: None,
+                },
+            }],
+            "t",
+            HashMap::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(failed.error, ProviderError::PreconditionFailed));
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::structs::metadata::Metadata;
+
+/// Changes a subscriber may fall behind by before it starts missing some.
+const CHANGE_BUFFER: usize = 1024;
+
+/// What happened to an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Add,
+    Update,
+    Remove,
+    /// Removed by a purge of its creator's entries.
+    Purge,
+    /// Removed once its TTL passed.
+    Expire,
+    /// Removed to keep the cache within its limits.
+    Evict,
+}
+
+impl ChangeKind {
+    /// The name the kind is serialized as.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Update => "update",
+            Self::Remove => "remove",
+            Self::Purge => "purge",
+            Self::Expire => "expire",
+            Self::Evict => "evict",
+        }
+    }
+}
+
+/// A change to one entry, as published to subscribers of [`ChangeFeed`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Change<T> {
+    pub kind: ChangeKind,
+    pub key: String,
+    /// Sequence number of the change, see [`SyncLog`](super::sync::SyncLog).
+    pub sequence: u64,
+    /// Metadata of the entry as written, `None` once it is gone.
+    pub metadata: Option<Metadata>,
+    /// The value as written, `None` once it is gone.
+    pub value: Option<T>,
+}
+
+/// Fans the changes of a provider out to every subscriber.
+///
+/// Publishing never waits: each subscriber has its own buffer of [`CHANGE_BUFFER`] changes,
+/// and one that falls further behind skips the oldest, learning how many it missed. Providers
+/// publish while still holding the lock that orders writes to the key, so changes to one key
+/// arrive in the order they happened.
+pub struct ChangeFeed<T> {
+    sender: broadcast::Sender<Arc<Change<T>>>,
+}
+
+impl<T> ChangeFeed<T> {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::Sender::new(CHANGE_BUFFER),
+        }
+    }
+
+    /// Receives every change published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Change<T>>> {
+        self.sender.subscribe()
+    }
+
+    /// Publishes the change built by `change`, which is only called if anyone listens.
+    pub fn publish(&self, change: impl FnOnce() -> Change<T>) {
+        if self.sender.receiver_count() > 0 {
+            // fails only when the last subscriber left in between
+            let _ = self.sender.send(Arc::new(change()));
+        }
+    }
+
+    /// Publishes that `key` is gone because of `kind`, at `sequence`.
+    pub fn removed(&self, kind: ChangeKind, key: &str, sequence: u64) {
+        self.publish(|| Change {
+            kind,
+            key: key.to_owned(),
+            sequence,
+            metadata: None,
+            value: None,
+        });
+    }
+}
+
+impl<T: Clone> ChangeFeed<T> {
+    /// Publishes that `key` now holds `value` with `metadata`.
+    pub fn written(&self, kind: ChangeKind, key: &str, value: &T, metadata: &Metadata) {
+        self.publish(|| Change {
+            kind,
+            key: key.to_owned(),
+            sequence: metadata.sequence,
+            metadata: Some(metadata.clone()),
+            value: Some(value.clone()),
+        });
+    }
+}
+
+impl<T> Default for ChangeFeed<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast::error::RecvError;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn slow_subscribers_skip_instead_of_blocking() {
+        let feed = ChangeFeed::<u32>::new();
+        let mut slow = feed.subscribe();
+
+        for key in 0..CHANGE_BUFFER + 5 {
+            feed.removed(ChangeKind::Remove, &key.to_string(), key as u64);
+        }
+
+        assert!(matches!(slow.recv().await, Err(RecvError::Lagged(5))));
+        assert_eq!(slow.recv().await.unwrap().key, "5");
+    }
+}
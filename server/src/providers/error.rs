@@ -0,0 +1,138 @@
+use std::{fmt, io};
+
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use serde_json::json;
+use tracing::error;
+
+/// Result of a [`CacheProvider`](super::CacheProvider) call.
+pub type ProviderResult<T> = Result<T, ProviderError>;
+
+/// Why a [`CacheProvider`](super::CacheProvider) call failed.
+#[derive(Debug)]
+pub enum ProviderError {
+    /// The key does not exist or has expired.
+    NotFound,
+    /// The key exists, but the requested revision of it is not kept.
+    RevisionNotFound,
+    /// The key already exists and the call does not overwrite.
+    AlreadyExists,
+    /// No index has the given name.
+    IndexNotFound,
+    /// An index with the given name already exists.
+    IndexExists,
+    /// The index is still being built from the stored entries.
+    IndexBuilding,
+    /// Changes after the given sequence number are no longer all known.
+    StaleCursor,
+    /// The entry's version did not satisfy the [`Precondition`](super::Precondition).
+    PreconditionFailed,
+    /// Other writes kept changing the entry while it was being modified.
+    Contended,
+    /// A transaction could not be finished, so writes are refused until it is.
+    Stalled,
+    /// The backend ran out of space, or the entry is larger than it can ever hold.
+    StorageFull,
+    /// Stored data could not be decoded.
+    Corrupt(String),
+    /// Reading or writing the underlying storage failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "entry not found"),
+            Self::RevisionNotFound => write!(f, "revision not found"),
+            Self::AlreadyExists => write!(f, "entry already exists"),
+            Self::IndexNotFound => write!(f, "index not found"),
+            Self::IndexExists => write!(f, "index already exists"),
+            Self::IndexBuilding => write!(f, "index is still being built"),
+            Self::StaleCursor => write!(f, "changes after the cursor are no longer known"),
+            Self::PreconditionFailed => write!(f, "entry version does not match the precondition"),
+            Self::Contended => write!(f, "entry kept changing while being modified"),
+            Self::Stalled => write!(f, "a transaction is half applied"),
+            Self::StorageFull => write!(f, "storage is full"),
+            Self::Corrupt(reason) => write!(f, "stored data is corrupt: {reason}"),
+            Self::Io(e) => write!(f, "storage i/o failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProviderError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => Self::StorageFull,
+            _ => Self::Io(e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ProviderError {
+    fn from(e: serde_json::Error) -> Self {
+        match e.io_error_kind() {
+            Some(_) => Self::from(io::Error::from(e)),
+            None => Self::Corrupt(e.to_string()),
+        }
+    }
+}
+
+/// Store routes answer with the usual `{ok, message, data}` body.
+///
+/// Server side failures are logged in full; clients only get a short description.
+impl ResponseError for ProviderError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound | Self::RevisionNotFound | Self::IndexNotFound => StatusCode::NOT_FOUND,
+            Self::AlreadyExists | Self::IndexExists => StatusCode::CONFLICT,
+            Self::IndexBuilding | Self::Contended | Self::Stalled => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            Self::StaleCursor => StatusCode::GONE,
+            Self::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Self::StorageFull => StatusCode::INSUFFICIENT_STORAGE,
+            Self::Corrupt(_) | Self::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.status_code().is_server_error() {
+            error!("provider error: {self}");
+        }
+
+        HttpResponse::build(self.status_code()).json(json!({
+            "ok": false,
+            "message": self.message(),
+            "data": {}
+        }))
+    }
+}
+
+impl ProviderError {
+    /// Short description of the failure shown to clients.
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::NotFound => "This entry does not exist",
+            Self::RevisionNotFound => "This revision of the entry is not kept",
+            Self::AlreadyExists => "This entry already exists",
+            Self::IndexNotFound => "This index does not exist",
+            Self::IndexExists => "This index already exists",
+            Self::IndexBuilding => "This index is still being built",
+            Self::StaleCursor => "This cursor is too old or unknown, sync again from 0",
+            Self::PreconditionFailed => "This entry does not match the given ETag",
+            Self::Contended => "This entry is changing too often, try again",
+            Self::Stalled => "A transaction could not be finished, writes resume after a restart",
+            Self::StorageFull => "Not enough storage to hold this entry",
+            Self::Corrupt(_) => "This entry is corrupt",
+            Self::Io(_) => "The storage backend failed",
+        }
+    }
+}
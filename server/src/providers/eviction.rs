@@ -0,0 +1,247 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, io,
+    str::FromStr,
+};
+
+use anyhow::{Error, bail};
+use chrono::TimeDelta;
+use serde::Serialize;
+
+/// Decides which entry is dropped first once a memory budget is exceeded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Least recently read or written.
+    #[default]
+    Lru,
+    /// Least frequently read or written, oldest access first on ties.
+    Lfu,
+    /// Earliest created, regardless of how it was used since.
+    Oldest,
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lru" => Ok(Self::Lru),
+            "lfu" => Ok(Self::Lfu),
+            "oldest" => Ok(Self::Oldest),
+            other => bail!("unknown eviction policy \"{other}\""),
+        }
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Lru => "lru",
+            Self::Lfu => "lfu",
+            Self::Oldest => "oldest",
+        })
+    }
+}
+
+/// Bounds enforced by a memory backed provider.
+///
+/// `None` leaves the corresponding dimension unbounded.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLimits {
+    pub max_entries: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub policy: EvictionPolicy,
+    /// Past revisions kept per entry, `0` keeps none. They count towards `max_bytes`, and
+    /// the oldest ones are dropped first when an entry would not fit otherwise.
+    pub history_depth: usize,
+    /// How long tombstones of removed entries are kept for syncing clients, `None` for good.
+    pub tombstone_retention: Option<TimeDelta>,
+}
+
+/// Returns the length of `value` once serialized as JSON, without allocating it.
+pub fn approximate_size<V: Serialize>(value: &V) -> usize {
+    struct Counter(usize);
+
+    impl io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.0
+}
+
+struct Slot {
+    rank: (u64, u64),
+    bytes: usize,
+}
+
+/// Orders keys by eviction priority and keeps the running byte total.
+///
+/// Each key is ranked by `(primary, tick)`, where `tick` is unique and increases on every
+/// touch. The primary component depends on the policy: the last touch for LRU, the hit count
+/// for LFU and the creation tick for oldest-first. The smallest rank is evicted first.
+pub struct EvictionIndex {
+    policy: EvictionPolicy,
+    tick: u64,
+    bytes: usize,
+    slots: HashMap<String, Slot>,
+    order: BTreeMap<(u64, u64), String>,
+}
+
+impl EvictionIndex {
+    pub fn new(policy: EvictionPolicy) -> Self {
+        Self {
+            policy,
+            tick: 0,
+            bytes: 0,
+            slots: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    /// Records a write of `bytes` to `key`, inserting it if unknown.
+    pub fn insert(&mut self, key: &str, bytes: usize) {
+        self.tick += 1;
+        let tick = self.tick;
+
+        let rank = match self.slots.get(key) {
+            Some(slot) => match self.policy {
+                EvictionPolicy::Lru => (tick, tick),
+                EvictionPolicy::Lfu => (slot.rank.0 + 1, tick),
+                EvictionPolicy::Oldest => slot.rank,
+            },
+            None => match self.policy {
+                EvictionPolicy::Lfu => (1, tick),
+                EvictionPolicy::Lru | EvictionPolicy::Oldest => (tick, tick),
+            },
+        };
+
+        if let Some(old) = self.slots.insert(key.to_owned(), Slot { rank, bytes }) {
+            self.order.remove(&old.rank);
+            self.bytes -= old.bytes;
+        }
+        self.order.insert(rank, key.to_owned());
+        self.bytes += bytes;
+    }
+
+    /// Records a read of `key`.
+    pub fn access(&mut self, key: &str) {
+        if self.policy == EvictionPolicy::Oldest {
+            return;
+        }
+
+        self.tick += 1;
+        let tick = self.tick;
+
+        if let Some(slot) = self.slots.get_mut(key) {
+            self.order.remove(&slot.rank);
+            slot.rank = match self.policy {
+                EvictionPolicy::Lfu => (slot.rank.0 + 1, tick),
+                _ => (tick, tick),
+            };
+            self.order.insert(slot.rank, key.to_owned());
+        }
+    }
+
+    /// Stops tracking `key`.
+    pub fn remove(&mut self, key: &str) {
+        if let Some(slot) = self.slots.remove(key) {
+            self.order.remove(&slot.rank);
+            self.bytes -= slot.bytes;
+        }
+    }
+
+    /// Removes and returns the key that should be evicted next, passing over the keys `spare`
+    /// holds on to.
+    pub fn pop_victim(&mut self, spare: impl Fn(&str) -> bool) -> Option<String> {
+        let rank = self
+            .order
+            .iter()
+            .find(|(_, key)| !spare(key))
+            .map(|(&rank, _)| rank)?;
+        let key = self.order.remove(&rank)?;
+        if let Some(slot) = self.slots.remove(&key) {
+            self.bytes -= slot.bytes;
+        }
+        Some(key)
+    }
+
+    /// Whether the tracked keys exceed `limits`.
+    pub fn exceeds(&self, limits: &MemoryLimits) -> bool {
+        limits.max_entries.is_some_and(|max| self.slots.len() > max)
+            || limits.max_bytes.is_some_and(|max| self.bytes > max)
+    }
+
+    pub fn policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lfu_evicts_least_used() {
+        let mut index = EvictionIndex::new(EvictionPolicy::Lfu);
+        index.insert("a", 1);
+        index.insert("b", 1);
+        index.access("a");
+        index.access("b");
+        index.access("a");
+
+        assert_eq!(index.pop_victim(|_| false).as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn oldest_ignores_access() {
+        let mut index = EvictionIndex::new(EvictionPolicy::Oldest);
+        index.insert("a", 1);
+        index.insert("b", 1);
+        index.access("a");
+        index.insert("a", 1);
+
+        assert_eq!(index.pop_victim(|_| false).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn spares_keys_being_written() {
+        let mut index = EvictionIndex::new(EvictionPolicy::Lfu);
+        index.insert("a", 1);
+        index.access("a");
+        index.insert("b", 1);
+
+        assert_eq!(index.pop_victim(|key| key == "b").as_deref(), Some("a"));
+        assert_eq!(index.pop_victim(|key| key == "b"), None);
+    }
+
+    #[test]
+    fn tracks_bytes() {
+        let mut index = EvictionIndex::new(EvictionPolicy::Lru);
+        index.insert("a", 10);
+        index.insert("b", 5);
+        index.insert("a", 3);
+        assert_eq!(index.bytes(), 8);
+
+        index.remove("b");
+        assert_eq!(index.bytes(), 3);
+
+        let limits = MemoryLimits {
+            max_bytes: Some(2),
+            ..Default::default()
+        };
+        assert!(index.exceeds(&limits));
+    }
+}
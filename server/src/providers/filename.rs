@@ -0,0 +1,182 @@
+use std::fmt::Write;
+
+use ciphers::sha256::SHA256;
+
+use crate::structs::encoding::hex;
+
+/// Longest encoded key stored under its own name. Past this, the name is hashed so that
+/// `<name>.meta` stays well within the usual 255 byte file name limit.
+const MAX_ENCODED_LEN: usize = 200;
+/// File name used for the empty key, which cannot be represented otherwise.
+const EMPTY_KEY: &str = "%";
+/// Prefix of hashed file names. Never produced by the plain encoding.
+pub const HASHED_PREFIX: char = '~';
+/// Prefix of shard directory names. Never produced by [`encode_key`], so a shard directory
+/// cannot clash with a file name.
+const SHARD_PREFIX: char = '@';
+
+/// Encodes `key` as a file name that is safe on any common filesystem.
+///
+/// Lowercase ASCII letters, digits, `_` and `-` are kept; every other byte becomes `%xx`.
+/// The result never contains `/`, `.` or uppercase letters, so it cannot escape the data
+/// directory, clash with a `.meta` suffix or collide on case-insensitive filesystems.
+///
+/// Keys whose encoding would be too long are stored as `~<sha256 of key>`; those names are
+/// not reversible on their own and callers must keep the original key elsewhere.
+pub fn encode_key(key: &str) -> String {
+    if key.is_empty() {
+        return EMPTY_KEY.to_owned();
+    }
+
+    let mut out = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        if is_plain(byte) {
+            out.push(byte as char);
+        } else {
+            let _ = write!(out, "%{byte:02x}");
+        }
+    }
+
+    if out.len() > MAX_ENCODED_LEN {
+        return hashed_name(key);
+    }
+
+    out
+}
+
+/// Reverses [`encode_key`].
+///
+/// Returns `None` for hashed names and for anything that is not a valid encoding.
+pub fn decode_key(name: &str) -> Option<String> {
+    if name == EMPTY_KEY {
+        return Some(String::new());
+    }
+
+    let bytes = name.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = name.get(i + 1..i + 3)?;
+                if !hex
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+                {
+                    return None;
+                }
+
+                out.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            byte if is_plain(byte) => {
+                out.push(byte);
+                i += 1;
+            }
+            _ => return None,
+        }
+    }
+
+    String::from_utf8(out).ok()
+}
+
+/// Whether `name` is a hashed file name produced by [`encode_key`].
+pub fn is_hashed(name: &str) -> bool {
+    name.starts_with(HASHED_PREFIX)
+}
+
+/// Directories, outermost first, that hold the file `name` in a layout `depth` levels deep.
+///
+/// Each level is one byte of the SHA-256 of `name` written as `@xx`, which spreads files
+/// evenly over at most 256 directories per level.
+pub fn shard_dirs(name: &str, depth: usize) -> Vec<String> {
+    if depth == 0 {
+        return Vec::new();
+    }
+
+    let mut hasher = SHA256::new_default();
+    hasher.update(name.as_bytes());
+
+    hasher
+        .get_hash()
+        .iter()
+        .take(depth)
+        .map(|byte| format!("{SHARD_PREFIX}{byte:02x}"))
+        .collect()
+}
+
+/// Whether `name` is a directory created by [`shard_dirs`].
+pub fn is_shard_dir(name: &str) -> bool {
+    name.strip_prefix(SHARD_PREFIX).is_some_and(|hex| {
+        hex.len() == 2
+            && hex
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    })
+}
+
+fn hashed_name(key: &str) -> String {
+    let mut hasher = SHA256::new_default();
+    hasher.update(key.as_bytes());
+
+    format!("{HASHED_PREFIX}{}", hex(&hasher.get_hash()))
+}
+
+fn is_plain(byte: u8) -> bool {
+    byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'_' || byte == b'-'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for key in [
+            "plain",
+            "projects:rust",
+            "../../etc/passwd",
+            "a.meta",
+            "Ünï",
+            "",
+        ] {
+            let name = encode_key(key);
+            assert!(!name.contains(['/', '.']));
+            assert_eq!(decode_key(&name).as_deref(), Some(key));
+        }
+    }
+
+    #[test]
+    fn distinguishes_case() {
+        assert_ne!(encode_key("Key"), encode_key("key"));
+    }
+
+    #[test]
+    fn hashes_long_keys() {
+        let key = "k".repeat(MAX_ENCODED_LEN + 1);
+        let name = encode_key(&key);
+
+        assert!(is_hashed(&name));
+        assert_eq!(decode_key(&name), None);
+    }
+
+    #[test]
+    fn shards_are_stable_and_recognized() {
+        let dirs = shard_dirs("plain", 2);
+
+        assert_eq!(dirs.len(), 2);
+        assert_eq!(dirs, shard_dirs("plain", 2));
+        assert!(dirs.iter().all(|dir| is_shard_dir(dir)));
+        assert!(shard_dirs("plain", 0).is_empty());
+        assert!(!is_shard_dir(&encode_key("@ab")));
+    }
+
+    #[test]
+    fn rejects_foreign_names() {
+        assert_eq!(decode_key("UPPER"), None);
+        assert_eq!(decode_key("a.meta"), None);
+        assert_eq!(decode_key("%zz"), None);
+        assert_eq!(decode_key("%4"), None);
+    }
+}
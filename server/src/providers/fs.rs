@@ -1,179 +1,2288 @@
 use std::{
+    collections::{HashMap, HashSet, hash_map},
+    io,
     marker::PhantomData,
     path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
 };
 
-use anyhow::Result;
-use chrono::Utc;
-use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use tokio::{fs, io::AsyncWriteExt};
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use chrono::{TimeDelta, Utc};
+use serde::{
+    Deserialize, Serialize,
+    de::{DeserializeOwned, IgnoredAny},
+};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::{MutexGuard, broadcast},
+};
+use tracing::{error, info, warn};
+
+use crate::structs::{entry::Entry, metadata::Metadata};
+
+use super::{
+    CacheProvider, KeyPage, Precondition, ProviderError, ProviderResult, WriteOptions,
+    batch::{BatchError, BatchOperation, BatchOutcome, BatchPlan},
+    changes::{Change, ChangeFeed, ChangeKind},
+    filename::{decode_key, encode_key, is_hashed, is_shard_dir, shard_dirs},
+    index::{IndexDefinition, IndexRange, Indexes},
+    locks::KeyLocks,
+    sync::{ChangePage, Changed, SyncChange, SyncLog, SyncState, Tombstone},
+};
+
+/// Scratch directory for files being written, renamed into place once complete.
+const TMP_DIR: &str = ".tmp";
+/// Where the startup recovery pass moves files it cannot make sense of.
+const QUARANTINE_DIR: &str = ".quarantine";
+/// Present once the directory uses encoded file names, see [`Layout`].
+const LAYOUT_FILE: &str = ".layout";
+/// Staging area used while migrating a directory to encoded file names.
+const MIGRATE_DIR: &str = ".migrate";
+/// Written into [`MIGRATE_DIR`] once every file has been staged.
+const MIGRATE_STAGED: &str = ".staged";
+/// Layout version with encoded file names and metadata kept apart in `<name>.meta`.
+const SPLIT_LAYOUT_VERSION: u32 = 1;
+/// Current on-disk layout version, stored in [`LAYOUT_FILE`].
+const LAYOUT_VERSION: u32 = 2;
+/// Definitions of the secondary indexes, see [`Indexes`].
+const INDEXES_FILE: &str = ".indexes";
+/// Journals of transactions being applied, see [`FileSystemProvider::transact`].
+const BATCH_DIR: &str = ".batches";
+/// Suffix of the file holding the past revisions of an entry.
+const HISTORY_SUFFIX: &str = ".history";
+/// Suffix of the file holding the [`Tombstone`] of a removed entry.
+const TOMBSTONE_SUFFIX: &str = ".tombstone";
+/// Horizon of the pruned tombstones, see [`SyncLog`].
+const SYNC_FILE: &str = ".sync";
+/// Deepest supported shard layout. Three levels already allow 16 million directories.
+pub const MAX_SHARD_DEPTH: usize = 3;
+
+/// Contents of [`LAYOUT_FILE`].
+///
+/// Older directories hold just the version number, which reads as an unsharded layout.
+#[derive(Debug, Serialize, Deserialize)]
+struct Layout {
+    version: u32,
+    #[serde(default)]
+    shard_depth: usize,
+    /// Set while [`FileSystemProvider::reshard`] moves files to a new depth.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resharding_to: Option<usize>,
+}
+
+/// An entry as written to disk, or the metadata of one in the split layout.
+///
+/// Hashed file names cannot be decoded, so their records also hold the original key.
+#[derive(Serialize, Deserialize)]
+struct Record<E> {
+    #[serde(flatten)]
+    entry: E,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+}
 
-use crate::structs::metadata::Metadata;
+/// How a transaction leaves one key: the entry (`None` once removed) and its past revisions.
+///
+/// Journaled before anything is written, so an interrupted transaction can be finished.
+#[derive(Serialize, Deserialize)]
+struct BatchCommit<V> {
+    key: String,
+    entry: Option<Entry<V>>,
+    history: Vec<Entry<V>>,
+    /// Left in place of the entry once removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tombstone: Option<Tombstone>,
+}
 
-use super::CacheProvider;
+/// A [`Record`] read without its value.
+#[derive(Deserialize)]
+struct RecordHeader {
+    metadata: Metadata,
+    #[serde(default)]
+    key: Option<String>,
+}
 
 /// A simple file‑based cache.
 ///
-/// * Each value is stored as `<key>` (JSON).
-/// * Its metadata lives next to it as `<key>.meta`.
-/// * All files reside in a single directory (`path`).
+/// * Each entry is stored as `<name>`, one JSON record holding both the value and its
+///   metadata, where `name` is the key encoded by [`encode_key`].
+/// * Revisions replaced by updates are kept in `<name>.history`, a JSON array ordered oldest
+///   first. It is written before the entry, so revisions in it that are not older than the
+///   entry are left over from an interrupted update and ignored.
+/// * Files reside in the cache directory (`path`), or with a shard depth of `n` in `n` levels
+///   of `@xx` directories picked by the hash of the name, see [`shard_dirs`]. Names starting
+///   with `.` belong to the provider itself.
+///
+/// * A removed entry leaves its [`Tombstone`] in `<name>.tombstone`, written before the entry
+///   is deleted and deleted once it is written again. Sequence numbers are recovered from the
+///   entries and tombstones on startup, and the horizon of pruned tombstones is kept in `.sync`.
+///
+/// * Secondary index definitions are kept in `.indexes`. Their contents live in memory and are
+///   built from the entries on startup.
+///
+/// * A transaction journals the entries and histories it leaves behind in `.batches/` before
+///   writing any of them, and deletes the journal once they are all in place. Journals found on
+///   startup are applied again. If writing them fails, they are written again right away; if
+///   that fails too, every write is refused until a restart finishes the transaction.
+///
+/// Files are never written in place: they are written to `.tmp/`, synced and renamed over the
+/// target, so readers see either the old or the new entry. Writes to a key hold its lock in
+/// `locks` from the moment the current version is checked until the record is in place and
+/// the indexes are updated and the change is published, and until then its sequence number in
+/// `sync` stays pending.
 pub struct FileSystemProvider<T: Clone> {
     path: PathBuf,
+    shard_depth: usize,
+    locks: KeyLocks,
+    tmp_counter: AtomicU64,
+    history_depth: usize,
+    indexes: Indexes,
+    changes: ChangeFeed<T>,
+    sync: SyncLog,
+    /// Set when a transaction is left half applied, see [`FileSystemProvider::transact`].
+    stalled: AtomicBool,
     _marker: PhantomData<T>, // uh.
 }
 
 impl<T: Clone> FileSystemProvider<T> {
     /// Create the cache directory if it does not exist and return a provider.
+    ///
+    /// An existing directory keeps the shard depth it was written with; new ones are flat.
+    /// Directories written before keys were encoded, or with metadata in separate files, are
+    /// migrated in place, then a recovery pass runs over the files, see
+    /// [`FileSystemProvider::recover`].
     pub async fn new(path: PathBuf) -> Result<Self> {
-        if !path.exists() {
-            fs::create_dir_all(&path).await?;
+        Self::open(path, None).await
+    }
+
+    /// Like [`FileSystemProvider::new`], but re-shards the directory to `shard_depth` levels
+    /// first if it was written with a different depth.
+    pub async fn with_shard_depth(path: PathBuf, shard_depth: usize) -> Result<Self> {
+        Self::open(path, Some(shard_depth)).await
+    }
+
+    /// Keeps the last `depth` revisions of every entry. Without it, no history is kept.
+    pub fn with_history_depth(mut self, depth: usize) -> Self {
+        self.history_depth = depth;
+        self
+    }
+
+    /// Prunes tombstones once they are older than `retention`. Without one they are kept.
+    pub fn with_tombstone_retention(mut self, retention: Option<TimeDelta>) -> Self {
+        self.sync = std::mem::take(&mut self.sync).with_retention(retention);
+        self
+    }
+
+    async fn open(path: PathBuf, shard_depth: Option<usize>) -> Result<Self> {
+        if let Some(depth) = shard_depth
+            && depth > MAX_SHARD_DEPTH
+        {
+            bail!("shard depth {depth} is deeper than the supported {MAX_SHARD_DEPTH}");
         }
 
-        Ok(Self {
+        fs::create_dir_all(path.join(TMP_DIR)).await?;
+
+        let mut provider = Self {
             path,
+            shard_depth: 0,
+            locks: KeyLocks::new(),
+            tmp_counter: AtomicU64::new(0),
+            history_depth: 0,
+            indexes: Indexes::default(),
+            changes: ChangeFeed::new(),
+            sync: SyncLog::default(),
+            stalled: AtomicBool::new(false),
             _marker: PhantomData,
+        };
+        provider.migrate().await?;
+
+        let layout = provider.read_layout().await?;
+        provider.shard_depth = layout.shard_depth;
+        if layout.version < LAYOUT_VERSION {
+            provider.merge_entries(&layout).await?;
+        }
+
+        // also picks up a re-shard that was interrupted, finishing it at the requested depth
+        let depth = shard_depth
+            .or(layout.resharding_to)
+            .unwrap_or(layout.shard_depth);
+        if depth != layout.shard_depth || layout.resharding_to.is_some() {
+            provider.reshard(depth).await?;
+        }
+
+        provider.finish_batches().await?;
+        provider.recover().await?;
+        provider.load_sync().await?;
+
+        provider.indexes = Indexes::open(Some(provider.path.join(INDEXES_FILE)))?;
+        for definition in provider.indexes.definitions() {
+            provider.build_index(&definition).await?;
+        }
+
+        Ok(provider)
+    }
+
+    /// Renames files stored under their raw key to the encoded layout.
+    ///
+    /// Files are first moved into `.migrate/` under their new names, then moved back once all
+    /// of them are staged. The `.staged` marker tells a restarted migration which of the two
+    /// steps was interrupted, so no name is ever encoded twice.
+    pub async fn migrate(&self) -> Result<()> {
+        let layout = self.path.join(LAYOUT_FILE);
+        if layout.exists() {
+            return Ok(());
+        }
+
+        let staging = self.path.join(MIGRATE_DIR);
+        let staged = staging.join(MIGRATE_STAGED);
+        fs::create_dir_all(&staging).await?;
+
+        let mut moved = 0;
+        if !staged.exists() {
+            let mut names = HashSet::new();
+            let mut entries = fs::read_dir(&self.path).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if let Some(name) = entry.file_name().to_str()
+                    && !name.starts_with('.')
+                    && !entry.file_type().await?.is_dir()
+                {
+                    names.insert(name.to_owned());
+                }
+            }
+
+            for name in &names {
+                // a legacy `<key>.meta` belongs to `<key>` if that file exists (or was already
+                // staged by an interrupted run), otherwise it is a value whose key happens to
+                // end in `.meta`
+                let target = match name.strip_suffix(".meta") {
+                    Some(key) if names.contains(key) || staging.join(encode_key(key)).exists() => {
+                        format!("{}.meta", encode_key(key))
+                    }
+                    _ => encode_key(name),
+                };
+
+                fs::rename(self.path.join(name), staging.join(target)).await?;
+                moved += 1;
+            }
+
+            fs::write(&staged, b"").await?;
+            Self::sync_dir(&self.path).await?;
+        }
+
+        let mut entries = fs::read_dir(&staging).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            if name != MIGRATE_STAGED {
+                fs::rename(entry.path(), self.path.join(name)).await?;
+            }
+        }
+
+        fs::remove_dir_all(&staging).await?;
+        self.write_layout(&Layout {
+            version: SPLIT_LAYOUT_VERSION,
+            shard_depth: 0,
+            resharding_to: None,
         })
+        .await?;
+
+        if moved > 0 {
+            info!(
+                "migrated {moved} files in {} to encoded names",
+                self.path.display()
+            );
+        }
+
+        Ok(())
     }
 
-    fn value_path(&self, key: &str) -> PathBuf {
-        self.path.join(key)
+    /// Folds the `<name>.meta` files of the split layout into their entries.
+    ///
+    /// Each entry is replaced by a record holding value and metadata before its `.meta` is
+    /// deleted. A restarted run knows an entry was already folded when it is a record with the
+    /// creation time and version of the `.meta` next to it. Pairs that cannot be read are left for
+    /// [`FileSystemProvider::recover`].
+    async fn merge_entries(&self, layout: &Layout) -> Result<()> {
+        let (files, _) = self.walk().await?;
+        let mut touched = HashSet::new();
+
+        for meta_path in files {
+            let Some(name) = Self::entry_name(&meta_path).and_then(|n| n.strip_suffix(".meta"))
+            else {
+                continue;
+            };
+            let path = meta_path.with_file_name(name);
+
+            let Ok(Some(meta)) = self.read_json::<_, Record<Metadata>>(&meta_path).await else {
+                continue;
+            };
+            let Ok(Some(value)) = self.read_json::<_, serde_json::Value>(&path).await else {
+                continue;
+            };
+
+            let folded =
+                Record::<Entry<serde_json::Value>>::deserialize(&value).is_ok_and(|record| {
+                    let folded = &record.entry.metadata;
+                    (folded.created_at, folded.version)
+                        == (meta.entry.created_at, meta.entry.version)
+                });
+            if !folded {
+                let mut metadata = meta.entry;
+                metadata.describe(&value)?;
+                let record = Record {
+                    entry: Entry { value, metadata },
+                    key: meta.key,
+                };
+                self.write_json(&path, &record).await?;
+            }
+
+            Self::remove_file(&meta_path).await?;
+            touched.extend(meta_path.parent().map(Path::to_path_buf));
+        }
+
+        for dir in &touched {
+            Self::sync_dir(dir).await?;
+        }
+
+        self.write_layout(&Layout {
+            version: LAYOUT_VERSION,
+            shard_depth: layout.shard_depth,
+            resharding_to: layout.resharding_to,
+        })
+        .await?;
+
+        if !touched.is_empty() {
+            info!(
+                "merged metadata files in {} into their entries",
+                self.path.display()
+            );
+        }
+
+        Ok(())
     }
 
-    fn meta_path(&self, key: &str) -> PathBuf {
-        self.path.join(format!("{key}.meta"))
+    /// Moves every file to where a layout `depth` levels deep expects it.
+    ///
+    /// The target depth is recorded in `.layout` before anything moves, so the next start
+    /// finishes an interrupted run. Each file moves with a single rename and is always found
+    /// in either its old or its new place. Shard directories left empty are removed.
+    pub async fn reshard(&mut self, depth: usize) -> Result<()> {
+        if depth > MAX_SHARD_DEPTH {
+            bail!("shard depth {depth} is deeper than the supported {MAX_SHARD_DEPTH}");
+        }
+
+        self.write_layout(&Layout {
+            version: LAYOUT_VERSION,
+            shard_depth: self.shard_depth,
+            resharding_to: Some(depth),
+        })
+        .await?;
+
+        let (files, dirs) = self.walk().await?;
+        let mut touched = HashSet::new();
+        let mut moved = 0;
+
+        for path in files {
+            let Some(name) = Self::entry_name(&path) else {
+                continue;
+            };
+
+            let target = Self::sharded_path(&self.path, name, depth);
+            if target != path {
+                Self::move_file(&path, &target).await?;
+                touched.extend(path.parent().map(Path::to_path_buf));
+                touched.extend(target.parent().map(Path::to_path_buf));
+                moved += 1;
+            }
+        }
+
+        // deeper directories were found later, so removing in reverse empties children first
+        for dir in dirs.iter().rev() {
+            if fs::remove_dir(dir).await.is_ok() {
+                touched.remove(dir);
+                touched.extend(dir.parent().map(Path::to_path_buf));
+            }
+        }
+
+        for dir in &touched {
+            Self::sync_dir(dir).await?;
+        }
+
+        info!(
+            "resharded {} from depth {} to {depth}, moved {moved} files",
+            self.path.display(),
+            self.shard_depth
+        );
+
+        self.shard_depth = depth;
+        self.write_layout(&Layout {
+            version: LAYOUT_VERSION,
+            shard_depth: depth,
+            resharding_to: None,
+        })
+        .await
     }
 
-    async fn write_json<P: AsRef<Path>, V: Serialize>(&self, path: P, value: &V) -> Result<()> {
-        let json = serde_json::to_string(value)?;
-        let mut f = fs::File::create(path).await?;
-        f.write_all(json.as_bytes()).await?;
+    /// Repairs what an interrupted write may have left behind.
+    ///
+    /// * Leftover temporary files are deleted.
+    /// * Files outside the shard directory they belong in are moved there.
+    /// * A `.meta` file of the split layout is deleted if its entry is gone. Otherwise the pair
+    ///   could not be merged, and both are moved to `.quarantine/`.
+    /// * An entry that is not a valid record is moved to `.quarantine/`.
+    /// * A `.history` file whose entry is gone is deleted, and one that cannot be read is moved
+    ///   to `.quarantine/`.
+    /// * A `.tombstone` file next to its entry was left by an interrupted write or removal. The
+    ///   newer of the two wins, the tombstone is deleted if the entry was written after it and
+    ///   the entry and its history otherwise. One that cannot be read is moved to `.quarantine/`.
+    pub async fn recover(&self) -> Result<()> {
+        let tmp_dir = self.path.join(TMP_DIR);
+        if tmp_dir.exists() {
+            fs::remove_dir_all(&tmp_dir).await?;
+        }
+        fs::create_dir_all(&tmp_dir).await?;
+
+        let (mut repaired, mut quarantined) = (0, 0);
+        let (files, _) = self.walk().await?;
+
+        let (mut entries, mut metas, mut histories, mut tombstones) =
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for path in files {
+            let Some(filename) = Self::entry_name(&path) else {
+                continue;
+            };
+
+            let expected = self.file_path(filename);
+            if expected != path {
+                Self::move_file(&path, &expected).await?;
+                repaired += 1;
+            }
+
+            if filename.ends_with(".meta") {
+                metas.push(expected);
+            } else if filename.ends_with(HISTORY_SUFFIX) {
+                histories.push(expected);
+            } else if filename.ends_with(TOMBSTONE_SUFFIX) {
+                tombstones.push(expected);
+            } else {
+                entries.push(expected);
+            }
+        }
+
+        // leftover metadata goes first, so entries quarantined along with it are skipped below
+        for meta_path in &metas {
+            let path = meta_path.with_extension("");
+            if path.exists() {
+                self.quarantine(&path).await?;
+                self.quarantine(meta_path).await?;
+                quarantined += 1;
+            } else {
+                fs::remove_file(meta_path).await?;
+                repaired += 1;
+            }
+        }
+
+        for path in &entries {
+            if path.exists() && !Self::is_valid_json::<Record<Entry<serde_json::Value>>>(path).await
+            {
+                self.quarantine(path).await?;
+                quarantined += 1;
+            }
+        }
+
+        // after the entries, so only readable ones are weighed against their tombstone
+        for tombstone_path in &tombstones {
+            let Ok(Some(tombstone)) = self.read_json::<_, Tombstone>(tombstone_path).await else {
+                self.quarantine(tombstone_path).await?;
+                quarantined += 1;
+                continue;
+            };
+
+            let path = tombstone_path.with_extension("");
+            let Some(header) = self.read_json::<_, RecordHeader>(&path).await? else {
+                continue;
+            };
+            if header.metadata.sequence > tombstone.sequence {
+                fs::remove_file(tombstone_path).await?;
+            } else {
+                fs::remove_file(&path).await?;
+            }
+            repaired += 1;
+        }
+
+        // after the entries, so the history of a quarantined or removed entry counts as orphaned
+        for history_path in &histories {
+            if !history_path.with_extension("").exists() {
+                fs::remove_file(history_path).await?;
+                repaired += 1;
+            } else if !Self::is_valid_json::<Vec<Entry<serde_json::Value>>>(history_path).await {
+                self.quarantine(history_path).await?;
+                quarantined += 1;
+            }
+        }
+
+        if repaired > 0 || quarantined > 0 {
+            warn!(
+                "recovered {}: repaired {repaired} misplaced or orphaned files, quarantined {quarantined} entries",
+                self.path.display()
+            );
+        } else {
+            info!("{} is consistent", self.path.display());
+        }
+
         Ok(())
     }
 
-    async fn read_json<P, V>(&self, path: P) -> Option<V>
-    where
-        P: AsRef<Path>,
-        V: DeserializeOwned,
-    {
-        let data = fs::read_to_string(path).await.ok()?;
-        serde_json::from_str(&data).ok()
+    /// Restores the sequence numbers and tombstones left by the last run.
+    ///
+    /// Every number handed out is still held by an entry or a tombstone, or at most the saved
+    /// horizon. Tombstones at or below the horizon were pruned but not yet deleted. Entries
+    /// written before writes were numbered are numbered now.
+    async fn load_sync(&mut self) -> Result<()> {
+        let state: SyncState = self
+            .read_json(self.path.join(SYNC_FILE))
+            .await?
+            .unwrap_or_default();
+        let horizon = state.horizon;
+        self.sync = SyncLog::restore(state);
+
+        let (files, _) = self.walk().await?;
+        let mut unnumbered = Vec::new();
+        for path in files {
+            let Some(name) = Self::entry_name(&path) else {
+                continue;
+            };
+
+            if name.ends_with(TOMBSTONE_SUFFIX) {
+                let Some(tombstone) = self.read_json::<_, Tombstone>(&path).await? else {
+                    continue;
+                };
+                match tombstone.sequence <= horizon {
+                    true => fs::remove_file(&path).await?,
+                    false => self.sync.bury(tombstone),
+                }
+            } else if let Some(name) = Self::entry_file(&path)
+                && let Some(header) = self.read_json::<_, RecordHeader>(&path).await?
+            {
+                let key = self.key_for(name).await?;
+                match (header.metadata.sequence, key) {
+                    (0, key) => unnumbered.push((key, path)),
+                    (sequence, Some(key)) => self.sync.written(&key, sequence),
+                    (sequence, None) => self.sync.observe(sequence),
+                }
+            }
+        }
+
+        for (key, path) in &unnumbered {
+            if let Some(mut record) = self
+                .read_json::<_, Record<Entry<serde_json::Value>>>(path)
+                .await?
+            {
+                let sequence = self.sync.next().sequence();
+                record.entry.metadata.sequence = sequence;
+                self.write_json(path, &record).await?;
+                if let Some(key) = key {
+                    self.sync.written(key, sequence);
+                }
+            }
+        }
+        if !unnumbered.is_empty() {
+            info!(
+                "numbered {} entries in {} written before writes were numbered",
+                unnumbered.len(),
+                self.path.display()
+            );
+        }
+
+        Ok(())
     }
-}
 
-impl<T: Clone + Serialize + for<'a> Deserialize<'a>> CacheProvider<T> for FileSystemProvider<T> {
-    async fn entry(&self, key: String) -> Option<T> {
-        self.read_json(self.value_path(&key)).await
+    /// Applies the journals of transactions a crash interrupted, then deletes them.
+    ///
+    /// Journals hold the final state of every key, so applying one again is harmless. Keys of
+    /// a journal were locked until it was deleted, so no later write needs to be preserved.
+    async fn finish_batches(&self) -> Result<()> {
+        let dir = self.path.join(BATCH_DIR);
+        let mut journals = match fs::read_dir(&dir).await {
+            Ok(journals) => journals,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut finished = 0;
+        while let Some(journal) = journals.next_entry().await? {
+            let path = journal.path();
+            let commits = match self
+                .read_json::<_, Vec<BatchCommit<serde_json::Value>>>(&path)
+                .await
+            {
+                Ok(Some(commits)) => commits,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("cannot finish transaction {}: {e}", path.display());
+                    self.quarantine(&path).await?;
+                    continue;
+                }
+            };
+
+            for commit in &commits {
+                self.apply_commit(commit).await?;
+            }
+            fs::remove_file(&path).await?;
+            finished += 1;
+        }
+
+        if finished > 0 {
+            Self::sync_dir(&dir).await?;
+            warn!(
+                "finished {finished} interrupted transactions in {}",
+                self.path.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Moves `path` into the quarantine directory, keeping its name.
+    async fn quarantine(&self, path: &Path) -> Result<()> {
+        let dir = self.path.join(QUARANTINE_DIR);
+        fs::create_dir_all(&dir).await?;
+
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("entry");
+        let target = dir.join(format!("{name}.{}", Utc::now().timestamp_millis()));
+        fs::rename(path, &target).await?;
+
+        warn!("quarantined {} as {}", path.display(), target.display());
+        Ok(())
     }
 
-    async fn add(&self, key: String, value: T, issuer: String) -> Option<T> {
-        let value_path = self.value_path(&key);
-        if value_path.exists() {
-            return None;
+    async fn is_valid_json<V: DeserializeOwned>(path: &Path) -> bool {
+        match fs::read(path).await {
+            Ok(data) => serde_json::from_slice::<V>(&data).is_ok(),
+            Err(_) => false,
         }
+    }
 
-        if self.write_json(&value_path, &value).await.is_err() {
-            return None;
+    async fn read_layout(&self) -> Result<Layout> {
+        let data = fs::read_to_string(self.path.join(LAYOUT_FILE)).await?;
+
+        if let Ok(version) = data.trim().parse::<u32>() {
+            return Ok(Layout {
+                version,
+                shard_depth: 0,
+                resharding_to: None,
+            });
         }
 
-        let metadata = Metadata {
-            created_at: Utc::now().to_rfc3339(),
-            version: 0,
-            issuer,
-        };
-        let _ = self.write_json(self.meta_path(&key), &metadata).await;
+        Ok(serde_json::from_str(&data)?)
+    }
 
-        Some(value)
+    async fn write_layout(&self, layout: &Layout) -> Result<()> {
+        Ok(self.write_json(self.path.join(LAYOUT_FILE), layout).await?)
     }
 
-    async fn update(&self, key: String, value: T, issuer: String) -> Option<T> {
-        let value_path = self.value_path(&key);
-        if !value_path.exists() {
-            return None;
+    /// Every file in the cache directory and its shard directories, along with those
+    /// directories. Entries belonging to the provider itself are skipped.
+    ///
+    /// Shard directories are searched at any depth, so files left behind by an interrupted
+    /// re-shard are found too.
+    async fn walk(&self) -> io::Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+        let (mut files, mut dirs) = (Vec::new(), Vec::new());
+        let mut pending = vec![self.path.clone()];
+
+        while let Some(dir) = pending.pop() {
+            let mut entries = fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let Some(name) = Self::entry_name(&path) else {
+                    continue;
+                };
+
+                if !entry.file_type().await?.is_dir() {
+                    files.push(path);
+                } else if is_shard_dir(name) {
+                    dirs.push(path.clone());
+                    pending.push(path);
+                }
+            }
         }
 
-        if self.write_json(&value_path, &value).await.is_err() {
-            return None;
+        Ok((files, dirs))
+    }
+
+    /// Renames `from` to `to`, creating the shard directories `to` lives in.
+    async fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).await?;
         }
+        fs::rename(from, to).await
+    }
 
-        let meta_path = self.meta_path(&key);
-        let mut meta = self
-            .read_json::<_, Metadata>(&meta_path)
-            .await
-            .unwrap_or(Metadata {
-                created_at: String::new(),
-                version: 0,
-                issuer: issuer.clone(),
-            });
+    /// Where the file `name` lives under `root` in a layout `depth` levels deep.
+    ///
+    /// `.history` files, and `.meta` files left from the split layout, are sharded by the name
+    /// of their entry, so they always share its directory.
+    fn sharded_path(root: &Path, name: &str, depth: usize) -> PathBuf {
+        let entry_name = name.split_once('.').map_or(name, |(entry, _)| entry);
 
-        meta.version += 1;
-        meta.created_at = Utc::now().to_rfc3339();
-        meta.issuer = issuer;
+        let mut path = root.to_path_buf();
+        path.extend(shard_dirs(entry_name, depth));
+        path.join(name)
+    }
 
-        let _ = self.write_json(&meta_path, &meta).await;
-        Some(value)
+    fn file_path(&self, name: &str) -> PathBuf {
+        Self::sharded_path(&self.path, name, self.shard_depth)
     }
 
-    async fn metadata(&self, key: String) -> Option<Metadata> {
-        self.read_json(self.meta_path(&key)).await
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.file_path(&encode_key(key))
     }
 
-    async fn remove(&self, key: String) -> Option<T> {
-        let value_path = self.value_path(&key);
-        let meta_path = self.meta_path(&key);
+    fn history_path(&self, key: &str) -> PathBuf {
+        Self::history_path_of(&self.entry_path(key))
+    }
 
-        let existing = self.read_json::<_, T>(&value_path).await;
-        let _ = fs::remove_file(value_path).await;
-        let _ = fs::remove_file(meta_path).await;
+    fn history_path_of(entry_path: &Path) -> PathBuf {
+        let mut name = entry_path.file_name().unwrap_or_default().to_owned();
+        name.push(HISTORY_SUFFIX);
+        entry_path.with_file_name(name)
+    }
 
-        existing
+    fn tombstone_path(&self, key: &str) -> PathBuf {
+        Self::tombstone_path_of(&self.entry_path(key))
     }
 
-    async fn list(&self) -> Vec<(String, T)> {
-        let mut out = Vec::new();
-        let mut entries = match fs::read_dir(&self.path).await {
-            Ok(e) => e,
-            Err(_) => return out,
-        };
+    fn tombstone_path_of(entry_path: &Path) -> PathBuf {
+        let mut name = entry_path.file_name().unwrap_or_default().to_owned();
+        name.push(TOMBSTONE_SUFFIX);
+        entry_path.with_file_name(name)
+    }
 
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            let path = entry.path();
-            if path
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .is_some_and(|e| e == "meta")
-            {
+    /// File name of `path`, unless it belongs to the provider itself.
+    fn entry_name(path: &Path) -> Option<&str> {
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .filter(|name| !name.starts_with('.'))
+    }
+
+    /// File name of `path` if it holds an entry. Encoded keys never contain `.`, so anything
+    /// with a suffix is history or left from an older layout.
+    fn entry_file(path: &Path) -> Option<&str> {
+        Self::entry_name(path).filter(|name| !name.contains('.'))
+    }
+
+    /// Recovers the key stored under the file name `name`, if it is one this provider wrote.
+    async fn key_for(&self, name: &str) -> ProviderResult<Option<String>> {
+        if !is_hashed(name) {
+            return Ok(decode_key(name));
+        }
+
+        Ok(self
+            .read_json::<_, RecordHeader>(self.file_path(name))
+            .await?
+            .and_then(|header| header.key))
+    }
+
+    /// Takes the lock of the key stored under the file name `name`, if it can be recovered.
+    async fn lock_file(&self, name: &str) -> ProviderResult<Option<(String, MutexGuard<'_, ()>)>> {
+        Ok(match self.key_for(name).await? {
+            Some(key) => {
+                let guard = self.locks.lock(&key).await;
+                Some((key, guard))
+            }
+            None => None,
+        })
+    }
+
+    /// Fills the index `definition` describes from the entries stored under its prefix.
+    ///
+    /// Entries are read without taking their locks; writes made meanwhile update the index
+    /// themselves, and [`Indexes::fill`] keeps those.
+    async fn build_index(&self, definition: &IndexDefinition) -> ProviderResult<()> {
+        let (files, _) = self.walk().await?;
+
+        let mut entries = Vec::new();
+        for path in files {
+            let Some(name) = Self::entry_file(&path) else {
+                continue;
+            };
+            let Some(key) = self.key_for(name).await? else {
+                continue;
+            };
+            if !key.starts_with(&definition.prefix) {
                 continue;
             }
 
-            if let (Some(filename), Some(value)) = (
-                path.file_name().and_then(|n| n.to_str()).map(str::to_owned),
-                self.read_json::<_, T>(&path).await,
-            ) {
-                out.push((filename, value));
+            // a record damaged since startup is left for reads to report
+            match self
+                .read_json::<_, Record<Entry<serde_json::Value>>>(&path)
+                .await
+            {
+                Ok(Some(record)) => entries.push((key, record.entry.value)),
+                Ok(None) => {}
+                Err(ProviderError::Corrupt(reason)) => {
+                    warn!("not indexing {key}: {reason}")
+                }
+                Err(e) => return Err(e),
             }
         }
-        out
+
+        self.indexes.fill(&definition.name, entries);
+        Ok(())
     }
 
-    async fn purge(&self, issuer: String) {
-        if let Ok(mut entries) = fs::read_dir(&self.path).await {
-            while let Ok(Some(entry)) = entries.next_entry().await {
-                let path = entry.path();
+    /// Writes the record of `key`, including the key itself when its file name is hashed.
+    async fn write_entry<V: Serialize>(&self, key: &str, entry: &Entry<V>) -> ProviderResult<()> {
+        let record = Record {
+            entry,
+            key: is_hashed(&encode_key(key)).then(|| key.to_owned()),
+        };
 
-                if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                    if ext != "meta" {
-                        continue;
-                    }
+        self.write_json(self.entry_path(key), &record).await
+    }
+
+    /// Reads the record of `key`, whether or not it has expired.
+    async fn read_entry(&self, key: &str) -> ProviderResult<Option<Entry<T>>>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(self
+            .read_json::<_, Record<Entry<T>>>(self.entry_path(key))
+            .await?
+            .map(|record| record.entry))
+    }
 
-                    if let Some(meta) = self.read_json::<_, Metadata>(&path).await
-                        && meta.issuer == issuer
-                            && let Some(filename) = path.file_stem().and_then(|n| n.to_str()) {
-                                let value_path = self.value_path(filename);
-                                let _ = fs::remove_file(&value_path).await;
-                                let _ = fs::remove_file(&path).await;
-                            }
+    /// Past revisions of `key` older than `version`, oldest first.
+    async fn read_history<V: DeserializeOwned>(
+        &self,
+        key: &str,
+        version: u64,
+    ) -> ProviderResult<Vec<Entry<V>>> {
+        let mut history: Vec<Entry<V>> = self
+            .read_json(self.history_path(key))
+            .await?
+            .unwrap_or_default();
+        history.retain(|entry| entry.metadata.version < version);
+
+        Ok(history)
+    }
+
+    /// Appends `previous` to the history of `key`, dropping the oldest revisions past the
+    /// history depth. Must be called before the entry replacing `previous` is written.
+    async fn push_history(&self, key: &str, previous: Entry<T>) -> ProviderResult<()>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let path = self.history_path(key);
+        if self.history_depth == 0 {
+            return Self::remove_file(&path).await;
+        }
+
+        let mut history = self.read_history(key, previous.metadata.version).await?;
+        history.push(previous);
+        let excess = history.len().saturating_sub(self.history_depth);
+        history.drain(..excess);
+
+        self.write_json(&path, &history).await
+    }
+
+    /// Puts the entry and history of `commit.key` in place, history first like updates do. A
+    /// removed entry leaves its tombstone first like removals do.
+    async fn apply_commit<V: Serialize>(&self, commit: &BatchCommit<V>) -> ProviderResult<()> {
+        let path = self.entry_path(&commit.key);
+        let history_path = Self::history_path_of(&path);
+
+        match &commit.entry {
+            Some(entry) => {
+                match commit.history.is_empty() {
+                    true => Self::remove_file(&history_path).await?,
+                    false => self.write_json(&history_path, &commit.history).await?,
+                }
+                self.write_entry(&commit.key, entry).await?;
+                self.indexes.insert(&commit.key, &entry.value);
+                Self::remove_file(&Self::tombstone_path_of(&path)).await?;
+            }
+            None => {
+                if let Some(tombstone) = &commit.tombstone {
+                    self.write_json(Self::tombstone_path_of(&path), tombstone)
+                        .await?;
                 }
+                self.delete_entry(&commit.key).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails with [`ProviderError::Stalled`] while a transaction is half applied. Writers call
+    /// it once they hold their locks, so none of them lands after a transaction stalled.
+    fn check_writable(&self) -> ProviderResult<()> {
+        match self.stalled.load(Ordering::Acquire) {
+            true => Err(ProviderError::Stalled),
+            false => Ok(()),
+        }
+    }
+
+    /// Puts every commit of a transaction in place.
+    async fn apply_commits<V: Serialize>(&self, commits: &[BatchCommit<V>]) -> ProviderResult<()> {
+        for commit in commits {
+            self.apply_commit(commit).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the entry of `key` and its history. The entry goes first, so an interrupted
+    /// removal leaves only an orphaned history.
+    async fn delete_entry(&self, key: &str) -> ProviderResult<()> {
+        let path = self.entry_path(key);
+        Self::remove_file(&path).await?;
+        self.indexes.remove(key);
+        Self::remove_file(&Self::history_path_of(&path)).await?;
+        if let Some(dir) = path.parent() {
+            Self::sync_dir(dir).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the entry of `key` because of `kind`, leaving a tombstone at the next sequence
+    /// number. The tombstone is written before the entry goes, see
+    /// [`FileSystemProvider::recover`]. The caller holds the lock of `key`.
+    async fn bury(&self, key: &str, kind: ChangeKind) -> ProviderResult<()> {
+        let ticket = self.sync.next();
+        let tombstone = Tombstone::new(key, ticket.sequence(), kind);
+        self.write_json(self.tombstone_path(key), &tombstone)
+            .await?;
+        self.delete_entry(key).await?;
+
+        self.changes.removed(kind, key, tombstone.sequence);
+        self.sync.bury(tombstone);
+
+        Ok(())
+    }
+
+    /// Prunes the tombstones older than the retention. The raised horizon is saved before
+    /// their files are deleted, so the next start deletes whatever is left of them.
+    async fn prune_tombstones(&self) -> ProviderResult<()> {
+        let pruned = self.sync.prune();
+        if pruned.is_empty() {
+            return Ok(());
+        }
+
+        let state = SyncState {
+            horizon: self.sync.horizon(),
+            tombstones: Vec::new(),
+        };
+        self.write_json(self.path.join(SYNC_FILE), &state).await?;
+
+        for tombstone in pruned {
+            let _guard = self.locks.lock(&tombstone.key).await;
+            // the key may have been removed again meanwhile, leaving a newer tombstone
+            if self.sync.tombstone(&tombstone.key).is_none() {
+                Self::remove_file(&self.tombstone_path(&tombstone.key)).await?;
             }
         }
+
+        Ok(())
+    }
+
+    /// Metadata of `key` if it is stored and has not expired.
+    async fn live_metadata(&self, key: &str) -> ProviderResult<Option<Metadata>> {
+        Ok(self
+            .read_json::<_, RecordHeader>(self.entry_path(key))
+            .await?
+            .map(|header| header.metadata)
+            .filter(|meta| !meta.is_expired()))
+    }
+
+    /// Atomically replaces the file at `path` with `value` serialized as JSON.
+    async fn write_json<P, V>(&self, path: P, value: &V) -> ProviderResult<()>
+    where
+        P: AsRef<Path>,
+        V: Serialize,
+    {
+        let path = path.as_ref();
+        let json = serde_json::to_vec(value)?;
+
+        let tmp_path = self.path.join(TMP_DIR).join(format!(
+            "{}.{}",
+            self.tmp_counter.fetch_add(1, Ordering::Relaxed),
+            std::process::id()
+        ));
+
+        let mut f = fs::File::create(&tmp_path).await?;
+        f.write_all(&json).await?;
+        f.sync_all().await?;
+        drop(f);
+
+        if let Err(e) = Self::move_file(&tmp_path, path).await {
+            let _ = fs::remove_file(&tmp_path).await;
+            return Err(e.into());
+        }
+
+        Ok(Self::sync_dir(path.parent().unwrap_or(&self.path)).await?)
+    }
+
+    /// Makes renames and removals in `dir` durable.
+    async fn sync_dir(dir: &Path) -> io::Result<()> {
+        #[cfg(unix)]
+        fs::File::open(dir).await?.sync_all().await?;
+
+        Ok(())
+    }
+
+    /// Reads the JSON file at `path`, or `None` if there is no such file.
+    async fn read_json<P, V>(&self, path: P) -> ProviderResult<Option<V>>
+    where
+        P: AsRef<Path>,
+        V: DeserializeOwned,
+    {
+        let path = path.as_ref();
+        let data = match fs::read_to_string(path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        serde_json::from_str(&data).map(Some).map_err(|e| {
+            ProviderError::Corrupt(format!("{} holds invalid JSON: {e}", path.display()))
+        })
+    }
+
+    /// Deletes the file at `path`, if there is one.
+    async fn remove_file(path: &Path) -> ProviderResult<()> {
+        match fs::remove_file(path).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<T> CacheProvider<T> for FileSystemProvider<T>
+where
+    T: Clone + Send + Sync + Serialize + for<'a> Deserialize<'a> + 'static,
+{
+    /// Value and metadata share one file that is only ever replaced by a rename, so no lock is
+    /// needed to read them consistently.
+    async fn entry(&self, key: String) -> ProviderResult<Entry<T>> {
+        self.read_entry(&key)
+            .await?
+            .filter(|entry| !entry.metadata.is_expired())
+            .ok_or(ProviderError::NotFound)
+    }
+
+    async fn add(
+        &self,
+        key: String,
+        value: T,
+        issuer: String,
+        options: WriteOptions,
+    ) -> ProviderResult<u64> {
+        let _guard = self.locks.lock(&key).await;
+        self.check_writable()?;
+        let current = self.live_metadata(&key).await?;
+        options
+            .precondition
+            .check(current.as_ref().map(|meta| meta.version))?;
+        if current.is_some() {
+            return Err(ProviderError::AlreadyExists);
+        }
+
+        // history left behind by an entry that expired or was removed is not ours
+        Self::remove_file(&self.history_path(&key)).await?;
+
+        let ticket = self.sync.next();
+        let mut metadata = Metadata::new(&value, issuer, options.expires_at())?;
+        metadata.sequence = ticket.sequence();
+        let entry = Entry { value, metadata };
+        self.write_entry(&key, &entry).await?;
+        Self::remove_file(&self.tombstone_path(&key)).await?;
+        self.sync.written(&key, ticket.sequence());
+        self.indexes.insert(&key, &entry.value);
+        self.changes
+            .written(ChangeKind::Add, &key, &entry.value, &entry.metadata);
+
+        Ok(entry.metadata.version)
+    }
+
+    async fn update(
+        &self,
+        key: String,
+        value: T,
+        issuer: String,
+        options: WriteOptions,
+    ) -> ProviderResult<u64> {
+        let _guard = self.locks.lock(&key).await;
+        self.check_writable()?;
+        let current = self
+            .read_entry(&key)
+            .await?
+            .filter(|entry| !entry.metadata.is_expired());
+        options
+            .precondition
+            .check(current.as_ref().map(|entry| entry.metadata.version))?;
+        let current = current.ok_or(ProviderError::NotFound)?;
+
+        let ticket = self.sync.next();
+        let mut metadata = current.metadata.clone();
+        metadata.update(&value, issuer, options.expires_at())?;
+        metadata.sequence = ticket.sequence();
+        self.push_history(&key, current).await?;
+
+        let entry = Entry { value, metadata };
+        self.write_entry(&key, &entry).await?;
+        self.sync.written(&key, ticket.sequence());
+        self.indexes.insert(&key, &entry.value);
+        self.changes
+            .written(ChangeKind::Update, &key, &entry.value, &entry.metadata);
+
+        Ok(entry.metadata.version)
+    }
+
+    async fn metadata(&self, key: String) -> ProviderResult<Metadata> {
+        self.live_metadata(&key)
+            .await?
+            .ok_or(ProviderError::NotFound)
+    }
+
+    async fn remove(&self, key: String, precondition: Precondition) -> ProviderResult<T> {
+        let _guard = self.locks.lock(&key).await;
+        self.check_writable()?;
+        let stored = self.read_entry(&key).await?;
+        let expired = stored
+            .as_ref()
+            .is_some_and(|entry| entry.metadata.is_expired());
+        let existing = stored.filter(|_| !expired);
+        precondition.check(existing.as_ref().map(|entry| entry.metadata.version))?;
+
+        match (&existing, expired) {
+            (Some(_), _) => self.bury(&key, ChangeKind::Remove).await?,
+            (None, true) => self.bury(&key, ChangeKind::Expire).await?,
+            // a history may still be orphaned
+            (None, false) => self.delete_entry(&key).await?,
+        }
+
+        existing
+            .map(|entry| entry.value)
+            .ok_or(ProviderError::NotFound)
+    }
+
+    async fn history(&self, key: String) -> ProviderResult<Vec<Metadata>> {
+        let current = self
+            .live_metadata(&key)
+            .await?
+            .ok_or(ProviderError::NotFound)?;
+        let history = self
+            .read_history::<IgnoredAny>(&key, current.version)
+            .await?;
+
+        Ok(std::iter::once(current)
+            .chain(history.into_iter().rev().map(|entry| entry.metadata))
+            .collect())
+    }
+
+    async fn revision(&self, key: String, version: u64) -> ProviderResult<Entry<T>> {
+        let current = self.entry(key.clone()).await?;
+        if current.metadata.version == version {
+            return Ok(current);
+        }
+
+        self.read_history(&key, current.metadata.version)
+            .await?
+            .into_iter()
+            .find(|entry| entry.metadata.version == version)
+            .ok_or(ProviderError::RevisionNotFound)
+    }
+
+    async fn list(&self) -> ProviderResult<Vec<(String, T)>> {
+        let mut out = Vec::new();
+        let (files, _) = self.walk().await?;
+
+        for path in files {
+            let Some(filename) = Self::entry_file(&path) else {
+                continue;
+            };
+
+            // an entry removed since the directory was read is simply skipped
+            let Some(record) = self.read_json::<_, Record<Entry<T>>>(&path).await? else {
+                continue;
+            };
+            if record.entry.metadata.is_expired() {
+                continue;
+            }
+
+            if let Some(key) = record.key.or_else(|| decode_key(filename)) {
+                out.push((key, record.entry.value));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Keys are recovered from file names alone (only hashed names need their record read),
+    /// and only the records of keys that make it into the page are read to check for expiry.
+    async fn list_keys(
+        &self,
+        prefix: String,
+        after: Option<String>,
+        limit: Option<usize>,
+    ) -> ProviderResult<KeyPage> {
+        let (files, _) = self.walk().await?;
+
+        let mut candidates = Vec::new();
+        for path in files {
+            let Some(filename) = Self::entry_file(&path) else {
+                continue;
+            };
+
+            if let Some(key) = self.key_for(filename).await?
+                && key.starts_with(&prefix)
+                && after.as_ref().is_none_or(|after| &key > after)
+            {
+                candidates.push(key);
+            }
+        }
+        candidates.sort_unstable();
+
+        let wanted = limit.map_or(usize::MAX, |limit| limit.saturating_add(1));
+        let mut keys = Vec::new();
+        for key in candidates {
+            if keys.len() == wanted {
+                break;
+            }
+            if self.live_metadata(&key).await?.is_some() {
+                keys.push(key);
+            }
+        }
+
+        Ok(KeyPage::from_sorted(keys, limit))
+    }
+
+    /// Holds the locks of every key involved throughout. Readers do not take locks, so they
+    /// may see some writes of a transaction before the others land.
+    async fn transact(
+        &self,
+        operations: Vec<BatchOperation<T>>,
+        issuer: String,
+    ) -> ProviderResult<Result<Vec<BatchOutcome<T>>, BatchError>> {
+        let keys: Vec<String> = operations
+            .iter()
+            .map(|operation| operation.key().to_owned())
+            .collect();
+        let _guards = self.locks.lock_all(keys.iter().map(String::as_str)).await;
+        self.check_writable()?;
+
+        let mut current = HashMap::new();
+        for key in keys {
+            if let hash_map::Entry::Vacant(slot) = current.entry(key) {
+                let entry = self
+                    .read_entry(slot.key())
+                    .await?
+                    .filter(|entry| !entry.metadata.is_expired());
+                slot.insert(entry);
+            }
+        }
+
+        let mut plan = match BatchPlan::new(operations, &issuer, current.clone()) {
+            Ok(plan) => plan,
+            Err(e) => return Ok(Err(e)),
+        };
+        if plan.writes.is_empty() {
+            return Ok(Ok(plan.outcomes));
+        }
+
+        // every write takes its own number, pending until its change is published
+        let tickets: Vec<_> = plan.writes.iter().map(|_| self.sync.next()).collect();
+        for (write, ticket) in plan.writes.iter_mut().zip(&tickets) {
+            if let Some(entry) = &mut write.entry {
+                entry.metadata.sequence = ticket.sequence();
+            }
+        }
+
+        // the final entry and history of every key written, in the order first written
+        let mut commits: Vec<BatchCommit<T>> = Vec::new();
+        let mut positions = HashMap::new();
+        for (write, ticket) in plan.writes.iter().zip(&tickets) {
+            let position = match positions.get(&write.key) {
+                Some(&position) => position,
+                None => {
+                    let entry = current.remove(&write.key).flatten();
+                    let history = match &entry {
+                        Some(entry) => {
+                            self.read_history(&write.key, entry.metadata.version)
+                                .await?
+                        }
+                        None => Vec::new(),
+                    };
+                    positions.insert(write.key.clone(), commits.len());
+                    commits.push(BatchCommit {
+                        key: write.key.clone(),
+                        entry,
+                        history,
+                        tombstone: None,
+                    });
+                    commits.len() - 1
+                }
+            };
+
+            let commit = &mut commits[position];
+            match write.entry.clone() {
+                Some(entry) if entry.metadata.version > 0 => {
+                    commit.history.extend(commit.entry.take());
+                    let excess = commit.history.len().saturating_sub(self.history_depth);
+                    commit.history.drain(..excess);
+                    commit.entry = Some(entry);
+                }
+                entry => {
+                    commit.history.clear();
+                    commit.tombstone = entry
+                        .is_none()
+                        .then(|| Tombstone::new(&write.key, ticket.sequence(), ChangeKind::Remove));
+                    commit.entry = entry;
+                }
+            }
+        }
+
+        let journal = self.path.join(BATCH_DIR).join(format!(
+            "{}.{}",
+            self.tmp_counter.fetch_add(1, Ordering::Relaxed),
+            std::process::id()
+        ));
+        self.write_json(&journal, &commits).await?;
+        if let Err(e) = self.apply_commits(&commits).await {
+            // the keys are still locked, so rolling forward now hides the partial state at once
+            warn!("retrying transaction {}: {e}", journal.display());
+            if let Err(e) = self.apply_commits(&commits).await {
+                self.stalled.store(true, Ordering::Release);
+                error!(
+                    "transaction {} is half applied, refusing writes until a restart finishes it: {e}",
+                    journal.display()
+                );
+                return Err(e);
+            }
+        }
+        Self::remove_file(&journal).await?;
+        Self::sync_dir(&self.path.join(BATCH_DIR)).await?;
+
+        for commit in commits {
+            match (&commit.entry, commit.tombstone) {
+                (Some(entry), _) => self.sync.written(&commit.key, entry.metadata.sequence),
+                (None, Some(tombstone)) => self.sync.bury(tombstone),
+                (None, None) => {}
+            }
+        }
+        for (write, ticket) in plan.writes.iter().zip(&tickets) {
+            match &write.entry {
+                Some(entry) => {
+                    let kind = match entry.metadata.version {
+                        0 => ChangeKind::Add,
+                        _ => ChangeKind::Update,
+                    };
+                    self.changes
+                        .written(kind, &write.key, &entry.value, &entry.metadata);
+                }
+                None => self
+                    .changes
+                    .removed(ChangeKind::Remove, &write.key, ticket.sequence()),
+            }
+        }
+
+        Ok(Ok(plan.outcomes))
+    }
+
+    async fn purge(&self, issuer: String) -> ProviderResult<()> {
+        let (files, _) = self.walk().await?;
+
+        for path in files {
+            let Some(name) = Self::entry_file(&path) else {
+                continue;
+            };
+
+            let Some((key, _guard)) = self.lock_file(name).await? else {
+                continue;
+            };
+            self.check_writable()?;
+            if let Some(header) = self.read_json::<_, RecordHeader>(&path).await?
+                && header.metadata.created_by == issuer
+            {
+                self.bury(&key, ChangeKind::Purge).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn sweep_expired(&self) -> ProviderResult<usize> {
+        let mut removed = 0;
+        let (files, _) = self.walk().await?;
+
+        for path in files {
+            let Some(name) = Self::entry_file(&path) else {
+                continue;
+            };
+
+            let Some((key, _guard)) = self.lock_file(name).await? else {
+                continue;
+            };
+            self.check_writable()?;
+            if let Some(header) = self.read_json::<_, RecordHeader>(&path).await?
+                && header.metadata.is_expired()
+            {
+                self.bury(&key, ChangeKind::Expire).await?;
+                removed += 1;
+            }
+        }
+        self.prune_tombstones().await?;
+
+        Ok(removed)
+    }
+
+    async fn indexes(&self) -> ProviderResult<Vec<IndexDefinition>> {
+        Ok(self.indexes.definitions())
+    }
+
+    async fn create_index(&self, index: IndexDefinition) -> ProviderResult<()> {
+        self.indexes.create(index.clone())?;
+        self.build_index(&index).await
+    }
+
+    async fn rebuild_index(&self, name: String) -> ProviderResult<()> {
+        let definition = self.indexes.reset(&name)?;
+        self.build_index(&definition).await
+    }
+
+    async fn drop_index(&self, name: String) -> ProviderResult<()> {
+        self.indexes.drop(&name)
+    }
+
+    async fn lookup(&self, name: String, range: IndexRange) -> ProviderResult<Vec<String>> {
+        self.indexes.lookup(&name, &range)
+    }
+
+    fn changes(&self) -> broadcast::Receiver<Arc<Change<T>>> {
+        self.changes.subscribe()
+    }
+
+    /// Takes the stable sequence number first, then reads the entries the sync log has
+    /// changed after `after`, in sequence order, until the page is full. Writes landing
+    /// meanwhile carry a higher number and are left for the next page.
+    async fn changes_since(
+        &self,
+        prefix: String,
+        after: u64,
+        limit: usize,
+    ) -> ProviderResult<ChangePage<T>> {
+        self.sync.check_cursor(after)?;
+        let stable = self.sync.stable();
+
+        let mut changes = Vec::new();
+        let mut from = after;
+        while changes.len() <= limit {
+            let changed = self.sync.changed(&prefix, from, stable, limit + 1);
+            let Some(last) = changed.last() else {
+                break;
+            };
+            from = last.sequence();
+
+            for change in changed {
+                let (key, sequence) = match change {
+                    Changed::Removed(tombstone) => {
+                        changes.push(SyncChange::Removed(tombstone));
+                        continue;
+                    }
+                    Changed::Written { key, sequence } => (key, sequence),
+                };
+
+                // an entry removed or written again since shows up at its later number
+                let Some(entry) = self.read_entry(&key).await? else {
+                    continue;
+                };
+                if entry.metadata.sequence == sequence && !entry.metadata.is_expired() {
+                    changes.push(SyncChange::Written { key, entry });
+                }
+            }
+        }
+
+        Ok(ChangePage::from_changes(changes, limit, stable))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::providers::{
+        VersionMatch,
+        batch::{BatchOperation, BatchOutcome},
+        changes::ChangeKind,
+        index::IndexValue,
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("objekt-fs-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn recovery_repairs_and_quarantines() {
+        let dir = temp_dir("recover");
+        let meta = r#"{"created_at":"2025-06-20T12:00:00Z","version":0,"issuer":"t"}"#;
+
+        std::fs::write(dir.join(LAYOUT_FILE), "1").unwrap();
+        std::fs::write(dir.join("good"), "1").unwrap();
+        std::fs::write(dir.join("good.meta"), meta).unwrap();
+        std::fs::write(dir.join("torn"), r#"{"half":"#).unwrap();
+        std::fs::write(dir.join("torn.meta"), meta).unwrap();
+        std::fs::write(dir.join("ownerless"), "2").unwrap();
+        std::fs::write(dir.join("orphan.meta"), meta).unwrap();
+        std::fs::create_dir_all(dir.join(TMP_DIR)).unwrap();
+        std::fs::write(dir.join(TMP_DIR).join("0.1"), "3").unwrap();
+
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+
+        assert_eq!(
+            cache.list().await.unwrap(),
+            vec![("good".to_owned(), json!(1))]
+        );
+        assert!(!dir.join("orphan.meta").exists());
+        assert_eq!(std::fs::read_dir(dir.join(TMP_DIR)).unwrap().count(), 0);
+        assert_eq!(
+            std::fs::read_dir(dir.join(QUARANTINE_DIR)).unwrap().count(),
+            3
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn writes_leave_no_temporary_files() {
+        let dir = temp_dir("atomic");
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+
+        cache
+            .add("a".into(), json!({"x": 1}), "t".into(), Default::default())
+            .await
+            .unwrap();
+        cache
+            .update("a".into(), json!({"x": 2}), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            cache.entry("a".into()).await.unwrap().value,
+            json!({"x": 2})
+        );
+        assert_eq!(std::fs::read_dir(dir.join(TMP_DIR)).unwrap().count(), 0);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn updates_keep_creation_metadata() {
+        let dir = temp_dir("metadata");
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+
+        cache
+            .add("a".into(), json!(1), "alice".into(), Default::default())
+            .await
+            .unwrap();
+        let created = cache.metadata("a".into()).await.unwrap();
+        cache
+            .update("a".into(), json!([1, 2]), "bob".into(), Default::default())
+            .await
+            .unwrap();
+        let updated = cache.metadata("a".into()).await.unwrap();
+
+        assert_eq!(updated.created_at, created.created_at);
+        assert_eq!(updated.created_by, "alice");
+        assert_eq!(updated.updated_by, "bob");
+        assert_eq!((updated.version, updated.size), (1, 5));
+        assert_ne!(updated.digest, created.digest);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn keeps_history_and_rolls_back() {
+        let dir = temp_dir("history");
+        let cache = FileSystemProvider::<Value>::new(dir.clone())
+            .await
+            .unwrap()
+            .with_history_depth(2);
+
+        cache
+            .add("a".into(), json!(0), "t".into(), Default::default())
+            .await
+            .unwrap();
+        for i in 1..=3 {
+            cache
+                .update("a".into(), json!(i), "t".into(), Default::default())
+                .await
+                .unwrap();
+        }
+
+        let versions: Vec<_> = cache
+            .history("a".into())
+            .await
+            .unwrap()
+            .iter()
+            .map(|metadata| metadata.version)
+            .collect();
+        assert_eq!(versions, vec![3, 2, 1]);
+        assert_eq!(cache.revision("a".into(), 2).await.unwrap().value, json!(2));
+        assert!(matches!(
+            cache.revision("a".into(), 0).await,
+            Err(ProviderError::RevisionNotFound)
+        ));
+
+        let version = cache
+            .rollback("a".into(), 1, "u".into(), Default::default())
+            .await
+            .unwrap();
+        let entry = cache.entry("a".into()).await.unwrap();
+        assert_eq!((version, entry.value), (4, json!(1)));
+        assert_eq!(entry.metadata.created_by, "t");
+
+        cache.remove("a".into(), Default::default()).await.unwrap();
+        assert!(!cache.history_path("a").exists());
+        cache
+            .add("a".into(), json!(5), "t".into(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(cache.history("a".into()).await.unwrap().len(), 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn rebuilds_indexes_from_entries() {
+        let dir = temp_dir("indexes");
+        let index = IndexDefinition {
+            name: "lang".into(),
+            prefix: "p:".into(),
+            pointer: "/lang".into(),
+        };
+        let rust = || IndexRange::eq(IndexValue::String("rust".into()));
+
+        {
+            let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+            cache
+                .add(
+                    "p:a".into(),
+                    json!({"lang": "rust"}),
+                    "t".into(),
+                    Default::default(),
+                )
+                .await
+                .unwrap();
+            cache.create_index(index.clone()).await.unwrap();
+            for (key, lang) in [("p:b", "go"), ("p:c", "rust"), ("q:d", "rust")] {
+                cache
+                    .add(
+                        key.into(),
+                        json!({"lang": lang}),
+                        "t".into(),
+                        Default::default(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            cache
+                .update(
+                    "p:b".into(),
+                    json!({"lang": "rust"}),
+                    "t".into(),
+                    Default::default(),
+                )
+                .await
+                .unwrap();
+            cache
+                .remove("p:a".into(), Default::default())
+                .await
+                .unwrap();
+
+            assert_eq!(
+                cache.lookup("lang".into(), rust()).await.unwrap(),
+                vec!["p:b", "p:c"]
+            );
+        }
+
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+        assert_eq!(cache.indexes().await.unwrap(), vec![index]);
+        assert_eq!(
+            cache.lookup("lang".into(), rust()).await.unwrap(),
+            vec!["p:b", "p:c"]
+        );
+
+        cache.purge("t".into()).await.unwrap();
+        assert!(
+            cache
+                .lookup("lang".into(), rust())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn transactions_apply_all_or_nothing() {
+        let dir = temp_dir("batch");
+        let cache = FileSystemProvider::<Value>::new(dir.clone())
+            .await
+            .unwrap()
+            .with_history_depth(2);
+        cache
+            .add("a".into(), json!(0), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        let update = |value| BatchOperation::Update {
+            key: "a".into(),
+            value,
+            options: Default::default(),
+        };
+        let failed = cache
+            .transact(
+                vec![
+                    update(json!(1)),
+                    BatchOperation::Add {
+                        key: "a".into(),
+                        value: json!(2),
+                        options: Default::default(),
+                    },
+                ],
+                "t".into(),
+            )
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(failed.index, 1);
+        assert!(matches!(failed.error, ProviderError::AlreadyExists));
+        assert_eq!(cache.entry("a".into()).await.unwrap().value, json!(0));
+
+        let outcomes = cache
+            .transact(
+                vec![update(json!(1)), update(json!(2)), update(json!(3))],
+                "t".into(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(outcomes.last(), Some(&BatchOutcome::Written(3)));
+        let versions: Vec<_> = cache
+            .history("a".into())
+            .await
+            .unwrap()
+            .iter()
+            .map(|metadata| metadata.version)
+            .collect();
+        assert_eq!(versions, vec![3, 2, 1]);
+        assert_eq!(std::fs::read_dir(dir.join(BATCH_DIR)).unwrap().count(), 0);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn finishes_interrupted_transaction() {
+        let dir = temp_dir("batch-crash");
+        {
+            let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+            cache
+                .add("a".into(), json!(1), "t".into(), Default::default())
+                .await
+                .unwrap();
+        }
+
+        // a journal whose writes never made it to their files
+        let metadata = Metadata::new(&json!(2), "t".into(), None).unwrap();
+        let journal = json!([
+            {"key": "a", "entry": null, "history": []},
+            {"key": "b", "entry": {"value": 2, "metadata": metadata}, "history": []}
+        ]);
+        std::fs::create_dir_all(dir.join(BATCH_DIR)).unwrap();
+        std::fs::write(dir.join(BATCH_DIR).join("7.1"), journal.to_string()).unwrap();
+
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+        assert!(cache.entry("a".into()).await.is_err());
+        assert_eq!(cache.entry("b".into()).await.unwrap().value, json!(2));
+        assert!(!dir.join(BATCH_DIR).join("7.1").exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn refuses_writes_after_a_transaction_stalls() {
+        let dir = temp_dir("batch-stall");
+        {
+            let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+            for key in ["a", "b"] {
+                cache
+                    .add(key.into(), json!(1), "t".into(), Default::default())
+                    .await
+                    .unwrap();
+            }
+
+            // a directory where the tombstone of `b` goes makes removing `b` fail, twice
+            std::fs::create_dir_all(dir.join("b.tombstone").join("x")).unwrap();
+            let operations = vec![
+                BatchOperation::Update {
+                    key: "a".into(),
+                    value: json!(2),
+                    options: Default::default(),
+                },
+                BatchOperation::Remove {
+                    key: "b".into(),
+                    precondition: Default::default(),
+                },
+            ];
+            assert!(cache.transact(operations, "t".into()).await.is_err());
+            assert!(matches!(
+                cache
+                    .add("c".into(), json!(3), "t".into(), Default::default())
+                    .await,
+                Err(ProviderError::Stalled)
+            ));
+        }
+
+        std::fs::remove_dir_all(dir.join("b.tombstone")).unwrap();
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+        assert_eq!(cache.entry("a".into()).await.unwrap().value, json!(2));
+        assert!(cache.entry("b".into()).await.is_err());
+        cache
+            .add("c".into(), json!(3), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn publishes_every_change() {
+        let dir = temp_dir("changes");
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+        let mut changes = cache.changes();
+
+        cache
+            .add("a".into(), json!(1), "t".into(), Default::default())
+            .await
+            .unwrap();
+        let expiring = WriteOptions {
+            ttl: Some(chrono::TimeDelta::milliseconds(1)),
+            ..Default::default()
+        };
+        cache
+            .add("b".into(), json!(2), "t".into(), expiring)
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        cache.sweep_expired().await.unwrap();
+        cache
+            .transact(
+                vec![
+                    BatchOperation::Update {
+                        key: "a".into(),
+                        value: json!(3),
+                        options: Default::default(),
+                    },
+                    BatchOperation::Remove {
+                        key: "a".into(),
+                        precondition: Default::default(),
+                    },
+                ],
+                "t".into(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut seen = Vec::new();
+        while let Ok(change) = changes.try_recv() {
+            let version = change.metadata.as_ref().map(|metadata| metadata.version);
+            seen.push((change.kind, change.key.clone(), version));
+        }
+        assert_eq!(
+            seen,
+            vec![
+                (ChangeKind::Add, "a".into(), Some(0)),
+                (ChangeKind::Add, "b".into(), Some(0)),
+                (ChangeKind::Expire, "b".into(), None),
+                (ChangeKind::Update, "a".into(), Some(1)),
+                (ChangeKind::Remove, "a".into(), None),
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// Sequence number, key and whether it was removed, for every change on `page`.
+    fn synced(page: &ChangePage<Value>) -> Vec<(u64, &str, bool)> {
+        page.changes
+            .iter()
+            .map(|change| match change {
+                SyncChange::Written { key, entry } => {
+                    (entry.metadata.sequence, key.as_str(), false)
+                }
+                SyncChange::Removed(tombstone) => {
+                    (tombstone.sequence, tombstone.key.as_str(), true)
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn syncs_changes_since_a_sequence_across_restarts() {
+        let dir = temp_dir("sync");
+
+        {
+            let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+            for key in ["p:a", "p:b", "q:c"] {
+                cache
+                    .add(key.into(), json!(1), "t".into(), Default::default())
+                    .await
+                    .unwrap();
+            }
+            cache
+                .update("p:a".into(), json!(2), "t".into(), Default::default())
+                .await
+                .unwrap();
+            cache
+                .remove("p:b".into(), Default::default())
+                .await
+                .unwrap();
+
+            let page = cache.changes_since("p:".into(), 0, 1).await.unwrap();
+            assert_eq!(synced(&page), [(4, "p:a", false)]);
+            assert_eq!((page.cursor, page.more), (4, true));
+        }
+
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+        let page = cache.changes_since("p:".into(), 4, 10).await.unwrap();
+        assert_eq!(synced(&page), [(5, "p:b", true)]);
+        assert_eq!((page.cursor, page.more), (5, false));
+        assert!(matches!(
+            cache.changes_since("p:".into(), 6, 10).await,
+            Err(ProviderError::StaleCursor)
+        ));
+
+        cache
+            .add("p:b".into(), json!(3), "t".into(), Default::default())
+            .await
+            .unwrap();
+        let page = cache.changes_since("p:".into(), 5, 10).await.unwrap();
+        assert_eq!(synced(&page), [(6, "p:b", false)]);
+        assert!(!cache.tombstone_path("p:b").exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn recovers_interrupted_removals_and_numbers_old_entries() {
+        let dir = temp_dir("tombstones");
+        let old = r#"{"value":1,"metadata":{"created_at":"2025-06-20T12:00:00Z","created_by":"t","version":0}}"#;
+
+        {
+            let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+            for key in ["a", "b"] {
+                cache
+                    .add(key.into(), json!(1), "t".into(), Default::default())
+                    .await
+                    .unwrap();
+            }
+        }
+        // the removal of `a` stopped after its tombstone, `b` was added again before its old
+        // tombstone was deleted, and `c` was written before writes were numbered
+        let tombstone = |key, sequence| {
+            serde_json::to_vec(&Tombstone::new(key, sequence, ChangeKind::Remove)).unwrap()
+        };
+        std::fs::write(dir.join("a.tombstone"), tombstone("a", 3)).unwrap();
+        std::fs::write(dir.join("b.tombstone"), tombstone("b", 1)).unwrap();
+        std::fs::write(dir.join("c"), old).unwrap();
+
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+        assert!(cache.entry("a".into()).await.is_err());
+        assert!(!dir.join("b.tombstone").exists());
+
+        let page = cache.changes_since(String::new(), 0, 10).await.unwrap();
+        assert_eq!(
+            synced(&page),
+            [(2, "b", false), (3, "a", true), (4, "c", false)]
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn reports_typed_errors() {
+        let dir = temp_dir("errors");
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+
+        cache
+            .add("a".into(), json!(1), "t".into(), Default::default())
+            .await
+            .unwrap();
+        let again = cache
+            .add("a".into(), json!(2), "t".into(), Default::default())
+            .await;
+        assert!(matches!(again, Err(ProviderError::AlreadyExists)));
+        assert!(matches!(
+            cache.entry("missing".into()).await,
+            Err(ProviderError::NotFound)
+        ));
+
+        // damaged after startup, so recovery did not get to quarantine it
+        std::fs::write(cache.entry_path("a"), "{").unwrap();
+        assert!(matches!(
+            cache.entry("a".into()).await,
+            Err(ProviderError::Corrupt(_))
+        ));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn conditional_writes_check_version() {
+        let dir = temp_dir("conditional");
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+        let if_match = |version| Precondition {
+            if_match: Some(VersionMatch::Versions(vec![version])),
+            ..Default::default()
+        };
+        let with = |precondition| WriteOptions {
+            precondition,
+            ..Default::default()
+        };
+
+        let missing = cache
+            .update("a".into(), json!(1), "t".into(), with(if_match(0)))
+            .await;
+        assert!(matches!(missing, Err(ProviderError::PreconditionFailed)));
+
+        let absent = Precondition {
+            if_none_match: Some(VersionMatch::Any),
+            ..Default::default()
+        };
+        let version = cache
+            .add("a".into(), json!(1), "t".into(), with(absent))
+            .await
+            .unwrap();
+        let version = cache
+            .update("a".into(), json!(2), "t".into(), with(if_match(version)))
+            .await
+            .unwrap();
+        assert_eq!(version, 1);
+
+        let stale = cache
+            .update("a".into(), json!(3), "t".into(), with(if_match(0)))
+            .await;
+        assert!(matches!(stale, Err(ProviderError::PreconditionFailed)));
+        let entry = cache.entry("a".into()).await.unwrap();
+        assert_eq!((entry.value, entry.metadata.version), (json!(2), 1));
+
+        cache.remove("a".into(), if_match(1)).await.unwrap();
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn pages_through_prefix() {
+        let dir = temp_dir("pages");
+        let cache = FileSystemProvider::<Value>::with_shard_depth(dir.clone(), 1)
+            .await
+            .unwrap();
+        for key in ["p:c", "p:a", "q:a", "p:b", "p:d"] {
+            cache
+                .add(key.into(), json!(1), "t".into(), Default::default())
+                .await
+                .unwrap();
+        }
+
+        let first = cache.list_keys("p:".into(), None, Some(3)).await.unwrap();
+        assert_eq!(first.keys, vec!["p:a", "p:b", "p:c"]);
+        assert_eq!(first.next.as_deref(), Some("p:c"));
+
+        let rest = cache
+            .list_keys("p:".into(), first.next, Some(3))
+            .await
+            .unwrap();
+        assert_eq!(rest.keys, vec!["p:d"]);
+        assert_eq!(rest.next, None);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn keys_cannot_escape_or_collide() {
+        let dir = temp_dir("encoding");
+        let cache = FileSystemProvider::<Value>::new(dir.join("data"))
+            .await
+            .unwrap();
+
+        cache
+            .add("../escape".into(), json!(1), "t".into(), Default::default())
+            .await
+            .unwrap();
+        cache
+            .add("a".into(), json!(2), "t".into(), Default::default())
+            .await
+            .unwrap();
+        cache
+            .add("a.meta".into(), json!(3), "t".into(), Default::default())
+            .await
+            .unwrap();
+        let long = "k".repeat(300);
+        cache
+            .add(long.clone(), json!(4), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        assert!(!dir.join("escape").exists());
+        assert_eq!(cache.metadata("a".into()).await.unwrap().version, 0);
+        assert_eq!(cache.entry("a.meta".into()).await.unwrap().value, json!(3));
+
+        let mut keys: Vec<String> = cache
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec!["../escape".to_owned(), "a".into(), "a.meta".into(), long]
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn migrates_raw_key_layout() {
+        let dir = temp_dir("migrate");
+        let meta = r#"{"created_at":"2025-06-20T12:00:00Z","version":0,"issuer":"t"}"#;
+
+        std::fs::write(dir.join("projects:Rust"), "1").unwrap();
+        std::fs::write(dir.join("projects:Rust.meta"), meta).unwrap();
+
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+
+        assert_eq!(
+            cache.entry("projects:Rust".into()).await.unwrap().value,
+            json!(1)
+        );
+        assert!(cache.metadata("projects:Rust".into()).await.is_ok());
+        assert!(!dir.join("projects:Rust").exists());
+        assert!(!dir.join(MIGRATE_DIR).exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn merges_split_metadata() {
+        let dir = temp_dir("merge");
+        let meta = r#"{"created_at":"2025-06-20T12:00:00Z","version":3,"issuer":"t"}"#;
+
+        std::fs::write(dir.join(LAYOUT_FILE), "1").unwrap();
+        std::fs::write(dir.join("a"), r#"{"x":1}"#).unwrap();
+        std::fs::write(dir.join("a.meta"), meta).unwrap();
+        // `b` was folded by an interrupted run that did not get to delete its `.meta`
+        std::fs::write(dir.join("b"), format!(r#"{{"value":2,"metadata":{meta}}}"#)).unwrap();
+        std::fs::write(dir.join("b.meta"), meta).unwrap();
+
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+
+        let a = cache.entry("a".into()).await.unwrap();
+        assert_eq!((a.value, a.metadata.version), (json!({"x": 1}), 3));
+        assert_eq!(cache.entry("b".into()).await.unwrap().value, json!(2));
+        assert!(!dir.join("a.meta").exists() && !dir.join("b.meta").exists());
+        assert_eq!(cache.read_layout().await.unwrap().version, LAYOUT_VERSION);
+        assert!(!dir.join(QUARANTINE_DIR).exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn reshards_in_place() {
+        let dir = temp_dir("reshard");
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+        for key in ["a", "b", "projects:rust"] {
+            cache
+                .add(key.into(), json!(key), "t".into(), Default::default())
+                .await
+                .unwrap();
+        }
+        drop(cache);
+
+        let cache = FileSystemProvider::<Value>::with_shard_depth(dir.clone(), 2)
+            .await
+            .unwrap();
+        assert!(!dir.join("a").exists());
+        assert!(
+            cache
+                .entry_path("a")
+                .starts_with(dir.join(&shard_dirs("a", 1)[0]))
+        );
+        assert_eq!(cache.entry("a".into()).await.unwrap().value, json!("a"));
+        assert_eq!(cache.list().await.unwrap().len(), 3);
+
+        cache
+            .add("c".into(), json!("c"), "t".into(), Default::default())
+            .await
+            .unwrap();
+        drop(cache);
+
+        // reopening without a depth keeps the sharded layout
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+        assert_eq!(cache.entry("c".into()).await.unwrap().value, json!("c"));
+
+        let cache = FileSystemProvider::<Value>::with_shard_depth(dir.clone(), 0)
+            .await
+            .unwrap();
+        assert!(dir.join("c").exists());
+        assert_eq!(cache.list().await.unwrap().len(), 4);
+        assert!(
+            std::fs::read_dir(&dir)
+                .unwrap()
+                .all(|e| !is_shard_dir(e.unwrap().file_name().to_str().unwrap()))
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn finishes_interrupted_reshard() {
+        let dir = temp_dir("reshard-resume");
+        let meta = r#"{"created_at":"2025-06-20T12:00:00Z","version":0,"issuer":"t"}"#;
+        let shard = dir.join(&shard_dirs("b", 1)[0]);
+
+        // `a` was still in place when the re-shard to depth 1 was cut short, `b` had moved
+        std::fs::write(
+            dir.join(LAYOUT_FILE),
+            r#"{"version":1,"shard_depth":0,"resharding_to":1}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("a"), "1").unwrap();
+        std::fs::write(dir.join("a.meta"), meta).unwrap();
+        std::fs::create_dir_all(&shard).unwrap();
+        std::fs::write(shard.join("b"), "2").unwrap();
+        std::fs::write(shard.join("b.meta"), meta).unwrap();
+
+        let cache = FileSystemProvider::<Value>::new(dir.clone()).await.unwrap();
+
+        assert_eq!(cache.shard_depth, 1);
+        assert_eq!(cache.entry("a".into()).await.unwrap().value, json!(1));
+        assert_eq!(cache.entry("b".into()).await.unwrap().value, json!(2));
+        assert!(!dir.join("a").exists());
+
+        let _ = std::fs::remove_dir_all(dir);
     }
 }
@@ -0,0 +1,486 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::{self, File},
+    io::{self, Write},
+    ops::Bound,
+    path::PathBuf,
+    sync::RwLock,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::error;
+
+use super::{ProviderError, ProviderResult};
+
+/// A secondary index: the values at `pointer` of every entry whose key starts with `prefix`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexDefinition {
+    pub name: String,
+    pub prefix: String,
+    /// JSON Pointer (RFC 6901) into the values.
+    pub pointer: String,
+}
+
+/// An indexed JSON scalar, ordered null < booleans < numbers < strings.
+///
+/// Numbers compare by value as `f64`, like they do in filters, so `1` and `1.0` are one key.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IndexValue {
+    Null,
+    Bool(bool),
+    Number(IndexNumber),
+    String(String),
+}
+
+/// A finite `f64` with a total order.
+#[derive(Debug, Clone, Copy)]
+pub struct IndexNumber(f64);
+
+/// Bounds of an index lookup. Equality is a range with both ends included.
+#[derive(Debug, Clone)]
+pub struct IndexRange {
+    pub lower: Bound<IndexValue>,
+    pub upper: Bound<IndexValue>,
+}
+
+impl IndexValue {
+    /// The key `value` is indexed under. Arrays and objects are not indexed.
+    pub fn from_json(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(Self::Null),
+            Value::Bool(b) => Some(Self::Bool(*b)),
+            // `-0.0 + 0.0` is `0.0`, so both zeros are one key
+            Value::Number(n) => Some(Self::Number(IndexNumber(n.as_f64()? + 0.0))),
+            Value::String(s) => Some(Self::String(s.clone())),
+            Value::Array(_) | Value::Object(_) => None,
+        }
+    }
+}
+
+impl PartialEq for IndexNumber {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for IndexNumber {}
+
+impl PartialOrd for IndexNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IndexNumber {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl IndexRange {
+    /// Values equal to `value`.
+    pub fn eq(value: IndexValue) -> Self {
+        Self {
+            lower: Bound::Included(value.clone()),
+            upper: Bound::Included(value),
+        }
+    }
+
+    fn contains(&self, value: &IndexValue) -> bool {
+        let above = match &self.lower {
+            Bound::Included(lower) => value >= lower,
+            Bound::Excluded(lower) => value > lower,
+            Bound::Unbounded => true,
+        };
+        let below = match &self.upper {
+            Bound::Included(upper) => value <= upper,
+            Bound::Excluded(upper) => value < upper,
+            Bound::Unbounded => true,
+        };
+
+        above && below
+    }
+}
+
+/// The contents of one index.
+struct SecondaryIndex {
+    definition: IndexDefinition,
+    /// `(value, key)` pairs, so lookups walk keys in value order.
+    entries: BTreeSet<(IndexValue, String)>,
+    /// The value each key is indexed under, to find its pair again.
+    values: HashMap<String, IndexValue>,
+    /// While the index is built from stored entries, the keys written in the meantime. What
+    /// the build read for them may already be stale.
+    building: Option<HashSet<String>>,
+}
+
+impl SecondaryIndex {
+    fn new(definition: IndexDefinition) -> Self {
+        Self {
+            definition,
+            entries: BTreeSet::new(),
+            values: HashMap::new(),
+            building: Some(HashSet::new()),
+        }
+    }
+
+    fn covers(&self, key: &str) -> bool {
+        key.starts_with(&self.definition.prefix)
+    }
+
+    fn insert(&mut self, key: &str, value: &Value) {
+        self.remove(key);
+
+        if let Some(indexed) = value
+            .pointer(&self.definition.pointer)
+            .and_then(IndexValue::from_json)
+        {
+            self.entries.insert((indexed.clone(), key.to_owned()));
+            self.values.insert(key.to_owned(), indexed);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(indexed) = self.values.remove(key) {
+            self.entries.remove(&(indexed, key.to_owned()));
+        }
+    }
+
+    fn lookup(&self, range: &IndexRange) -> Vec<String> {
+        let start = match &range.lower {
+            Bound::Included(lower) | Bound::Excluded(lower) => {
+                Bound::Included((lower.clone(), String::new()))
+            }
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        self.entries
+            .range((start, Bound::Unbounded))
+            .skip_while(
+                |(value, _)| matches!(&range.lower, Bound::Excluded(lower) if value == lower),
+            )
+            .take_while(|(value, _)| range.contains(value))
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+}
+
+/// Every secondary index of a provider.
+///
+/// Definitions are saved to `file`, if given, whenever one is created or dropped; contents are
+/// only kept in memory and built from the stored entries. Providers call
+/// [`Indexes::insert`] and [`Indexes::remove`] after every write, while still holding the lock
+/// that orders writes to the key, so the index sees writes to a key in the order they happened.
+pub struct Indexes {
+    file: Option<PathBuf>,
+    indexes: RwLock<BTreeMap<String, SecondaryIndex>>,
+}
+
+impl Indexes {
+    /// Loads the definitions saved in `file`. Every index starts out being built, see
+    /// [`Indexes::fill`].
+    pub fn open(file: Option<PathBuf>) -> io::Result<Self> {
+        let definitions: Vec<IndexDefinition> = match &file {
+            Some(path) => match fs::read(path) {
+                Ok(data) => serde_json::from_slice(&data)?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e),
+            },
+            None => Vec::new(),
+        };
+
+        let indexes = definitions
+            .into_iter()
+            .map(|definition| (definition.name.clone(), SecondaryIndex::new(definition)))
+            .collect();
+
+        Ok(Self {
+            file,
+            indexes: RwLock::new(indexes),
+        })
+    }
+
+    pub fn definitions(&self) -> Vec<IndexDefinition> {
+        self.indexes
+            .read()
+            .unwrap()
+            .values()
+            .map(|index| index.definition.clone())
+            .collect()
+    }
+
+    /// Names of the indexes still being built.
+    pub fn building(&self) -> Vec<String> {
+        self.indexes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|index| index.building.is_some())
+            .map(|index| index.definition.name.clone())
+            .collect()
+    }
+
+    /// Whether any index covers `key`, so its writes need to be indexed.
+    pub fn covers(&self, key: &str) -> bool {
+        self.indexes
+            .read()
+            .unwrap()
+            .values()
+            .any(|index| index.covers(key))
+    }
+
+    /// Indexes `value` as the new value of `key`.
+    pub fn insert<T: Serialize>(&self, key: &str, value: &T) {
+        if !self.covers(key) {
+            return;
+        }
+
+        match serde_json::to_value(value) {
+            Ok(value) => self.update(key, |index| index.insert(key, &value)),
+            Err(e) => {
+                error!("failed to index {key}: {e}");
+                self.update(key, |index| index.remove(key));
+            }
+        }
+    }
+
+    /// Drops `key` from every index.
+    pub fn remove(&self, key: &str) {
+        if self.covers(key) {
+            self.update(key, |index| index.remove(key));
+        }
+    }
+
+    fn update(&self, key: &str, mut change: impl FnMut(&mut SecondaryIndex)) {
+        let mut indexes = self.indexes.write().unwrap();
+
+        for index in indexes.values_mut().filter(|index| index.covers(key)) {
+            if let Some(touched) = &mut index.building {
+                touched.insert(key.to_owned());
+            }
+            change(index);
+        }
+    }
+
+    /// Declares a new index and saves the definitions. It is built by [`Indexes::fill`].
+    pub fn create(&self, definition: IndexDefinition) -> ProviderResult<()> {
+        let mut indexes = self.indexes.write().unwrap();
+        if indexes.contains_key(&definition.name) {
+            return Err(ProviderError::IndexExists);
+        }
+
+        let name = definition.name.clone();
+        indexes.insert(name.clone(), SecondaryIndex::new(definition));
+        if let Err(e) = self.save(&indexes) {
+            indexes.remove(&name);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Empties an index so it can be built again by [`Indexes::fill`], returning its
+    /// definition.
+    pub fn reset(&self, name: &str) -> ProviderResult<IndexDefinition> {
+        let mut indexes = self.indexes.write().unwrap();
+        let index = indexes.get_mut(name).ok_or(ProviderError::IndexNotFound)?;
+
+        *index = SecondaryIndex::new(index.definition.clone());
+        Ok(index.definition.clone())
+    }
+
+    /// Removes an index and saves the definitions.
+    pub fn drop(&self, name: &str) -> ProviderResult<()> {
+        let mut indexes = self.indexes.write().unwrap();
+        let removed = indexes.remove(name).ok_or(ProviderError::IndexNotFound)?;
+
+        if let Err(e) = self.save(&indexes) {
+            indexes.insert(name.to_owned(), removed);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Finishes building the index `name` from `entries`, the stored entries as read after
+    /// it was created or reset. Entries written since are already indexed and skipped.
+    pub fn fill(&self, name: &str, entries: impl IntoIterator<Item = (String, Value)>) {
+        let mut indexes = self.indexes.write().unwrap();
+        let Some(index) = indexes.get_mut(name) else {
+            return;
+        };
+        let Some(touched) = index.building.take() else {
+            return;
+        };
+
+        for (key, value) in entries {
+            if index.covers(&key) && !touched.contains(&key) {
+                index.insert(&key, &value);
+            }
+        }
+    }
+
+    /// Keys of the index `name` whose values fall in `range`, in value order.
+    pub fn lookup(&self, name: &str, range: &IndexRange) -> ProviderResult<Vec<String>> {
+        let indexes = self.indexes.read().unwrap();
+        let index = indexes.get(name).ok_or(ProviderError::IndexNotFound)?;
+        if index.building.is_some() {
+            return Err(ProviderError::IndexBuilding);
+        }
+
+        Ok(index.lookup(range))
+    }
+
+    /// Writes the definitions to a temporary file, synced and renamed over `file`.
+    fn save(&self, indexes: &BTreeMap<String, SecondaryIndex>) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+
+        let definitions: Vec<_> = indexes.values().map(|index| &index.definition).collect();
+        let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&serde_json::to_vec(&definitions)?)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+
+        #[cfg(unix)]
+        if let Some(dir) = path.parent() {
+            File::open(dir)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// No indexes, and none saved.
+impl Default for Indexes {
+    fn default() -> Self {
+        Self {
+            file: None,
+            indexes: RwLock::new(BTreeMap::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn definition() -> IndexDefinition {
+        IndexDefinition {
+            name: "stars".into(),
+            prefix: "p:".into(),
+            pointer: "/stars".into(),
+        }
+    }
+
+    fn number(n: f64) -> IndexValue {
+        IndexValue::from_json(&json!(n)).unwrap()
+    }
+
+    #[test]
+    fn answers_equality_and_ranges() {
+        let indexes = Indexes::default();
+        indexes.create(definition()).unwrap();
+        indexes.fill("stars", Vec::new());
+
+        for (key, stars) in [("p:a", json!(5)), ("p:b", json!(10)), ("p:c", json!(10.0))] {
+            indexes.insert(key, &json!({ "stars": stars }));
+        }
+        indexes.insert("p:d", &json!({ "stars": "many" }));
+        indexes.insert("p:e", &json!({ "stars": [1] }));
+        indexes.insert("q:a", &json!({ "stars": 10 }));
+
+        let lookup = |lower, upper| {
+            indexes
+                .lookup("stars", &IndexRange { lower, upper })
+                .unwrap()
+        };
+        assert_eq!(
+            indexes
+                .lookup("stars", &IndexRange::eq(number(10.0)))
+                .unwrap(),
+            vec!["p:b", "p:c"]
+        );
+        assert_eq!(
+            lookup(Bound::Excluded(number(5.0)), Bound::Unbounded),
+            vec!["p:b", "p:c", "p:d"]
+        );
+        assert_eq!(
+            lookup(Bound::Unbounded, Bound::Excluded(number(10.0))),
+            vec!["p:a"]
+        );
+
+        indexes.insert("p:b", &json!({ "stars": 1 }));
+        indexes.remove("p:c");
+        assert_eq!(
+            lookup(Bound::Included(number(1.0)), Bound::Included(number(10.0))),
+            vec!["p:b", "p:a"]
+        );
+    }
+
+    #[test]
+    fn builds_around_concurrent_writes() {
+        let indexes = Indexes::default();
+        indexes.create(definition()).unwrap();
+        assert!(matches!(
+            indexes.lookup("stars", &IndexRange::eq(number(1.0))),
+            Err(ProviderError::IndexBuilding)
+        ));
+
+        // written while the build was reading, so what it read is stale
+        indexes.insert("p:a", &json!({ "stars": 2 }));
+        indexes.remove("p:b");
+        indexes.fill(
+            "stars",
+            vec![
+                ("p:a".to_owned(), json!({ "stars": 1 })),
+                ("p:b".to_owned(), json!({ "stars": 1 })),
+                ("p:c".to_owned(), json!({ "stars": 1 })),
+            ],
+        );
+
+        assert_eq!(
+            indexes
+                .lookup("stars", &IndexRange::eq(number(1.0)))
+                .unwrap(),
+            vec!["p:c"]
+        );
+        assert!(matches!(
+            indexes.create(definition()),
+            Err(ProviderError::IndexExists)
+        ));
+    }
+
+    #[test]
+    fn saves_definitions() {
+        let dir = std::env::temp_dir().join(format!("objekt-index-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("indexes.json");
+
+        let indexes = Indexes::open(Some(file.clone())).unwrap();
+        indexes.create(definition()).unwrap();
+        indexes
+            .create(IndexDefinition {
+                name: "gone".into(),
+                ..definition()
+            })
+            .unwrap();
+        indexes.drop("gone").unwrap();
+
+        let reopened = Indexes::open(Some(file)).unwrap();
+        assert_eq!(reopened.definitions(), vec![definition()]);
+        assert_eq!(reopened.building(), vec!["stars".to_owned()]);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    collections::BTreeSet,
+    hash::{BuildHasher, RandomState},
+};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Number of mutexes keys are spread over.
+const STRIPES: usize = 64;
+
+/// A fixed set of async mutexes that keys are hashed onto.
+///
+/// Holding the lock of a key excludes everyone else working on the same key; unrelated keys
+/// only wait on each other when they happen to share a stripe.
+pub struct KeyLocks {
+    hasher: RandomState,
+    stripes: Box<[Mutex<()>]>,
+}
+
+impl KeyLocks {
+    pub fn new() -> Self {
+        Self {
+            hasher: RandomState::new(),
+            stripes: (0..STRIPES).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    /// Waits for and takes the lock guarding `key`.
+    pub async fn lock(&self, key: &str) -> MutexGuard<'_, ()> {
+        self.stripes[self.stripe(key)].lock().await
+    }
+
+    /// Takes the locks guarding every key in `keys` at once.
+    ///
+    /// Stripes are taken once each and in ascending order, so two callers locking overlapping
+    /// sets cannot deadlock.
+    pub async fn lock_all<'k>(
+        &self,
+        keys: impl IntoIterator<Item = &'k str>,
+    ) -> Vec<MutexGuard<'_, ()>> {
+        let stripes: BTreeSet<usize> = keys.into_iter().map(|key| self.stripe(key)).collect();
+
+        let mut guards = Vec::with_capacity(stripes.len());
+        for stripe in stripes {
+            guards.push(self.stripes[stripe].lock().await);
+        }
+        guards
+    }
+
+    fn stripe(&self, key: &str) -> usize {
+        self.hasher.hash_one(key) as usize % self.stripes.len()
+    }
+}
+
+impl Default for KeyLocks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
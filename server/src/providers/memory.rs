@@ -1,118 +1,1839 @@
-use std::sync::Arc;
+use std::{
+    collections::{BTreeSet, VecDeque},
+    ops::Bound,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
-use crate::structs::metadata::Metadata;
-use chrono::Utc;
+use anyhow::Result;
+
+use crate::structs::{entry::Entry, metadata::Metadata, stats::ProviderStats};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
-use serde_json;
+use tokio::sync::broadcast;
+use tracing::{debug, error, info};
+
+use super::{
+    CacheProvider, KeyPage, Precondition, ProviderError, ProviderResult, WriteOptions,
+    batch::{BatchError, BatchOperation, BatchOutcome, BatchPlan},
+    changes::{Change, ChangeFeed, ChangeKind},
+    eviction::{EvictionIndex, MemoryLimits, approximate_size},
+    index::{IndexDefinition, IndexRange, Indexes},
+    sync::{ChangePage, Changed, SyncChange, SyncLog, Tombstone},
+    wal::{SnapshotEntry, Wal, WalConfig, WalGuard, WalRecord},
+};
 
-use super::CacheProvider;
+/// File in the write-ahead log directory holding the index definitions.
+const INDEXES_FILE: &str = "indexes.json";
 
 /// A thread-safe, in-memory cache implementation using `DashMap`.
 ///
-/// Stores key-value pairs in memory and keeps metadata (e.g. creation time, version, issuer)
-/// alongside each value.
+/// Every key maps to one [`Entry`] holding the value and its metadata (e.g. creation time,
+/// version, author), so the two are always inserted, read and dropped together.
+///
+/// The approximate serialized size of every entry is tracked. When [`MemoryLimits`] bound the
+/// entry count or byte total, entries are evicted (together with their metadata) following the
+/// configured policy until the cache fits again.
+///
+/// Expiry instants of entries with a TTL are mirrored in `expiries`, so sweeping only visits
+/// entries that can expire.
+///
+/// Keys are also kept in `ordered`, so listings come back sorted and a prefix scan only visits
+/// the keys under that prefix.
+///
+/// Up to [`MemoryLimits::history_depth`] revisions replaced by updates are kept per key in
+/// `history`, oldest first, and are dropped along with the entry.
+///
+/// Secondary `indexes` are kept in step whenever an entry is stored or dropped, and every
+/// mutation is published to `changes` under the lock.
+///
+/// Every mutation takes a sequence number from `sync` under the lock, and removals leave a
+/// tombstone there. Readers of the changes since a sequence number hold the lock shared, so
+/// no number they see belongs to a write that is not applied yet.
+///
+/// Mutations hold `lock` exclusively from the moment they check an entry until they are
+/// applied, so version preconditions cannot race with other writers.
+///
+/// Built with [`MemoryProvider::durable`], every mutation is appended to a write-ahead log
+/// before it is applied, and the log is replayed on startup. Index definitions are saved next
+/// to the log, and indexes are filled again as entries are replayed. Removals are logged with
+/// their tombstone, and the tombstones are saved with every snapshot.
 pub struct MemoryProvider<T: Clone + Serialize + for<'a> Deserialize<'a>> {
-    storage: Arc<DashMap<String, T>>,
-    meta: Arc<DashMap<String, String>>,
+    storage: Arc<DashMap<String, Entry<T>>>,
+    history: Arc<DashMap<String, VecDeque<Entry<T>>>>,
+    expiries: Arc<DashMap<String, DateTime<Utc>>>,
+    ordered: RwLock<BTreeSet<String>>,
+    limits: MemoryLimits,
+    index: Mutex<EvictionIndex>,
+    evictions: AtomicU64,
+    indexes: Indexes,
+    changes: ChangeFeed<T>,
+    sync: SyncLog,
+    lock: RwLock<()>,
+    wal: Option<Wal>,
 }
 
 impl<T: Clone + Serialize + for<'a> Deserialize<'a>> MemoryProvider<T> {
-    /// Creates a new memory cache with a given initial capacity.
-    pub fn new(capacity: usize) -> Self {
+    /// Creates a new memory cache enforcing `limits`.
+    pub fn new(limits: MemoryLimits) -> Self {
+        let capacity = limits.max_entries.unwrap_or_default();
+        let sync = SyncLog::default().with_retention(limits.tombstone_retention);
+
         Self {
             storage: Arc::new(DashMap::with_capacity(capacity)),
-            meta: Arc::new(DashMap::with_capacity(capacity)),
+            history: Arc::new(DashMap::new()),
+            expiries: Arc::new(DashMap::new()),
+            ordered: RwLock::new(BTreeSet::new()),
+            index: Mutex::new(EvictionIndex::new(limits.policy)),
+            limits,
+            evictions: AtomicU64::new(0),
+            indexes: Indexes::default(),
+            changes: ChangeFeed::new(),
+            sync,
+            lock: RwLock::new(()),
+            wal: None,
         }
     }
 
-    /// Internal helper to serialize and store metadata.
-    fn write_metadata(&self, key: &str, metadata: &Metadata) {
-        if let Ok(json) = serde_json::to_string(metadata) {
-            self.meta.insert(Self::meta_key(key), json);
+    /// Creates a memory cache backed by a write-ahead log in `config.dir`.
+    ///
+    /// The last snapshot and any records logged after it are replayed before returning, then a
+    /// fresh snapshot is taken so the log starts out empty.
+    pub fn durable(limits: MemoryLimits, config: WalConfig) -> Result<Self> {
+        let indexes = Indexes::open(Some(config.dir.join(INDEXES_FILE)))?;
+        let (wal, recovered) = Wal::open::<T>(config)?;
+        let mut provider = Self::new(limits);
+        provider.indexes = indexes;
+        provider.sync =
+            SyncLog::restore(recovered.sync).with_retention(provider.limits.tombstone_retention);
+
+        let restored = recovered.snapshot.len();
+        let replayed = recovered.records.len();
+        for entry in recovered.snapshot {
+            if !entry.history.is_empty() {
+                provider
+                    .history
+                    .insert(entry.key.clone(), entry.history.into());
+            }
+            provider.restore(
+                entry.key,
+                Entry {
+                    value: entry.value,
+                    metadata: entry.metadata,
+                },
+                &[],
+            );
+        }
+        for record in recovered.records {
+            provider.replay(record);
+        }
+        info!("restored {restored} entries from snapshot and replayed {replayed} wal records");
+
+        let numbered = provider.number_entries();
+        if numbered > 0 {
+            info!("numbered {numbered} entries written before writes were numbered");
+        }
+
+        // every restored entry went through `store`, so the indexes are complete
+        for name in provider.indexes.building() {
+            provider.indexes.fill(&name, Vec::new());
         }
+
+        if replayed > 0 || numbered > 0 {
+            wal.lock()
+                .snapshot(provider.snapshot_entries(), &provider.sync.saved())?;
+        }
+
+        provider.wal = Some(wal);
+        Ok(provider)
     }
 
-    /// Internal helper to format metadata keys.
-    fn meta_key(key: &str) -> String {
-        format!("{key}$")
+    /// Takes the write-ahead log lock, if the cache is durable.
+    fn lock_wal(&self) -> Option<WalGuard<'_>> {
+        self.wal.as_ref().map(Wal::lock)
     }
-}
 
-impl<T: Clone + Serialize + for<'a> Deserialize<'a>> CacheProvider<T> for MemoryProvider<T> {
-    async fn entry(&self, key: String) -> Option<T> {
-        self.storage.get(&key).map(|entry| entry.value().clone())
+    /// Appends the record built by `record` to the log, if the cache is durable.
+    ///
+    /// When the record could not be written the mutation must not be applied.
+    fn journal(
+        &self,
+        wal: &mut Option<WalGuard<'_>>,
+        record: impl FnOnce() -> WalRecord<T>,
+    ) -> ProviderResult<()> {
+        if let Some(guard) = wal {
+            guard.append(&record())?;
+        }
+
+        Ok(())
     }
 
-    async fn add(&self, key: String, value: T, issuer: String) -> Option<T> {
-        if self.storage.contains_key(&key) {
-            return None;
+    /// Leaves tombstones for evicted entries and logs them, then compacts the log once enough
+    /// records piled up.
+    fn finish_write(&self, wal: &mut Option<WalGuard<'_>>, evicted: Vec<String>) {
+        for key in evicted {
+            let tombstone = Tombstone::new(&key, self.sync.next().sequence(), ChangeKind::Evict);
+            if let Some(guard) = wal
+                && let Err(e) = guard.append(&WalRecord::<T>::Remove {
+                    key: key.clone(),
+                    tombstone: Some(tombstone.clone()),
+                })
+            {
+                error!("failed to append eviction to wal: {e}");
+            }
+
+            self.changes
+                .removed(ChangeKind::Evict, &key, tombstone.sequence);
+            self.sync.bury(tombstone);
         }
 
-        let metadata = Metadata {
-            created_at: Utc::now().to_rfc3339(),
-            version: 0,
-            issuer,
+        let Some(guard) = wal else {
+            return;
         };
+        if guard.should_snapshot()
+            && let Err(e) = guard.snapshot(self.snapshot_entries(), &self.sync.saved())
+        {
+            error!("failed to write snapshot: {e}");
+        }
+    }
 
-        self.write_metadata(&key, &metadata);
-        self.storage.insert(key.clone(), value.clone());
+    /// Every stored entry in key order, in the form written to snapshots.
+    fn snapshot_entries(&self) -> Vec<SnapshotEntry<T>> {
+        self.ordered
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|key| {
+                let entry = self.storage.get(key)?;
 
-        Some(value)
+                Some(SnapshotEntry {
+                    key: key.clone(),
+                    value: entry.value.clone(),
+                    metadata: entry.metadata.clone(),
+                    history: self
+                        .history
+                        .get(key)
+                        .map(|history| history.iter().cloned().collect())
+                        .unwrap_or_default(),
+                })
+            })
+            .collect()
     }
 
-    async fn update(&self, key: String, value: T, issuer: String) -> Option<T> {
-        if !self.storage.contains_key(&key) {
-            return None;
+    /// Stores an entry exactly as given, replacing whatever was there. An update keeps the
+    /// revision it replaces in the history.
+    ///
+    /// Returns the keys evicted to make room for it, never `key` itself or one of `spare`.
+    fn restore(&self, key: String, mut entry: Entry<T>, spare: &[&str]) -> Vec<String> {
+        // entries logged before values were described get their size and digest now
+        if entry.metadata.digest.is_empty()
+            && let Err(e) = entry.metadata.describe(&entry.value)
+        {
+            error!("failed to describe restored entry {key}: {e}");
         }
 
-        if let Some(mut meta_entry) = self.meta.get_mut(&Self::meta_key(&key))
-            && let Ok(mut metadata) = serde_json::from_str::<Metadata>(&meta_entry) {
-                metadata.version += 1;
-                metadata.issuer = issuer;
-                if let Ok(updated) = serde_json::to_string(&metadata) {
-                    *meta_entry = updated;
+        match entry.metadata.expires_at {
+            Some(at) => self.expiries.insert(key.clone(), at),
+            None => self.expiries.remove(&key).map(|(_, at)| at),
+        };
+        let bytes = Self::entry_size(&key, &entry);
+        let version = entry.metadata.version;
+        self.sync.written(&key, entry.metadata.sequence);
+        let previous = self.store(key.clone(), entry);
+        let kept = self.push_history(&key, previous, version, bytes);
+        self.track_write(&key, bytes + kept, spare)
+    }
+
+    /// Keeps `previous` as a past revision of `key` if the entry now at `version` replaced it,
+    /// or forgets the history of `key` if `version` starts a new entry.
+    ///
+    /// The oldest revisions are dropped past the history depth, and while the entry, which
+    /// takes up `bytes` on its own, would not fit the byte budget along with them. Returns the
+    /// bytes taken up by the revisions kept.
+    fn push_history(
+        &self,
+        key: &str,
+        previous: Option<Entry<T>>,
+        version: u64,
+        bytes: usize,
+    ) -> usize {
+        let mut history = self.history.entry(key.to_owned()).or_default();
+        if version == 0 {
+            history.clear();
+        } else if let Some(previous) = previous
+            && previous.metadata.version < version
+        {
+            history.push_back(previous);
+        }
+
+        let mut kept: usize = history.iter().map(Self::revision_size).sum();
+        while history.len() > self.limits.history_depth
+            || self.limits.max_bytes.is_some_and(|max| bytes + kept > max)
+        {
+            let Some(oldest) = history.pop_front() else {
+                break;
+            };
+            kept -= Self::revision_size(&oldest);
+        }
+
+        if history.is_empty() {
+            drop(history);
+            self.history.remove(key);
+        }
+        kept
+    }
+
+    /// Applies a logged mutation.
+    fn replay(&self, record: WalRecord<T>) {
+        match record {
+            WalRecord::Add {
+                key,
+                value,
+                metadata,
+            }
+            | WalRecord::Update {
+                key,
+                value,
+                metadata,
+            } => {
+                self.restore(key, Entry { value, metadata }, &[]);
+            }
+            WalRecord::Remove { key, tombstone } => {
+                self.delete(&key);
+                if let Some(tombstone) = tombstone {
+                    self.sync.bury(tombstone);
                 }
             }
+            WalRecord::Purge { issuer } => {
+                for key in self.keys_issued_by(&issuer) {
+                    self.delete(&key);
+                }
+            }
+            WalRecord::Batch { records } => {
+                for record in records {
+                    self.replay(record);
+                }
+            }
+        }
+    }
+
+    /// Keys of every entry created by `issuer`.
+    fn keys_issued_by(&self, issuer: &str) -> Vec<String> {
+        self.storage
+            .iter()
+            .filter(|entry| entry.metadata.created_by == issuer)
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Approximate number of bytes `entry` takes up under `key`.
+    fn entry_size(key: &str, entry: &Entry<T>) -> usize {
+        key.len() + Self::revision_size(entry)
+    }
+
+    /// Approximate number of bytes a revision takes up in the history.
+    fn revision_size(entry: &Entry<T>) -> usize {
+        entry.metadata.size as usize + approximate_size(&entry.metadata)
+    }
+
+    /// Records a write of `bytes` to `key` and evicts entries if the limits are now exceeded.
+    ///
+    /// `key` and the keys in `spare`, written by the same operation, are never evicted: an
+    /// entry a client was just told is stored must still be there. Returns the evicted keys.
+    fn track_write(&self, key: &str, bytes: usize, spare: &[&str]) -> Vec<String> {
+        let mut index = self.index.lock().unwrap();
+        index.insert(key, bytes);
+
+        let mut evicted = Vec::new();
+        while index.exceeds(&self.limits) {
+            let Some(victim) =
+                index.pop_victim(|candidate| candidate == key || spare.contains(&candidate))
+            else {
+                break;
+            };
+
+            self.unstore(&victim);
+            self.expiries.remove(&victim);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+            debug!("evicted entry {victim} ({} policy)", index.policy());
+            evicted.push(victim);
+        }
+
+        evicted
+    }
+
+    /// Records a read of `key`.
+    fn track_read(&self, key: &str) {
+        self.index.lock().unwrap().access(key);
+    }
+
+    /// Stops tracking `key`.
+    fn untrack(&self, key: &str) {
+        self.index.lock().unwrap().remove(key);
+    }
+
+    /// Whether `key` has a TTL that has already passed.
+    fn is_expired(&self, key: &str) -> bool {
+        self.expiries
+            .get(key)
+            .is_some_and(|at| *at.value() <= Utc::now())
+    }
+
+    /// Whether `key` is stored and has not expired.
+    fn is_live(&self, key: &str) -> bool {
+        self.storage.contains_key(key) && !self.is_expired(key)
+    }
+
+    /// Version of `key` if it is stored and has not expired.
+    fn current_version(&self, key: &str) -> ProviderResult<Option<u64>> {
+        if !self.is_live(key) {
+            return Ok(None);
+        }
+
+        Ok(self.storage.get(key).map(|entry| entry.metadata.version))
+    }
+
+    /// Logs that `key` is removed because of `kind`, then drops it, leaving a tombstone.
+    fn bury(
+        &self,
+        wal: &mut Option<WalGuard<'_>>,
+        key: &str,
+        kind: ChangeKind,
+    ) -> ProviderResult<Option<Entry<T>>> {
+        let tombstone = Tombstone::new(key, self.sync.next().sequence(), kind);
+        self.journal(wal, || WalRecord::Remove {
+            key: key.to_owned(),
+            tombstone: Some(tombstone.clone()),
+        })?;
+
+        let entry = self.delete(key);
+        self.changes.removed(kind, key, tombstone.sequence);
+        self.sync.bury(tombstone);
 
-        self.storage.insert(key.clone(), value.clone());
-        Some(value)
+        Ok(entry)
     }
 
-    async fn metadata(&self, key: String) -> Option<Metadata> {
-        self.meta
-            .get(&Self::meta_key(&key))
-            .and_then(|entry| serde_json::from_str(entry.value()).ok())
+    /// Numbers the entries stored before writes were numbered, returning how many there were.
+    fn number_entries(&self) -> usize {
+        let mut numbered = 0;
+        for key in self.ordered.read().unwrap().iter() {
+            if let Some(mut entry) = self.storage.get_mut(key)
+                && entry.metadata.sequence == 0
+            {
+                entry.metadata.sequence = self.sync.next().sequence();
+                self.sync.written(key, entry.metadata.sequence);
+                numbered += 1;
+            }
+        }
+
+        numbered
     }
 
-    async fn remove(&self, key: String) -> Option<T> {
-        self.storage.remove(&key).map(|(_, value)| value)
+    /// Drops the entry under `key` along with its expiry.
+    fn delete(&self, key: &str) -> Option<Entry<T>> {
+        self.untrack(key);
+        self.expiries.remove(key);
+        self.unstore(key)
     }
 
-    async fn list(&self) -> Vec<(String, T)> {
+    /// Inserts `entry` under `key`, keeping the ordered key set in step.
+    ///
+    /// Returns the entry it replaced.
+    fn store(&self, key: String, entry: Entry<T>) -> Option<Entry<T>> {
+        self.ordered.write().unwrap().insert(key.clone());
+        self.indexes.insert(&key, &entry.value);
+        self.storage.insert(key, entry)
+    }
+
+    /// Removes `key` from storage, its history, the ordered key set and the indexes.
+    fn unstore(&self, key: &str) -> Option<Entry<T>> {
+        self.ordered.write().unwrap().remove(key);
+        self.indexes.remove(key);
+        self.history.remove(key);
+        self.storage.remove(key).map(|(_, entry)| entry)
+    }
+
+    /// Fills the index `definition` describes from the entries stored under its prefix.
+    fn build_index(&self, definition: &IndexDefinition) -> ProviderResult<()> {
+        let ordered = self.ordered.read().unwrap();
+        let entries = ordered
+            .range::<str, _>((
+                Bound::Included(definition.prefix.as_str()),
+                Bound::Unbounded,
+            ))
+            .take_while(|key| key.starts_with(&definition.prefix))
+            .filter_map(|key| {
+                let entry = self.storage.get(key)?;
+                Some(serde_json::to_value(&entry.value).map(|value| (key.clone(), value)))
+            })
+            .collect::<serde_json::Result<Vec<_>>>()?;
+
+        self.indexes.fill(&definition.name, entries);
+        Ok(())
+    }
+
+    /// Fails if `bytes` would not fit the byte budget even with everything else evicted.
+    fn check_fits(&self, bytes: usize) -> ProviderResult<()> {
+        match self.limits.max_bytes {
+            Some(max) if bytes > max => Err(ProviderError::StorageFull),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<T> CacheProvider<T> for MemoryProvider<T>
+where
+    T: Clone + Send + Sync + Serialize + for<'a> Deserialize<'a> + 'static,
+{
+    async fn entry(&self, key: String) -> ProviderResult<Entry<T>> {
+        let _read = self.lock.read().unwrap();
+        if self.is_expired(&key) {
+            return Err(ProviderError::NotFound);
+        }
+
+        let entry = self
+            .storage
+            .get(&key)
+            .map(|entry| entry.value().clone())
+            .ok_or(ProviderError::NotFound)?;
+        self.track_read(&key);
+
+        Ok(entry)
+    }
+
+    async fn add(
+        &self,
+        key: String,
+        value: T,
+        issuer: String,
+        options: WriteOptions,
+    ) -> ProviderResult<u64> {
+        let _write = self.lock.write().unwrap();
+        let mut wal = self.lock_wal();
+        options.precondition.check(self.current_version(&key)?)?;
+        if self.is_live(&key) {
+            return Err(ProviderError::AlreadyExists);
+        }
+
+        let mut metadata = Metadata::new(&value, issuer, options.expires_at())?;
+        metadata.sequence = self.sync.next().sequence();
+        let entry = Entry { value, metadata };
+        self.check_fits(Self::entry_size(&key, &entry))?;
+
+        self.journal(&mut wal, || WalRecord::Add {
+            key: key.clone(),
+            value: entry.value.clone(),
+            metadata: entry.metadata.clone(),
+        })?;
+
+        let version = entry.metadata.version;
+        self.changes
+            .written(ChangeKind::Add, &key, &entry.value, &entry.metadata);
+        let evicted = self.restore(key, entry, &[]);
+        self.finish_write(&mut wal, evicted);
+
+        Ok(version)
+    }
+
+    async fn update(
+        &self,
+        key: String,
+        value: T,
+        issuer: String,
+        options: WriteOptions,
+    ) -> ProviderResult<u64> {
+        let _write = self.lock.write().unwrap();
+        let mut wal = self.lock_wal();
+        options.precondition.check(self.current_version(&key)?)?;
+        if !self.is_live(&key) {
+            return Err(ProviderError::NotFound);
+        }
+
+        let mut metadata = self
+            .storage
+            .get(&key)
+            .map(|entry| entry.metadata.clone())
+            .ok_or(ProviderError::NotFound)?;
+        metadata.update(&value, issuer, options.expires_at())?;
+        metadata.sequence = self.sync.next().sequence();
+
+        let entry = Entry { value, metadata };
+        self.check_fits(Self::entry_size(&key, &entry))?;
+
+        self.journal(&mut wal, || WalRecord::Update {
+            key: key.clone(),
+            value: entry.value.clone(),
+            metadata: entry.metadata.clone(),
+        })?;
+
+        let version = entry.metadata.version;
+        self.changes
+            .written(ChangeKind::Update, &key, &entry.value, &entry.metadata);
+        let evicted = self.restore(key, entry, &[]);
+        self.finish_write(&mut wal, evicted);
+
+        Ok(version)
+    }
+
+    async fn metadata(&self, key: String) -> ProviderResult<Metadata> {
+        let _read = self.lock.read().unwrap();
+        if self.is_expired(&key) {
+            return Err(ProviderError::NotFound);
+        }
+
         self.storage
+            .get(&key)
+            .map(|entry| entry.metadata.clone())
+            .ok_or(ProviderError::NotFound)
+    }
+
+    async fn history(&self, key: String) -> ProviderResult<Vec<Metadata>> {
+        let _read = self.lock.read().unwrap();
+        if self.is_expired(&key) {
+            return Err(ProviderError::NotFound);
+        }
+
+        let current = self
+            .storage
+            .get(&key)
+            .map(|entry| entry.metadata.clone())
+            .ok_or(ProviderError::NotFound)?;
+
+        let mut revisions = vec![current];
+        if let Some(history) = self.history.get(&key) {
+            revisions.extend(history.iter().rev().map(|entry| entry.metadata.clone()));
+        }
+
+        Ok(revisions)
+    }
+
+    async fn revision(&self, key: String, version: u64) -> ProviderResult<Entry<T>> {
+        let _read = self.lock.read().unwrap();
+        if self.is_expired(&key) {
+            return Err(ProviderError::NotFound);
+        }
+
+        let current = self.storage.get(&key).ok_or(ProviderError::NotFound)?;
+        if current.metadata.version == version {
+            return Ok(current.clone());
+        }
+
+        self.history
+            .get(&key)
+            .and_then(|history| {
+                history
+                    .iter()
+                    .find(|entry| entry.metadata.version == version)
+                    .cloned()
+            })
+            .ok_or(ProviderError::RevisionNotFound)
+    }
+
+    async fn remove(&self, key: String, precondition: Precondition) -> ProviderResult<T> {
+        let _write = self.lock.write().unwrap();
+        let mut wal = self.lock_wal();
+        precondition.check(self.current_version(&key)?)?;
+        if !self.storage.contains_key(&key) {
+            return Err(ProviderError::NotFound);
+        }
+
+        let expired = self.is_expired(&key);
+        let kind = match expired {
+            true => ChangeKind::Expire,
+            false => ChangeKind::Remove,
+        };
+        let entry = self.bury(&mut wal, &key, kind)?;
+        self.finish_write(&mut wal, Vec::new());
+
+        match entry {
+            Some(entry) if !expired => Ok(entry.value),
+            _ => Err(ProviderError::NotFound),
+        }
+    }
+
+    async fn list(&self) -> ProviderResult<Vec<(String, T)>> {
+        let _read = self.lock.read().unwrap();
+
+        Ok(self
+            .ordered
+            .read()
+            .unwrap()
             .iter()
-            .map(|entry| (entry.key().clone(), entry.value().clone()))
-            .collect()
+            .filter(|key| !self.is_expired(key))
+            .filter_map(|key| Some((key.clone(), self.storage.get(key)?.value.clone())))
+            .collect())
+    }
+
+    /// Walks the ordered key set from the first key the page can hold, stopping at the end of
+    /// the prefix or once the page is full.
+    async fn list_keys(
+        &self,
+        prefix: String,
+        after: Option<String>,
+        limit: Option<usize>,
+    ) -> ProviderResult<KeyPage> {
+        let _read = self.lock.read().unwrap();
+        let ordered = self.ordered.read().unwrap();
+
+        let start = match after.as_deref() {
+            Some(after) if after >= prefix.as_str() => Bound::Excluded(after),
+            _ => Bound::Included(prefix.as_str()),
+        };
+        let wanted = limit.map_or(usize::MAX, |limit| limit.saturating_add(1));
+
+        let keys = ordered
+            .range::<str, _>((start, Bound::Unbounded))
+            .take_while(|key| key.starts_with(&prefix))
+            .filter(|key| !self.is_expired(key))
+            .take(wanted)
+            .cloned()
+            .collect();
+
+        Ok(KeyPage::from_sorted(keys, limit))
     }
 
-    async fn purge(&self, issuer: String) {
-        let keys_to_remove: Vec<String> = self
-            .meta
+    /// Runs under the write lock, so readers see the cache before or after the whole
+    /// transaction. Its writes are logged as one record.
+    async fn transact(
+        &self,
+        operations: Vec<BatchOperation<T>>,
+        issuer: String,
+    ) -> ProviderResult<Result<Vec<BatchOutcome<T>>, BatchError>> {
+        let _write = self.lock.write().unwrap();
+        let mut wal = self.lock_wal();
+
+        let current = operations
             .iter()
-            .filter_map(|entry| {
-                let meta_json = entry.value();
-                if let Ok(meta) = serde_json::from_str::<Metadata>(meta_json)
-                    && meta.issuer == issuer {
-                        return Some(entry.key().trim_end_matches('$').to_owned());
+            .map(|operation| {
+                let key = operation.key();
+                let entry = self
+                    .is_live(key)
+                    .then(|| self.storage.get(key).map(|entry| entry.clone()))
+                    .flatten();
+                (key.to_owned(), entry)
+            })
+            .collect();
+        let mut plan = match BatchPlan::new(operations, &issuer, current) {
+            Ok(plan) => plan,
+            Err(e) => return Ok(Err(e)),
+        };
+        // every write takes its own number, removals leave a tombstone at theirs
+        let tombstones: Vec<Option<Tombstone>> = plan
+            .writes
+            .iter_mut()
+            .map(|write| {
+                let sequence = self.sync.next().sequence();
+                match &mut write.entry {
+                    Some(entry) => {
+                        entry.metadata.sequence = sequence;
+                        None
                     }
-                None
+                    None => Some(Tombstone::new(&write.key, sequence, ChangeKind::Remove)),
+                }
             })
             .collect();
+        for write in &plan.writes {
+            if let Some(entry) = &write.entry
+                && let Err(error) = self.check_fits(Self::entry_size(&write.key, entry))
+            {
+                return Ok(Err(BatchError {
+                    index: write.index,
+                    error,
+                }));
+            }
+        }
+
+        if !plan.writes.is_empty() {
+            self.journal(&mut wal, || WalRecord::Batch {
+                records: plan
+                    .writes
+                    .iter()
+                    .zip(&tombstones)
+                    .map(|(write, tombstone)| match &write.entry {
+                        Some(entry) if entry.metadata.version == 0 => WalRecord::Add {
+                            key: write.key.clone(),
+                            value: entry.value.clone(),
+                            metadata: entry.metadata.clone(),
+                        },
+                        Some(entry) => WalRecord::Update {
+                            key: write.key.clone(),
+                            value: entry.value.clone(),
+                            metadata: entry.metadata.clone(),
+                        },
+                        None => WalRecord::Remove {
+                            key: write.key.clone(),
+                            tombstone: tombstone.clone(),
+                        },
+                    })
+                    .collect(),
+            })?;
+        }
+
+        let written: Vec<String> = plan.writes.iter().map(|write| write.key.clone()).collect();
+        let spare: Vec<&str> = written.iter().map(String::as_str).collect();
+        let mut evicted = Vec::new();
+        for (write, tombstone) in plan.writes.into_iter().zip(tombstones) {
+            match write.entry {
+                Some(entry) => {
+                    let kind = match entry.metadata.version {
+                        0 => ChangeKind::Add,
+                        _ => ChangeKind::Update,
+                    };
+                    self.changes
+                        .written(kind, &write.key, &entry.value, &entry.metadata);
+                    evicted.extend(self.restore(write.key, entry, &spare));
+                }
+                None => {
+                    self.delete(&write.key);
+                    if let Some(tombstone) = tombstone {
+                        self.changes
+                            .removed(ChangeKind::Remove, &write.key, tombstone.sequence);
+                        self.sync.bury(tombstone);
+                    }
+                }
+            }
+        }
+        self.finish_write(&mut wal, evicted);
+
+        Ok(Ok(plan.outcomes))
+    }
+
+    async fn purge(&self, issuer: String) -> ProviderResult<()> {
+        let _write = self.lock.write().unwrap();
+        let mut wal = self.lock_wal();
+        let tombstones: Vec<Tombstone> = self
+            .keys_issued_by(&issuer)
+            .iter()
+            .map(|key| Tombstone::new(key, self.sync.next().sequence(), ChangeKind::Purge))
+            .collect();
+        if !tombstones.is_empty() {
+            self.journal(&mut wal, || WalRecord::Batch {
+                records: tombstones
+                    .iter()
+                    .map(|tombstone| WalRecord::Remove {
+                        key: tombstone.key.clone(),
+                        tombstone: Some(tombstone.clone()),
+                    })
+                    .collect(),
+            })?;
+        }
 
-        for key in keys_to_remove {
-            self.storage.remove(&key);
-            self.meta.remove(&Self::meta_key(&key));
+        for tombstone in tombstones {
+            self.delete(&tombstone.key);
+            self.changes
+                .removed(ChangeKind::Purge, &tombstone.key, tombstone.sequence);
+            self.sync.bury(tombstone);
         }
+        self.finish_write(&mut wal, Vec::new());
+
+        Ok(())
+    }
+
+    async fn sweep_expired(&self) -> ProviderResult<usize> {
+        let _write = self.lock.write().unwrap();
+        let mut wal = self.lock_wal();
+        let now = Utc::now();
+        let expired: Vec<String> = self
+            .expiries
+            .iter()
+            .filter(|entry| *entry.value() <= now)
+            .map(|entry| entry.key().clone())
+            .collect();
+
+        for key in &expired {
+            self.bury(&mut wal, key, ChangeKind::Expire)?;
+        }
+        self.sync.prune();
+        self.finish_write(&mut wal, Vec::new());
+
+        Ok(expired.len())
+    }
+
+    async fn indexes(&self) -> ProviderResult<Vec<IndexDefinition>> {
+        Ok(self.indexes.definitions())
+    }
+
+    async fn create_index(&self, index: IndexDefinition) -> ProviderResult<()> {
+        let _write = self.lock.write().unwrap();
+        self.indexes.create(index.clone())?;
+
+        self.build_index(&index)
+    }
+
+    async fn rebuild_index(&self, name: String) -> ProviderResult<()> {
+        let _write = self.lock.write().unwrap();
+        let definition = self.indexes.reset(&name)?;
+
+        self.build_index(&definition)
+    }
+
+    async fn drop_index(&self, name: String) -> ProviderResult<()> {
+        self.indexes.drop(&name)
+    }
+
+    async fn lookup(&self, name: String, range: IndexRange) -> ProviderResult<Vec<String>> {
+        self.indexes.lookup(&name, &range)
+    }
+
+    fn changes(&self) -> broadcast::Receiver<Arc<Change<T>>> {
+        self.changes.subscribe()
+    }
+
+    /// Reads the keys the sync log has changed after `after`, in sequence order, until the
+    /// page is full. Expired entries are left for their tombstone.
+    async fn changes_since(
+        &self,
+        prefix: String,
+        after: u64,
+        limit: usize,
+    ) -> ProviderResult<ChangePage<T>> {
+        let _read = self.lock.read().unwrap();
+        self.sync.check_cursor(after)?;
+        let stable = self.sync.stable();
+
+        let mut changes = Vec::new();
+        let mut from = after;
+        while changes.len() <= limit {
+            let changed = self.sync.changed(&prefix, from, stable, limit + 1);
+            let Some(last) = changed.last() else {
+                break;
+            };
+            from = last.sequence();
+
+            for change in changed {
+                match change {
+                    Changed::Removed(tombstone) => changes.push(SyncChange::Removed(tombstone)),
+                    Changed::Written { key, sequence } => {
+                        if let Some(entry) = self.storage.get(&key)
+                            && entry.metadata.sequence == sequence
+                            && !self.is_expired(&key)
+                        {
+                            changes.push(SyncChange::Written {
+                                key,
+                                entry: entry.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(ChangePage::from_changes(changes, limit, stable))
+    }
+
+    async fn stats(&self) -> ProviderStats {
+        let index = self.index.lock().unwrap();
+
+        ProviderStats {
+            entries: self.storage.len(),
+            max_entries: self.limits.max_entries,
+            bytes: Some(index.bytes()),
+            max_bytes: self.limits.max_bytes,
+            eviction_policy: Some(index.policy().to_string()),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::providers::{
+        VersionMatch,
+        batch::{BatchOperation, BatchOutcome},
+        changes::ChangeKind,
+        eviction::EvictionPolicy,
+        index::IndexValue,
+    };
+
+    #[tokio::test]
+    async fn evicts_least_recently_used() {
+        let cache = MemoryProvider::<Value>::new(MemoryLimits {
+            max_entries: Some(2),
+            ..Default::default()
+        });
+
+        cache
+            .add("a".into(), json!(1), "t".into(), Default::default())
+            .await
+            .unwrap();
+        cache
+            .add("b".into(), json!(2), "t".into(), Default::default())
+            .await
+            .unwrap();
+        // reading `a` makes `b` the least recently used entry
+        cache.entry("a".into()).await.unwrap();
+        cache
+            .add("c".into(), json!(3), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        assert_eq!(cache.entry("a".into()).await.unwrap().value, json!(1));
+        assert!(cache.entry("b".into()).await.is_err());
+        assert!(cache.metadata("b".into()).await.is_err());
+        assert_eq!(cache.entry("c".into()).await.unwrap().value, json!(3));
+
+        let stats = cache.stats().await;
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.evictions, 1);
+    }
+
+    #[tokio::test]
+    async fn never_evicts_what_it_just_wrote() {
+        let cache = MemoryProvider::<Value>::new(MemoryLimits {
+            max_entries: Some(2),
+            policy: EvictionPolicy::Lfu,
+            ..Default::default()
+        });
+
+        for key in ["a", "b"] {
+            cache
+                .add(key.into(), json!(1), "t".into(), Default::default())
+                .await
+                .unwrap();
+            cache.entry(key.into()).await.unwrap();
+        }
+        // never read, so ranked below both, yet it is the entry that was just written
+        cache
+            .add("c".into(), json!(2), "t".into(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(cache.entry("c".into()).await.unwrap().value, json!(2));
+
+        let operations = ["d", "e"]
+            .map(|key| BatchOperation::Add {
+                key: key.into(),
+                value: json!(3),
+                options: Default::default(),
+            })
+            .to_vec();
+        cache
+            .transact(operations, "t".into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(cache.entry("d".into()).await.is_ok());
+        assert!(cache.entry("e".into()).await.is_ok());
+        assert_eq!(cache.stats().await.evictions, 3);
+    }
+
+    #[tokio::test]
+    async fn enforces_byte_budget() {
+        let cache = MemoryProvider::<Value>::new(MemoryLimits {
+            max_bytes: Some(600),
+            ..Default::default()
+        });
+
+        cache
+            .add("small".into(), json!({}), "t".into(), Default::default())
+            .await
+            .unwrap();
+        cache
+            .add(
+                "big".into(),
+                json!("x".repeat(300)),
+                "t".into(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+
+        assert!(cache.entry("small".into()).await.is_err());
+        assert!(cache.stats().await.bytes.unwrap() <= 600);
+
+        let huge = cache
+            .add(
+                "huge".into(),
+                json!("x".repeat(500)),
+                "t".into(),
+                Default::default(),
+            )
+            .await;
+        assert!(matches!(huge, Err(ProviderError::StorageFull)));
+        assert_eq!(
+            cache.entry("big".into()).await.unwrap().value,
+            json!("x".repeat(300))
+        );
+    }
+
+    #[tokio::test]
+    async fn hides_and_sweeps_expired_entries() {
+        let cache = MemoryProvider::<Value>::new(Default::default());
+        let expired = WriteOptions {
+            ttl: Some(TimeDelta::seconds(-1)),
+            ..Default::default()
+        };
+
+        cache
+            .add("gone".into(), json!(1), "t".into(), expired)
+            .await
+            .unwrap();
+        cache
+            .add("kept".into(), json!(2), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        assert!(cache.entry("gone".into()).await.is_err());
+        assert!(cache.metadata("gone".into()).await.is_err());
+        assert_eq!(cache.list().await.unwrap().len(), 1);
+
+        assert_eq!(cache.sweep_expired().await.unwrap(), 1);
+        assert_eq!(cache.stats().await.entries, 1);
+    }
+
+    #[tokio::test]
+    async fn removes_value_and_metadata_together() {
+        let cache = MemoryProvider::<Value>::new(Default::default());
+
+        cache
+            .add("a".into(), json!(1), "t".into(), Default::default())
+            .await
+            .unwrap();
+        let entry = cache.entry("a".into()).await.unwrap();
+        assert_eq!(entry.metadata, cache.metadata("a".into()).await.unwrap());
+
+        cache.remove("a".into(), Default::default()).await.unwrap();
+        assert!(matches!(
+            cache.metadata("a".into()).await,
+            Err(ProviderError::NotFound)
+        ));
+        assert_eq!(cache.stats().await.bytes, Some(0));
+    }
+
+    #[tokio::test]
+    async fn keeps_bounded_history_and_rolls_back() {
+        let cache = MemoryProvider::<Value>::new(MemoryLimits {
+            history_depth: 2,
+            ..Default::default()
+        });
+
+        cache
+            .add("a".into(), json!(0), "t".into(), Default::default())
+            .await
+            .unwrap();
+        for i in 1..=3 {
+            cache
+                .update("a".into(), json!(i), "t".into(), Default::default())
+                .await
+                .unwrap();
+        }
+
+        let versions: Vec<_> = cache
+            .history("a".into())
+            .await
+            .unwrap()
+            .iter()
+            .map(|metadata| metadata.version)
+            .collect();
+        assert_eq!(versions, vec![3, 2, 1]);
+        assert_eq!(cache.revision("a".into(), 1).await.unwrap().value, json!(1));
+        assert!(matches!(
+            cache.revision("a".into(), 0).await,
+            Err(ProviderError::RevisionNotFound)
+        ));
+
+        let stale = Precondition {
+            if_match: Some(VersionMatch::Versions(vec![2])),
+            ..Default::default()
+        };
+        assert!(matches!(
+            cache.rollback("a".into(), 1, "u".into(), stale).await,
+            Err(ProviderError::PreconditionFailed)
+        ));
+
+        let version = cache
+            .rollback("a".into(), 1, "u".into(), Default::default())
+            .await
+            .unwrap();
+        let entry = cache.entry("a".into()).await.unwrap();
+        assert_eq!((version, entry.value), (4, json!(1)));
+        assert_eq!(entry.metadata.updated_by, "u");
+        assert_eq!(cache.history("a".into()).await.unwrap().len(), 3);
+
+        cache.remove("a".into(), Default::default()).await.unwrap();
+        cache
+            .add("a".into(), json!(5), "t".into(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(cache.history("a".into()).await.unwrap().len(), 1);
+        assert_eq!(
+            cache.stats().await.bytes,
+            Some(MemoryProvider::entry_size(
+                "a",
+                &cache.entry("a".into()).await.unwrap()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_conditional_updates_have_one_winner() {
+        let cache = Arc::new(MemoryProvider::<Value>::new(Default::default()));
+        cache
+            .add("a".into(), json!(0), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        let writers: Vec<_> = (1..=8)
+            .map(|i| {
+                let cache = cache.clone();
+                tokio::spawn(async move {
+                    let options = WriteOptions {
+                        precondition: Precondition {
+                            if_match: Some(VersionMatch::Versions(vec![0])),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    };
+                    cache
+                        .update("a".into(), json!(i), "t".into(), options)
+                        .await
+                })
+            })
+            .collect();
+
+        let mut won = 0;
+        for writer in writers {
+            match writer.await.unwrap() {
+                Ok(version) => {
+                    assert_eq!(version, 1);
+                    won += 1;
+                }
+                Err(e) => assert!(matches!(e, ProviderError::PreconditionFailed)),
+            }
+        }
+        assert_eq!(won, 1);
+
+        let stale = Precondition {
+            if_match: Some(VersionMatch::Versions(vec![0])),
+            ..Default::default()
+        };
+        assert!(matches!(
+            cache.remove("a".into(), stale).await,
+            Err(ProviderError::PreconditionFailed)
+        ));
+        assert_eq!(cache.entry("a".into()).await.unwrap().metadata.version, 1);
+    }
+
+    #[tokio::test]
+    async fn pages_skip_expired_keys() {
+        let cache = MemoryProvider::<Value>::new(Default::default());
+        let expired = WriteOptions {
+            ttl: Some(TimeDelta::seconds(-1)),
+            ..Default::default()
+        };
+        for key in ["p:b", "p:a", "p:c"] {
+            cache
+                .add(key.into(), json!(1), "t".into(), Default::default())
+                .await
+                .unwrap();
+        }
+        cache
+            .add("p:aa".into(), json!(1), "t".into(), expired)
+            .await
+            .unwrap();
+
+        let page = cache.list_keys("p:".into(), None, Some(2)).await.unwrap();
+        assert_eq!(page.keys, vec!["p:a", "p:b"]);
+
+        let page = cache
+            .list_keys("p:".into(), page.next, Some(2))
+            .await
+            .unwrap();
+        assert_eq!(
+            page,
+            KeyPage {
+                keys: vec!["p:c".into()],
+                next: None
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn transactions_apply_all_or_nothing() {
+        let cache = MemoryProvider::<Value>::new(Default::default());
+        cache
+            .add("a".into(), json!(1), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        let failed = cache
+            .transact(
+                vec![
+                    BatchOperation::Update {
+                        key: "a".into(),
+                        value: json!(2),
+                        options: Default::default(),
+                    },
+                    BatchOperation::Add {
+                        key: "b".into(),
+                        value: json!(3),
+                        options: Default::default(),
+                    },
+                    BatchOperation::Remove {
+                        key: "missing".into(),
+                        precondition: Default::default(),
+                    },
+                ],
+                "t".into(),
+            )
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(failed.index, 2);
+        assert_eq!(cache.entry("a".into()).await.unwrap().value, json!(1));
+        assert!(cache.entry("b".into()).await.is_err());
+
+        let outcomes = cache
+            .transact(
+                vec![
+                    BatchOperation::Update {
+                        key: "a".into(),
+                        value: json!(2),
+                        options: Default::default(),
+                    },
+                    BatchOperation::Add {
+                        key: "b".into(),
+                        value: json!(3),
+                        options: Default::default(),
+                    },
+                    BatchOperation::Get { key: "a".into() },
+                ],
+                "t".into(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            outcomes[..2],
+            [BatchOutcome::Written(1), BatchOutcome::Written(0)]
+        );
+        assert_eq!(cache.entry("b".into()).await.unwrap().value, json!(3));
+        assert_eq!(cache.history("a".into()).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn publishes_every_change() {
+        let cache = MemoryProvider::<Value>::new(MemoryLimits {
+            max_entries: Some(1),
+            ..Default::default()
+        });
+        let mut changes = cache.changes();
+
+        cache
+            .add("a".into(), json!(1), "t".into(), Default::default())
+            .await
+            .unwrap();
+        cache
+            .update("a".into(), json!(2), "t".into(), Default::default())
+            .await
+            .unwrap();
+        cache
+            .add("b".into(), json!(3), "t".into(), Default::default())
+            .await
+            .unwrap();
+        cache.purge("t".into()).await.unwrap();
+
+        let mut seen = Vec::new();
+        while let Ok(change) = changes.try_recv() {
+            seen.push((change.kind, change.key.clone(), change.value.clone()));
+        }
+        assert_eq!(
+            seen,
+            vec![
+                (ChangeKind::Add, "a".into(), Some(json!(1))),
+                (ChangeKind::Update, "a".into(), Some(json!(2))),
+                (ChangeKind::Add, "b".into(), Some(json!(3))),
+                (ChangeKind::Evict, "a".into(), None),
+                (ChangeKind::Purge, "b".into(), None),
+            ]
+        );
+    }
+
+    /// Sequence number, key and whether it was removed, for every change on `page`.
+    fn synced(page: &ChangePage<Value>) -> Vec<(u64, &str, bool)> {
+        page.changes
+            .iter()
+            .map(|change| match change {
+                SyncChange::Written { key, entry } => {
+                    (entry.metadata.sequence, key.as_str(), false)
+                }
+                SyncChange::Removed(tombstone) => {
+                    (tombstone.sequence, tombstone.key.as_str(), true)
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn syncs_changes_since_a_sequence() {
+        let cache = MemoryProvider::<Value>::new(Default::default());
+        for key in ["p:a", "p:b", "q:c"] {
+            cache
+                .add(key.into(), json!(1), "t".into(), Default::default())
+                .await
+                .unwrap();
+        }
+        cache
+            .update("p:a".into(), json!(2), "t".into(), Default::default())
+            .await
+            .unwrap();
+        cache
+            .remove("p:b".into(), Default::default())
+            .await
+            .unwrap();
+
+        let page = cache.changes_since("p:".into(), 0, 10).await.unwrap();
+        assert_eq!(synced(&page), [(4, "p:a", false), (5, "p:b", true)]);
+        assert_eq!((page.cursor, page.more), (5, false));
+
+        let page = cache.changes_since("p:".into(), 0, 1).await.unwrap();
+        assert_eq!((page.cursor, page.more), (4, true));
+        let page = cache.changes_since("p:".into(), 4, 1).await.unwrap();
+        assert_eq!(synced(&page), [(5, "p:b", true)]);
+
+        cache
+            .add("p:b".into(), json!(3), "t".into(), Default::default())
+            .await
+            .unwrap();
+        let page = cache.changes_since("p:".into(), 5, 10).await.unwrap();
+        assert_eq!(synced(&page), [(6, "p:b", false)]);
+        assert_eq!(page.cursor, 6);
+
+        assert!(matches!(
+            cache.changes_since("p:".into(), 7, 10).await,
+            Err(ProviderError::StaleCursor)
+        ));
+
+        // an expired entry waiting for the sweeper is skipped without cutting the page short
+        let expired = WriteOptions {
+            ttl: Some(TimeDelta::seconds(-1)),
+            ..Default::default()
+        };
+        cache
+            .add("p:x".into(), json!(4), "t".into(), expired)
+            .await
+            .unwrap();
+        cache
+            .add("p:y".into(), json!(5), "t".into(), Default::default())
+            .await
+            .unwrap();
+        let page = cache.changes_since("p:".into(), 6, 1).await.unwrap();
+        assert_eq!(synced(&page), [(8, "p:y", false)]);
+        assert_eq!((page.cursor, page.more), (8, false));
+    }
+
+    fn stars_index() -> IndexDefinition {
+        IndexDefinition {
+            name: "stars".into(),
+            prefix: "p:".into(),
+            pointer: "/stars".into(),
+        }
+    }
+
+    async fn starred(cache: &MemoryProvider<Value>, stars: u64) -> Vec<String> {
+        let range = IndexRange::eq(IndexValue::from_json(&json!(stars)).unwrap());
+        cache.lookup("stars".into(), range).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn maintains_indexes_through_writes() {
+        let cache = MemoryProvider::<Value>::new(MemoryLimits {
+            max_entries: Some(3),
+            ..Default::default()
+        });
+        cache
+            .add(
+                "p:a".into(),
+                json!({"stars": 1}),
+                "t".into(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        cache.create_index(stars_index()).await.unwrap();
+        assert!(matches!(
+            cache.create_index(stars_index()).await,
+            Err(ProviderError::IndexExists)
+        ));
+        assert_eq!(starred(&cache, 1).await, vec!["p:a"]);
+
+        cache
+            .add(
+                "p:b".into(),
+                json!({"stars": 1}),
+                "u".into(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        cache
+            .add(
+                "q:c".into(),
+                json!({"stars": 1}),
+                "t".into(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(starred(&cache, 1).await, vec!["p:a", "p:b"]);
+
+        cache
+            .update(
+                "p:a".into(),
+                json!({"stars": 2}),
+                "t".into(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(starred(&cache, 1).await, vec!["p:b"]);
+        assert_eq!(starred(&cache, 2).await, vec!["p:a"]);
+
+        cache.purge("u".into()).await.unwrap();
+        assert!(starred(&cache, 1).await.is_empty());
+
+        // reading `q:c` makes `p:a` the least recently used entry
+        cache.entry("q:c".into()).await.unwrap();
+        cache
+            .add(
+                "p:d".into(),
+                json!({"stars": 2}),
+                "t".into(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        cache
+            .add(
+                "p:e".into(),
+                json!({"stars": 2}),
+                "t".into(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(starred(&cache, 2).await, vec!["p:d", "p:e"]);
+
+        cache
+            .remove("p:d".into(), Default::default())
+            .await
+            .unwrap();
+        let range = IndexRange {
+            lower: Bound::Excluded(IndexValue::from_json(&json!(1)).unwrap()),
+            upper: Bound::Unbounded,
+        };
+        assert_eq!(
+            cache.lookup("stars".into(), range).await.unwrap(),
+            vec!["p:e"]
+        );
+
+        cache.drop_index("stars".into()).await.unwrap();
+        assert!(matches!(
+            cache
+                .lookup("stars".into(), IndexRange::eq(IndexValue::Null))
+                .await,
+            Err(ProviderError::IndexNotFound)
+        ));
+    }
+
+    fn wal_config(name: &str, snapshot_every: usize) -> WalConfig {
+        let dir = std::env::temp_dir().join(format!("objekt-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        WalConfig {
+            dir,
+            snapshot_every,
+            fsync: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn durable_cache_survives_restart() {
+        let config = wal_config("restart", 1000);
+
+        {
+            let cache =
+                MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+            cache
+                .add("a".into(), json!(1), "t".into(), Default::default())
+                .await
+                .unwrap();
+            cache
+                .add("b".into(), json!(2), "u".into(), Default::default())
+                .await
+                .unwrap();
+            cache
+                .update("a".into(), json!(3), "t".into(), Default::default())
+                .await
+                .unwrap();
+            cache
+                .add("c".into(), json!(4), "t".into(), Default::default())
+                .await
+                .unwrap();
+            cache.remove("c".into(), Default::default()).await.unwrap();
+            cache.purge("u".into()).await.unwrap();
+        }
+
+        let cache = MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+        assert_eq!(cache.entry("a".into()).await.unwrap().value, json!(3));
+        assert_eq!(cache.metadata("a".into()).await.unwrap().version, 1);
+        assert!(cache.entry("b".into()).await.is_err());
+        assert!(cache.entry("c".into()).await.is_err());
+
+        let _ = std::fs::remove_dir_all(config.dir);
+    }
+
+    #[tokio::test]
+    async fn durable_cache_keeps_sequence_and_tombstones() {
+        let config = wal_config("sync", 2);
+
+        {
+            let cache =
+                MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+            for key in ["a", "b", "c"] {
+                cache
+                    .add(key.into(), json!(1), "t".into(), Default::default())
+                    .await
+                    .unwrap();
+            }
+            cache.remove("a".into(), Default::default()).await.unwrap();
+            cache.remove("c".into(), Default::default()).await.unwrap();
+        }
+
+        let cache = MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+        let page = cache.changes_since(String::new(), 0, 10).await.unwrap();
+        assert_eq!(
+            synced(&page),
+            [(2, "b", false), (4, "a", true), (5, "c", true)]
+        );
+        assert_eq!(page.cursor, 5);
+
+        let version = cache
+            .add("a".into(), json!(2), "t".into(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(cache.metadata("a".into()).await.unwrap().sequence, 6);
+
+        let _ = std::fs::remove_dir_all(config.dir);
+    }
+
+    #[tokio::test]
+    async fn durable_cache_keeps_history() {
+        let config = wal_config("history", 2);
+        let limits = MemoryLimits {
+            history_depth: 5,
+            ..Default::default()
+        };
+
+        {
+            let cache = MemoryProvider::<Value>::durable(limits.clone(), config.clone()).unwrap();
+            cache
+                .add("a".into(), json!(0), "t".into(), Default::default())
+                .await
+                .unwrap();
+            for i in 1..=3 {
+                cache
+                    .update("a".into(), json!(i), "t".into(), Default::default())
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let cache = MemoryProvider::<Value>::durable(limits, config.clone()).unwrap();
+        assert_eq!(cache.history("a".into()).await.unwrap().len(), 4);
+        assert_eq!(cache.revision("a".into(), 0).await.unwrap().value, json!(0));
+
+        let _ = std::fs::remove_dir_all(config.dir);
+    }
+
+    #[tokio::test]
+    async fn durable_cache_keeps_index_definitions() {
+        let config = wal_config("indexes", 2);
+
+        {
+            let cache =
+                MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+            cache.create_index(stars_index()).await.unwrap();
+            for (key, stars) in [("p:a", 1), ("p:b", 2), ("p:c", 1)] {
+                cache
+                    .add(
+                        key.into(),
+                        json!({"stars": stars}),
+                        "t".into(),
+                        Default::default(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            cache
+                .remove("p:c".into(), Default::default())
+                .await
+                .unwrap();
+        }
+
+        let cache = MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+        assert_eq!(cache.indexes().await.unwrap(), vec![stars_index()]);
+        assert_eq!(starred(&cache, 1).await, vec!["p:a"]);
+        assert_eq!(starred(&cache, 2).await, vec!["p:b"]);
+
+        let _ = std::fs::remove_dir_all(config.dir);
+    }
+
+    #[tokio::test]
+    async fn durable_cache_replays_transactions() {
+        let config = wal_config("batch", 1000);
+
+        {
+            let cache =
+                MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+            cache
+                .add("a".into(), json!(1), "t".into(), Default::default())
+                .await
+                .unwrap();
+            cache
+                .transact(
+                    vec![
+                        BatchOperation::Remove {
+                            key: "a".into(),
+                            precondition: Default::default(),
+                        },
+                        BatchOperation::Add {
+                            key: "b".into(),
+                            value: json!(2),
+                            options: Default::default(),
+                        },
+                        BatchOperation::Update {
+                            key: "b".into(),
+                            value: json!(3),
+                            options: Default::default(),
+                        },
+                    ],
+                    "t".into(),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+        }
+
+        let log = std::fs::read_to_string(config.dir.join("wal.log")).unwrap();
+        assert_eq!(log.lines().count(), 2);
+
+        let cache = MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+        assert!(cache.entry("a".into()).await.is_err());
+        let b = cache.entry("b".into()).await.unwrap();
+        assert_eq!((b.value, b.metadata.version), (json!(3), 1));
+
+        let _ = std::fs::remove_dir_all(config.dir);
+    }
+
+    #[tokio::test]
+    async fn durable_cache_recovers_from_torn_tail() {
+        let config = wal_config("torn", 1000);
+
+        {
+            let cache =
+                MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+            cache
+                .add("a".into(), json!(1), "t".into(), Default::default())
+                .await
+                .unwrap();
+            cache
+                .add("b".into(), json!(2), "t".into(), Default::default())
+                .await
+                .unwrap();
+        }
+
+        // simulate a crash halfway through appending a record
+        let log = config.dir.join("wal.log");
+        let mut data = std::fs::read(&log).unwrap();
+        data.extend_from_slice(br#"{"op":"add","key":"c","value":"#);
+        std::fs::write(&log, data).unwrap();
+
+        {
+            let cache =
+                MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+            assert_eq!(cache.entry("a".into()).await.unwrap().value, json!(1));
+            assert_eq!(cache.entry("b".into()).await.unwrap().value, json!(2));
+            assert!(cache.entry("c".into()).await.is_err());
+
+            cache
+                .add("d".into(), json!(4), "t".into(), Default::default())
+                .await
+                .unwrap();
+        }
+
+        let cache = MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+        assert_eq!(cache.list().await.unwrap().len(), 3);
+        assert_eq!(cache.entry("d".into()).await.unwrap().value, json!(4));
+
+        let _ = std::fs::remove_dir_all(config.dir);
+    }
+
+    #[tokio::test]
+    async fn lists_in_key_order_across_restarts() {
+        let config = wal_config("ordered", 3);
+        let keys = ["m", "b:2", "z", "b:10", "a", "b:1"];
+
+        {
+            let cache =
+                MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+            for key in keys {
+                cache
+                    .add(key.into(), json!(key), "t".into(), Default::default())
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let cache = MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+        let listed: Vec<String> = cache
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(listed, vec!["a", "b:1", "b:10", "b:2", "m", "z"]);
+
+        let page = cache
+            .list_keys("b:".into(), Some("a".into()), None)
+            .await
+            .unwrap();
+        assert_eq!(page.keys, vec!["b:1", "b:10", "b:2"]);
+
+        let _ = std::fs::remove_dir_all(config.dir);
+    }
+
+    #[tokio::test]
+    async fn durable_cache_compacts_into_snapshots() {
+        let config = wal_config("snapshot", 2);
+
+        {
+            let cache =
+                MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+            for i in 0..5 {
+                cache
+                    .add(format!("k{i}"), json!(i), "t".into(), Default::default())
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let log = std::fs::read_to_string(config.dir.join("wal.log")).unwrap();
+        assert!(log.lines().count() < 2);
+
+        let cache = MemoryProvider::<Value>::durable(Default::default(), config.clone()).unwrap();
+        assert_eq!(cache.list().await.unwrap().len(), 5);
+
+        let _ = std::fs::remove_dir_all(config.dir);
     }
 }
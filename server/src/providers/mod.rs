@@ -1,43 +1,688 @@
-use crate::structs::metadata::Metadata;
+use std::sync::Arc;
 
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::structs::{
+    counter::{Increment, IncrementError},
+    entry::Entry,
+    metadata::Metadata,
+    stats::ProviderStats,
+};
+use batch::{BatchError, BatchOperation, BatchOutcome};
+use changes::Change;
+use index::{IndexDefinition, IndexRange};
+use sync::ChangePage;
+
+pub use error::{ProviderError, ProviderResult};
+
+pub mod batch;
+pub mod changes;
+pub mod error;
+#[cfg(feature = "memory")]
+pub mod eviction;
+pub mod filename;
 pub mod fs;
+pub mod index;
+pub mod locks;
 #[cfg(feature = "memory")]
 pub mod memory;
+pub mod registry;
+pub mod sweeper;
+pub mod sync;
+#[cfg(feature = "memory")]
+pub mod wal;
+
+/// Options accepted by [`CacheProvider::add`] and [`CacheProvider::update`].
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// How long the entry lives. Updates without a TTL keep the current expiry.
+    pub ttl: Option<TimeDelta>,
+    /// Checked against the current version in the same step as the write.
+    pub precondition: Precondition,
+}
+
+/// A set of entry versions, as listed in an `If-Match` or `If-None-Match` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionMatch {
+    /// `*`: any version, as long as the entry exists.
+    Any,
+    /// One of these versions.
+    Versions(Vec<u64>),
+}
+
+impl VersionMatch {
+    /// Whether an entry at `current` (`None` if it does not exist) is in the set.
+    pub fn matches(&self, current: Option<u64>) -> bool {
+        match (self, current) {
+            (_, None) => false,
+            (Self::Any, Some(_)) => true,
+            (Self::Versions(versions), Some(version)) => versions.contains(&version),
+        }
+    }
+}
+
+/// Conditions on the current version of an entry that a write requires.
+///
+/// Providers check it while holding whatever lock guards the write itself, so nothing can
+/// change the entry in between.
+#[derive(Debug, Clone, Default)]
+pub struct Precondition {
+    /// The entry must exist with a version in this set.
+    pub if_match: Option<VersionMatch>,
+    /// The entry must not exist with a version in this set.
+    pub if_none_match: Option<VersionMatch>,
+}
+
+impl Precondition {
+    /// Fails with [`ProviderError::PreconditionFailed`] unless an entry at `current` (`None` if
+    /// it does not exist) satisfies the condition.
+    pub fn check(&self, current: Option<u64>) -> ProviderResult<()> {
+        if let Some(expected) = &self.if_match
+            && !expected.matches(current)
+        {
+            return Err(ProviderError::PreconditionFailed);
+        }
+        if let Some(unexpected) = &self.if_none_match
+            && unexpected.matches(current)
+        {
+            return Err(ProviderError::PreconditionFailed);
+        }
+
+        Ok(())
+    }
+}
+
+impl WriteOptions {
+    /// The instant the entry expires at, if a TTL was given.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.ttl.map(|ttl| Utc::now() + ttl)
+    }
+}
+
+/// One page of keys returned by [`CacheProvider::list_keys`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyPage {
+    /// Matching keys in ascending byte order.
+    pub keys: Vec<String>,
+    /// Last key of this page if more keys follow; pass it as `after` to get the next page.
+    pub next: Option<String>,
+}
+
+impl KeyPage {
+    /// Builds a page from `keys` in any order, keeping those that start with `prefix` and sort
+    /// after `after`.
+    pub fn from_keys(
+        keys: impl IntoIterator<Item = String>,
+        prefix: &str,
+        after: Option<&str>,
+        limit: Option<usize>,
+    ) -> Self {
+        let mut keys: Vec<String> = keys
+            .into_iter()
+            .filter(|key| key.starts_with(prefix) && after.is_none_or(|after| key.as_str() > after))
+            .collect();
+        keys.sort_unstable();
+
+        Self::from_sorted(keys, limit)
+    }
+
+    /// Cuts already sorted `keys` down to `limit`, noting where the next page starts.
+    ///
+    /// `keys` may hold one extra key beyond the limit; it only signals that more follow.
+    pub fn from_sorted(mut keys: Vec<String>, limit: Option<usize>) -> Self {
+        let next = match limit {
+            Some(limit) if keys.len() > limit => {
+                keys.truncate(limit);
+                keys.last().cloned()
+            }
+            _ => None,
+        };
+
+        Self { keys, next }
+    }
+}
 
 /// A trait that defines how a cache backend should behave.
 ///
 /// This is generic over the type of value you're caching (`T`),
 /// which must implement `Clone`.
-pub trait CacheProvider<T: Clone> {
-    /// Looks up a value by key.
+///
+/// The trait is object safe and every returned future is `Send`, so a backend can be
+/// stored as `Arc<dyn CacheProvider<T>>` and its calls moved into `tokio::spawn`.
+#[async_trait]
+pub trait CacheProvider<T: Clone + Send + 'static>: Send + Sync {
+    /// Looks up a value by key, along with its metadata. Both are read as one record.
     ///
-    /// Returns `Some(value)` if the key exists, or `None` otherwise.
-    async fn entry(&self, key: String) -> Option<T>;
+    /// Fails with [`ProviderError::NotFound`] if the key does not exist or has expired.
+    async fn entry(&self, key: String) -> ProviderResult<Entry<T>>;
 
     /// Attempts to add a new entry to the cache.
     ///
-    /// If the key already exists, this returns `None` and does not overwrite the value.
-    /// Otherwise, returns `Some(value)` after inserting it.
-    async fn add(&self, key: String, value: T, issuer: String) -> Option<T>;
+    /// If the key already exists, this fails with [`ProviderError::AlreadyExists`] and does
+    /// not overwrite the value. Otherwise, returns the version of the new entry.
+    /// An expired entry counts as missing and is replaced.
+    async fn add(
+        &self,
+        key: String,
+        value: T,
+        issuer: String,
+        options: WriteOptions,
+    ) -> ProviderResult<u64>;
 
-    /// Removes an entry from the cache.
-    async fn remove(&self, key: String) -> Option<T>;
+    /// Removes an entry from the cache, returning its value.
+    ///
+    /// Fails with [`ProviderError::NotFound`] if the key does not exist or has expired, and
+    /// with [`ProviderError::PreconditionFailed`] if it does not satisfy `precondition`.
+    async fn remove(&self, key: String, precondition: Precondition) -> ProviderResult<T>;
 
-    /// Lists all keys and values currently stored in the cache.
-    async fn list(&self) -> Vec<(String, T)>;
+    /// Lists all keys and values currently stored in the cache, skipping expired ones.
+    async fn list(&self) -> ProviderResult<Vec<(String, T)>>;
+
+    /// Lists up to `limit` keys starting with `prefix` that sort after `after`, in ascending
+    /// order, skipping expired ones.
+    ///
+    /// Paging by the last key seen stays stable while entries are added or removed; one key
+    /// past the limit tells whether another page follows, see [`KeyPage::from_sorted`]. The
+    /// default implementation filters [`CacheProvider::list`]; backends should override it
+    /// with something that does not load every value.
+    async fn list_keys(
+        &self,
+        prefix: String,
+        after: Option<String>,
+        limit: Option<usize>,
+    ) -> ProviderResult<KeyPage> {
+        let keys = self.list().await?.into_iter().map(|(key, _)| key);
+        Ok(KeyPage::from_keys(keys, &prefix, after.as_deref(), limit))
+    }
 
     /// Retrieves metadata for a given key.
     ///
-    /// Returns `Some(metadata)` if the key exists and has metadata.
-    /// Returns `None` if the key does not exist.
-    async fn metadata(&self, key: String) -> Option<Metadata>;
+    /// Fails with [`ProviderError::NotFound`] if the key does not exist or has expired.
+    async fn metadata(&self, key: String) -> ProviderResult<Metadata>;
+
+    /// Lists the metadata of every kept revision of `key`, newest first, starting with the
+    /// current one.
+    ///
+    /// Fails with [`ProviderError::NotFound`] if the key does not exist or has expired. The
+    /// default implementation keeps no history and only reports the current revision.
+    async fn history(&self, key: String) -> ProviderResult<Vec<Metadata>> {
+        Ok(vec![self.metadata(key).await?])
+    }
+
+    /// Reads revision `version` of `key`, which may be the current one.
+    ///
+    /// Fails with [`ProviderError::NotFound`] if the key does not exist or has expired, and
+    /// with [`ProviderError::RevisionNotFound`] if that revision is no longer kept.
+    async fn revision(&self, key: String, version: u64) -> ProviderResult<Entry<T>> {
+        let entry = self.entry(key).await?;
+
+        match entry.metadata.version == version {
+            true => Ok(entry),
+            false => Err(ProviderError::RevisionNotFound),
+        }
+    }
+
+    /// Writes the value of revision `version` of `key` again as a new revision, so history
+    /// stays linear. Returns the new version.
+    ///
+    /// `precondition` is checked against the current version. The new revision is written
+    /// with [`CacheProvider::update`], requiring the version the rollback started from, so a
+    /// concurrent write makes it fail with [`ProviderError::PreconditionFailed`] instead of
+    /// being overwritten.
+    async fn rollback(
+        &self,
+        key: String,
+        version: u64,
+        issuer: String,
+        precondition: Precondition,
+    ) -> ProviderResult<u64> {
+        let current = self.metadata(key.clone()).await?.version;
+        precondition.check(Some(current))?;
+
+        let revision = self.revision(key.clone(), version).await?;
+        let options = WriteOptions {
+            ttl: None,
+            precondition: Precondition {
+                if_match: Some(VersionMatch::Versions(vec![current])),
+                if_none_match: None,
+            },
+        };
+
+        self.update(key, revision.value, issuer, options).await
+    }
 
     /// Updates the value for an existing key.
     ///
-    /// Returns `Some(value)` if the key exists and is updated,
-    /// or `None` if the key does not exist.
-    async fn update(&self, key: String, value: T, issuer: String) -> Option<T>;
+    /// Returns the new version once updated, or fails with [`ProviderError::NotFound`] if the
+    /// key does not exist.
+    async fn update(
+        &self,
+        key: String,
+        value: T,
+        issuer: String,
+        options: WriteOptions,
+    ) -> ProviderResult<u64>;
+
+    /// Runs `operations` on behalf of `issuer` as one transaction: either every step applies
+    /// or none does.
+    ///
+    /// Steps run in order, each seeing the writes of the steps before it. When a step fails,
+    /// nothing is written and the inner result names it; the outer error reports a storage
+    /// failure while applying the transaction. Providers keep other writes to the keys
+    /// involved out for the whole transaction, and a crash midway leaves either all of its
+    /// writes or none.
+    async fn transact(
+        &self,
+        operations: Vec<BatchOperation<T>>,
+        issuer: String,
+    ) -> ProviderResult<Result<Vec<BatchOutcome<T>>, BatchError>>;
 
     /// Removes all entries created by the specified issuer.
-    async fn purge(&self, issuer: String);
+    async fn purge(&self, issuer: String) -> ProviderResult<()>;
+
+    /// Deletes every entry whose TTL has passed, returning how many were removed.
+    ///
+    /// Expired entries are already hidden from reads; this reclaims their storage.
+    async fn sweep_expired(&self) -> ProviderResult<usize>;
+
+    /// Lists the secondary indexes declared on this backend.
+    async fn indexes(&self) -> ProviderResult<Vec<IndexDefinition>>;
+
+    /// Declares a secondary index, saves its definition with the store and builds it from the
+    /// entries already stored. From then on every write keeps it up to date.
+    ///
+    /// Fails with [`ProviderError::IndexExists`] if an index already has that name.
+    async fn create_index(&self, index: IndexDefinition) -> ProviderResult<()>;
+
+    /// Throws away the contents of the index `name` and builds it again from the stored
+    /// entries.
+    async fn rebuild_index(&self, name: String) -> ProviderResult<()>;
+
+    /// Removes the index `name` along with its saved definition.
+    async fn drop_index(&self, name: String) -> ProviderResult<()>;
+
+    /// Keys of the entries whose indexed value falls in `range`, in value order.
+    ///
+    /// An index is updated after the write it reflects, so callers should read the entries
+    /// and check them again. Fails with [`ProviderError::IndexBuilding`] until the index is
+    /// built.
+    async fn lookup(&self, name: String, range: IndexRange) -> ProviderResult<Vec<String>>;
+
+    /// Subscribes to every change made to the entries from now on.
+    ///
+    /// Every mutation, including expiry, purges and evictions, publishes one change per entry
+    /// it touches. Writers never wait for subscribers; see [`changes::ChangeFeed`].
+    fn changes(&self) -> broadcast::Receiver<Arc<Change<T>>>;
+
+    /// The last change to every key under `prefix` made after the sequence number `after`, up
+    /// to `limit` of them in sequence order: the entry for keys that exist, the tombstone for
+    /// removed ones. Expired entries are left out until their removal is swept.
+    ///
+    /// Passing the returned cursor as `after` next time picks up where the page ended. Fails
+    /// with [`ProviderError::StaleCursor`] when removals after `after` were already pruned,
+    /// see [`sync::SyncLog`].
+    async fn changes_since(
+        &self,
+        prefix: String,
+        after: u64,
+        limit: usize,
+    ) -> ProviderResult<ChangePage<T>>;
+
+    /// Reports usage figures for this backend.
+    ///
+    /// Providers that do not track anything return the defaults.
+    async fn stats(&self) -> ProviderStats {
+        ProviderStats::default()
+    }
+}
+
+/// Number of times [`modify`] and [`increment`] retry after losing a race to another write.
+const MAX_ATTEMPTS: usize = 16;
+
+/// Rewrites the value of an existing `key` with `change`, returning the new version and value.
+///
+/// The value is read, changed and written back with [`CacheProvider::update`] requiring the
+/// version it was read at. If another write gets in between, the whole step is retried on the
+/// newer value, so the change applies atomically. `options.precondition` is checked against
+/// the version read; once a concurrent write has moved past it, the call fails with
+/// [`ProviderError::PreconditionFailed`]. After [`MAX_ATTEMPTS`] lost races it gives up with
+/// [`ProviderError::Contended`].
+///
+/// When `change` refuses the value, nothing is written and its error is returned inside.
+pub async fn modify<T, E>(
+    provider: &dyn CacheProvider<T>,
+    key: String,
+    issuer: String,
+    options: WriteOptions,
+    mut change: impl FnMut(&T) -> Result<T, E>,
+) -> ProviderResult<Result<(u64, T), E>>
+where
+    T: Clone + Send + 'static,
+{
+    for _ in 0..MAX_ATTEMPTS {
+        let entry = provider.entry(key.clone()).await?;
+        let current = entry.metadata.version;
+        options.precondition.check(Some(current))?;
+
+        let value = match change(&entry.value) {
+            Ok(value) => value,
+            Err(e) => return Ok(Err(e)),
+        };
+        let attempt = WriteOptions {
+            ttl: options.ttl,
+            precondition: Precondition {
+                if_match: Some(VersionMatch::Versions(vec![current])),
+                if_none_match: None,
+            },
+        };
+
+        match provider
+            .update(key.clone(), value.clone(), issuer.clone(), attempt)
+            .await
+        {
+            Err(ProviderError::PreconditionFailed) => continue,
+            written => return Ok(Ok((written?, value))),
+        }
+    }
+
+    Err(ProviderError::Contended)
+}
+
+/// Applies `increment` to the value of `key`, returning the new version and value.
+///
+/// The value is changed through [`modify`], so concurrent increments all apply. If `key` does
+/// not exist and `initial` is given, the entry is created as `initial` with the increment
+/// applied, at version 0; should another write create it first, the increment applies to
+/// that entry instead. Like [`modify`], it gives up with [`ProviderError::Contended`] after
+/// [`MAX_ATTEMPTS`] lost races.
+pub async fn increment(
+    provider: &dyn CacheProvider<Value>,
+    key: String,
+    issuer: String,
+    options: WriteOptions,
+    increment: &Increment,
+    initial: Option<&Value>,
+) -> ProviderResult<Result<(u64, Value), IncrementError>> {
+    for _ in 0..MAX_ATTEMPTS {
+        let modified = modify(
+            provider,
+            key.clone(),
+            issuer.clone(),
+            options.clone(),
+            |current| increment.apply(current).map(|(value, _)| value),
+        )
+        .await;
+        let (Err(ProviderError::NotFound), Some(initial)) = (&modified, initial) else {
+            return modified;
+        };
+
+        let value = match increment.apply(initial) {
+            Ok((value, _)) => value,
+            Err(e) => return Ok(Err(e)),
+        };
+        match provider
+            .add(key.clone(), value.clone(), issuer.clone(), options.clone())
+            .await
+        {
+            Err(ProviderError::AlreadyExists) => continue,
+            added => return Ok(Ok((added?, value))),
+        }
+    }
+
+    Err(ProviderError::Contended)
+}
+
+/// Number of keys [`scan`] lists at a time.
+const SCAN_PAGE: usize = 256;
+
+/// Visits every live entry whose key starts with `prefix`, in key order, until `visit`
+/// returns `false`.
+///
+/// Works with any backend: keys are paged through [`CacheProvider::list_keys`] and read one
+/// at a time, skipping entries removed in between. Only the current page of keys is held.
+pub async fn scan<T>(
+    provider: &dyn CacheProvider<T>,
+    prefix: String,
+    mut visit: impl FnMut(String, Entry<T>) -> bool,
+) -> ProviderResult<()>
+where
+    T: Clone + Send + 'static,
+{
+    let mut after = None;
+
+    loop {
+        let page = provider
+            .list_keys(prefix.clone(), after, Some(SCAN_PAGE))
+            .await?;
+
+        for key in page.keys {
+            match provider.entry(key.clone()).await {
+                Ok(entry) => {
+                    if !visit(key, entry) {
+                        return Ok(());
+                    }
+                }
+                Err(ProviderError::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        match page.next {
+            Some(next) => after = Some(next),
+            None => return Ok(()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "memory"))]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::{Value, json};
+    use tokio::{runtime::Handle, task::block_in_place};
+
+    use super::{
+        CacheProvider, MAX_ATTEMPTS, ProviderError, SCAN_PAGE, fs::FileSystemProvider, increment,
+        memory::MemoryProvider, modify, scan,
+    };
+    use crate::structs::counter::Increment;
+
+    #[tokio::test]
+    async fn boxed_provider_runs_in_spawned_task() {
+        let provider: Arc<dyn CacheProvider<Value>> =
+            Arc::new(MemoryProvider::new(Default::default()));
+
+        let background = provider.clone();
+        tokio::spawn(async move {
+            background
+                .add(
+                    "spawned".into(),
+                    json!(1),
+                    "tester".into(),
+                    Default::default(),
+                )
+                .await
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            provider.entry("spawned".into()).await.unwrap().value,
+            json!(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_modifications_all_apply() {
+        let provider: Arc<dyn CacheProvider<Value>> =
+            Arc::new(MemoryProvider::new(Default::default()));
+        provider
+            .add("n".into(), json!(0), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        let writers: Vec<_> = (0..8)
+            .map(|_| {
+                let provider = provider.clone();
+                tokio::spawn(async move {
+                    modify(
+                        &*provider,
+                        "n".into(),
+                        "t".into(),
+                        Default::default(),
+                        |n| Ok::<_, ()>(json!(n.as_u64().unwrap() + 1)),
+                    )
+                    .await
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.await.unwrap().unwrap().unwrap();
+        }
+
+        let entry = provider.entry("n".into()).await.unwrap();
+        assert_eq!((entry.value, entry.metadata.version), (json!(8), 8));
+
+        let refused = modify(
+            &*provider,
+            "n".into(),
+            "t".into(),
+            Default::default(),
+            |_| Err("no"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(refused, Err("no"));
+        assert_eq!(provider.metadata("n".into()).await.unwrap().version, 8);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn modify_gives_up_when_always_overtaken() {
+        let provider: Arc<dyn CacheProvider<Value>> =
+            Arc::new(MemoryProvider::new(Default::default()));
+        provider
+            .add("n".into(), json!(0), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        let mut attempts = 0;
+        let overtaken = modify(
+            &*provider,
+            "n".into(),
+            "t".into(),
+            Default::default(),
+            |n| {
+                attempts += 1;
+                let rival = provider.update("n".into(), json!(-1), "r".into(), Default::default());
+                block_in_place(|| Handle::current().block_on(rival)).unwrap();
+                Ok::<_, ()>(json!(n.as_i64().unwrap() + 1))
+            },
+        )
+        .await;
+
+        assert!(matches!(overtaken, Err(ProviderError::Contended)));
+        assert_eq!(attempts, MAX_ATTEMPTS);
+        assert_eq!(provider.entry("n".into()).await.unwrap().value, json!(-1));
+    }
+
+    #[tokio::test]
+    async fn concurrent_increments_all_apply() {
+        let dir = std::env::temp_dir().join(format!("objekt-increment-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let providers: [Arc<dyn CacheProvider<Value>>; 2] = [
+            Arc::new(MemoryProvider::new(Default::default())),
+            Arc::new(FileSystemProvider::new(dir.clone()).await.unwrap()),
+        ];
+        let by_two: Increment =
+            serde_json::from_value(json!({"pointer": "/hits", "by": 2})).unwrap();
+
+        for provider in providers {
+            let writers: Vec<_> = (0..8)
+                .map(|_| {
+                    let provider = provider.clone();
+                    let by_two = by_two.clone();
+                    tokio::spawn(async move {
+                        let initial = json!({"hits": 0});
+                        increment(
+                            &*provider,
+                            "n".into(),
+                            "t".into(),
+                            Default::default(),
+                            &by_two,
+                            Some(&initial),
+                        )
+                        .await
+                    })
+                })
+                .collect();
+            let mut created = 0;
+            for writer in writers {
+                let (version, _) = writer.await.unwrap().unwrap().unwrap();
+                created += usize::from(version == 0);
+            }
+
+            let entry = provider.entry("n".into()).await.unwrap();
+            assert_eq!(created, 1);
+            assert_eq!(
+                (entry.value, entry.metadata.version),
+                (json!({"hits": 16}), 7)
+            );
+
+            let missing = increment(
+                &*provider,
+                "m".into(),
+                "t".into(),
+                Default::default(),
+                &by_two,
+                None,
+            )
+            .await;
+            assert!(missing.is_err());
+        }
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn scans_prefix_across_pages() {
+        let provider: Arc<dyn CacheProvider<Value>> =
+            Arc::new(MemoryProvider::new(Default::default()));
+        for i in 0..SCAN_PAGE + 10 {
+            provider
+                .add(
+                    format!("p:{i:04}"),
+                    json!(i),
+                    "t".into(),
+                    Default::default(),
+                )
+                .await
+                .unwrap();
+        }
+        provider
+            .add("q".into(), json!(-1), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        let mut seen = Vec::new();
+        scan(&*provider, "p:".into(), |_, entry| {
+            seen.push(entry.value.as_u64().unwrap());
+            true
+        })
+        .await
+        .unwrap();
+        assert_eq!(seen, (0..SCAN_PAGE as u64 + 10).collect::<Vec<_>>());
+
+        let mut visits = 0;
+        scan(&*provider, "p:".into(), |_, _| {
+            visits += 1;
+            visits < 3
+        })
+        .await
+        .unwrap();
+        assert_eq!(visits, 3);
+    }
 }
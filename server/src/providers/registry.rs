@@ -0,0 +1,197 @@
+use std::{collections::HashMap, env, path::PathBuf, sync::Arc};
+
+use anyhow::{Context, Result, anyhow};
+use chrono::TimeDelta;
+use futures::future::BoxFuture;
+use serde_json::Value;
+
+use super::{CacheProvider, fs::FileSystemProvider};
+#[cfg(feature = "memory")]
+use super::{
+    eviction::{EvictionPolicy, MemoryLimits},
+    wal::WalConfig,
+};
+
+const DEFAULT_PROVIDER: &str = "memory";
+#[cfg(feature = "memory")]
+const DEFAULT_MEMORY_MAX_ENTRIES: usize = 50;
+const DEFAULT_FS_PATH: &str = "./cache";
+const DEFAULT_HISTORY_DEPTH: usize = 10;
+const DEFAULT_TOMBSTONE_RETENTION_SECS: i64 = 30 * 24 * 60 * 60;
+#[cfg(feature = "memory")]
+const DEFAULT_SNAPSHOT_EVERY: usize = 1000;
+
+/// A shared, type-erased cache backend.
+pub type DynProvider = Arc<dyn CacheProvider<Value>>;
+
+/// Builds a backend, reading whatever options it needs from the environment.
+pub type ProviderFactory = Box<dyn Fn() -> BoxFuture<'static, Result<DynProvider>> + Send + Sync>;
+
+/// Maps `CACHE_PROVIDER` names to the factories that build them.
+///
+/// Ships with `memory` and `fs`; other backends can be added with [`ProviderRegistry::register`]
+/// before the server starts.
+pub struct ProviderRegistry {
+    factories: HashMap<String, ProviderFactory>,
+}
+
+impl ProviderRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Creates a registry containing the bundled backends.
+    ///
+    /// * `memory` - bounded by `MEMORY_MAX_ENTRIES` (50 when unset) and `MEMORY_MAX_BYTES`
+    ///   (unbounded when unset), where `0` lifts the bound, evicting by
+    ///   `MEMORY_EVICTION_POLICY` (`lru`, `lfu` or `oldest`). Setting `MEMORY_WAL_DIR` makes
+    ///   it durable, snapshotting every `MEMORY_SNAPSHOT_EVERY` records and syncing each
+    ///   record when `MEMORY_WAL_FSYNC` is `true`.
+    /// * `fs` - rooted at `FS_PATH`. Setting `FS_SHARD_DEPTH` re-shards the directory to that
+    ///   many levels on start; otherwise it keeps the depth it was written with.
+    ///
+    /// Both keep the last `HISTORY_DEPTH` revisions of every entry (10 when unset, `0` keeps
+    /// none), and the tombstones of removed entries for `TOMBSTONE_RETENTION_SECS` (30 days
+    /// when unset, `0` keeps them for good).
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+
+        #[cfg(feature = "memory")]
+        registry.register("memory", || {
+            Box::pin(async {
+                let max_entries =
+                    env_usize("MEMORY_MAX_ENTRIES")?.unwrap_or(DEFAULT_MEMORY_MAX_ENTRIES);
+                let limits = MemoryLimits {
+                    max_entries: Some(max_entries).filter(|&max| max > 0),
+                    max_bytes: env_usize("MEMORY_MAX_BYTES")?.filter(|&max| max > 0),
+                    policy: match env::var("MEMORY_EVICTION_POLICY") {
+                        Ok(policy) => policy.parse()?,
+                        Err(_) => EvictionPolicy::default(),
+                    },
+                    history_depth: history_depth()?,
+                    tombstone_retention: tombstone_retention()?,
+                };
+
+                let provider = match env::var("MEMORY_WAL_DIR") {
+                    Ok(dir) => {
+                        let config = WalConfig {
+                            dir: PathBuf::from(dir),
+                            snapshot_every: env_usize("MEMORY_SNAPSHOT_EVERY")?
+                                .filter(|&every| every > 0)
+                                .unwrap_or(DEFAULT_SNAPSHOT_EVERY),
+                            fsync: env::var("MEMORY_WAL_FSYNC")
+                                .is_ok_and(|v| matches!(v.as_str(), "1" | "true")),
+                        };
+
+                        super::memory::MemoryProvider::durable(limits, config)?
+                    }
+                    Err(_) => super::memory::MemoryProvider::new(limits),
+                };
+
+                Ok(Arc::new(provider) as DynProvider)
+            })
+        });
+
+        registry.register("fs", || {
+            Box::pin(async {
+                let path = PathBuf::from(
+                    env::var("FS_PATH").unwrap_or_else(|_| DEFAULT_FS_PATH.to_owned()),
+                );
+
+                let provider = match env::var("FS_SHARD_DEPTH") {
+                    Ok(depth) => {
+                        let depth = depth
+                            .parse()
+                            .with_context(|| format!("invalid FS_SHARD_DEPTH \"{depth}\""))?;
+                        FileSystemProvider::with_shard_depth(path, depth).await?
+                    }
+                    Err(_) => FileSystemProvider::new(path).await?,
+                };
+
+                let provider = provider
+                    .with_history_depth(history_depth()?)
+                    .with_tombstone_retention(tombstone_retention()?);
+
+                Ok(Arc::new(provider) as DynProvider)
+            })
+        });
+
+        registry
+    }
+
+    /// Registers (or replaces) the factory for `name`.
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn() -> BoxFuture<'static, Result<DynProvider>> + Send + Sync + 'static,
+    {
+        self.factories
+            .insert(name.to_lowercase(), Box::new(factory));
+    }
+
+    /// Builds the backend registered under `name`.
+    pub async fn build(&self, name: &str) -> Result<DynProvider> {
+        let factory = self
+            .factories
+            .get(&name.to_lowercase())
+            .ok_or_else(|| anyhow!("unknown cache provider \"{name}\""))?;
+
+        factory().await
+    }
+
+    /// Builds the backend named by `CACHE_PROVIDER` (defaults to `memory`).
+    pub async fn build_from_env(&self) -> Result<(String, DynProvider)> {
+        let name = env::var("CACHE_PROVIDER").unwrap_or_else(|_| DEFAULT_PROVIDER.to_owned());
+        let provider = self.build(&name).await?;
+
+        Ok((name, provider))
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self::with_defaults()
+    }
+}
+
+/// Reads `HISTORY_DEPTH`, the number of past revisions kept per entry.
+fn history_depth() -> Result<usize> {
+    match env::var("HISTORY_DEPTH") {
+        Ok(depth) => depth
+            .parse()
+            .with_context(|| format!("invalid HISTORY_DEPTH \"{depth}\"")),
+        Err(_) => Ok(DEFAULT_HISTORY_DEPTH),
+    }
+}
+
+/// Reads `TOMBSTONE_RETENTION_SECS`, how long removed entries are remembered for syncing.
+fn tombstone_retention() -> Result<Option<TimeDelta>> {
+    let secs = match env::var("TOMBSTONE_RETENTION_SECS") {
+        Ok(secs) => secs
+            .parse()
+            .with_context(|| format!("invalid TOMBSTONE_RETENTION_SECS \"{secs}\""))?,
+        Err(_) => DEFAULT_TOMBSTONE_RETENTION_SECS,
+    };
+
+    match secs {
+        0 => Ok(None),
+        secs => TimeDelta::try_seconds(secs)
+            .filter(|retention| *retention > TimeDelta::zero())
+            .map(Some)
+            .ok_or_else(|| anyhow!("invalid TOMBSTONE_RETENTION_SECS \"{secs}\"")),
+    }
+}
+
+/// Reads a number from the environment, `None` when unset.
+#[cfg(feature = "memory")]
+fn env_usize(name: &str) -> Result<Option<usize>> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .with_context(|| format!("invalid {name} \"{value}\"")),
+        Err(_) => Ok(None),
+    }
+}
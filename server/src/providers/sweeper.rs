@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+use tokio::{task::JoinHandle, time};
+use tracing::{debug, error};
+
+use super::registry::DynProvider;
+
+/// Periodically reclaims expired entries from `provider` in a background task.
+pub fn spawn_sweeper(provider: DynProvider, every: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = time::interval(every);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            match provider.sweep_expired().await {
+                Ok(0) => {}
+                Ok(removed) => debug!("swept {removed} expired entries"),
+                Err(e) => error!("failed to sweep expired entries: {e}"),
+            }
+        }
+    })
+}
@@ -0,0 +1,474 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::structs::entry::Entry;
+
+use super::{ProviderError, ProviderResult, changes::ChangeKind};
+
+/// A removed entry, remembered so clients syncing the store learn that it is gone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub key: String,
+    /// Sequence number of the removal.
+    pub sequence: u64,
+    /// Why the entry went away.
+    pub kind: ChangeKind,
+    /// When it went away.
+    pub at: DateTime<Utc>,
+}
+
+impl Tombstone {
+    pub fn new(key: &str, sequence: u64, kind: ChangeKind) -> Self {
+        Self {
+            key: key.to_owned(),
+            sequence,
+            kind,
+            at: Utc::now(),
+        }
+    }
+}
+
+/// The last change to one key, as returned by
+/// [`CacheProvider::changes_since`](super::CacheProvider::changes_since).
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncChange<T> {
+    /// The key holds `entry`, written at `entry.metadata.sequence`.
+    Written { key: String, entry: Entry<T> },
+    /// The key was removed.
+    Removed(Tombstone),
+}
+
+impl<T> SyncChange<T> {
+    pub fn sequence(&self) -> u64 {
+        match self {
+            Self::Written { entry, .. } => entry.metadata.sequence,
+            Self::Removed(tombstone) => tombstone.sequence,
+        }
+    }
+}
+
+/// A key whose last change falls in the range asked of [`SyncLog::changed`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Changed {
+    /// The key was written at `sequence`; the provider holds the entry.
+    Written { key: String, sequence: u64 },
+    /// The key was removed.
+    Removed(Tombstone),
+}
+
+impl Changed {
+    pub fn sequence(&self) -> u64 {
+        match self {
+            Self::Written { sequence, .. } => *sequence,
+            Self::Removed(tombstone) => tombstone.sequence,
+        }
+    }
+}
+
+/// One page of [`SyncChange`]s, in sequence order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangePage<T> {
+    pub changes: Vec<SyncChange<T>>,
+    /// Sequence number to ask for changes after next time.
+    pub cursor: u64,
+    /// Whether more changes are waiting past `cursor` already.
+    pub more: bool,
+}
+
+impl<T> ChangePage<T> {
+    /// Builds a page from `changes` in any order, all of them at or below `stable`.
+    ///
+    /// A full page ends at its last change. Otherwise every change up to `stable` was in it,
+    /// so the cursor moves on to `stable`.
+    pub fn from_changes(mut changes: Vec<SyncChange<T>>, limit: usize, stable: u64) -> Self {
+        changes.sort_unstable_by_key(SyncChange::sequence);
+
+        let more = changes.len() > limit;
+        changes.truncate(limit);
+        let cursor = match more {
+            true => changes.last().map_or(stable, SyncChange::sequence),
+            false => stable,
+        };
+
+        Self {
+            changes,
+            cursor,
+            more,
+        }
+    }
+}
+
+/// What of a [`SyncLog`] outlives a restart on its own, see [`SyncLog::saved`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    /// Tombstones up to this sequence number were pruned.
+    pub horizon: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tombstones: Vec<Tombstone>,
+}
+
+/// Hands out the sequence numbers of a provider and keeps its tombstones.
+///
+/// Every mutation of an entry takes the next number, so a client that remembers the highest
+/// one it has seen can ask for everything after it. An entry records the number of its last
+/// write in its metadata; a removed one leaves a [`Tombstone`] instead, one per key. Writing
+/// the key again buries the tombstone for good. The last change of every key is also kept in
+/// sequence order, so [`SyncLog::changed`] finds the keys changed after a cursor without
+/// looking at the others.
+///
+/// A number is handed out before the write it belongs to lands. Until the [`Ticket`] is
+/// dropped it stays pending, and [`SyncLog::stable`] stays below it, so readers never move a
+/// cursor past a write they could not see yet.
+///
+/// Tombstones older than the retention are pruned, raising the horizon: cursors below it may
+/// have missed a removal and are refused.
+#[derive(Debug, Default)]
+pub struct SyncLog {
+    state: Mutex<LogState>,
+    retention: Option<TimeDelta>,
+}
+
+#[derive(Debug, Default)]
+struct LogState {
+    last: u64,
+    pending: BTreeSet<u64>,
+    horizon: u64,
+    tombstones: BTreeMap<String, Tombstone>,
+    /// Sequence number of the last change of each key.
+    latest: HashMap<String, u64>,
+    /// The same changes, keyed by sequence number.
+    order: BTreeMap<u64, String>,
+}
+
+impl LogState {
+    /// Records that the last change of `key` is the one at `sequence`, unless a later one
+    /// is known already. Entries written before writes were numbered are at 0 and recorded
+    /// once they are numbered. Returns whether it was recorded.
+    fn change(&mut self, key: &str, sequence: u64) -> bool {
+        self.last = self.last.max(sequence);
+        if sequence == 0 {
+            return false;
+        }
+
+        match self.latest.get(key) {
+            Some(&latest) if latest >= sequence => return false,
+            Some(latest) => {
+                self.order.remove(latest);
+            }
+            None => {}
+        }
+
+        self.latest.insert(key.to_owned(), sequence);
+        self.order.insert(sequence, key.to_owned());
+        true
+    }
+}
+
+/// A sequence number handed out for a write that has not landed yet.
+#[must_use]
+pub struct Ticket<'a> {
+    log: &'a SyncLog,
+    sequence: u64,
+}
+
+impl Ticket<'_> {
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        self.log
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .remove(&self.sequence);
+    }
+}
+
+impl SyncLog {
+    /// A log continuing from `state`, as returned by [`SyncLog::saved`].
+    pub fn restore(state: SyncState) -> Self {
+        let log = Self::default();
+        log.state.lock().unwrap().horizon = state.horizon;
+        log.observe(state.horizon);
+        for tombstone in state.tombstones {
+            log.bury(tombstone);
+        }
+
+        log
+    }
+
+    /// Prunes tombstones once they are older than `retention`. Without one they are kept.
+    pub fn with_retention(mut self, retention: Option<TimeDelta>) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Everything needed to restore the log, see [`SyncLog::restore`].
+    pub fn saved(&self) -> SyncState {
+        let state = self.state.lock().unwrap();
+
+        SyncState {
+            horizon: state.horizon,
+            tombstones: state.tombstones.values().cloned().collect(),
+        }
+    }
+
+    /// Hands out the next sequence number.
+    pub fn next(&self) -> Ticket<'_> {
+        let mut state = self.state.lock().unwrap();
+        state.last += 1;
+        let sequence = state.last;
+        state.pending.insert(sequence);
+
+        Ticket {
+            log: self,
+            sequence,
+        }
+    }
+
+    /// Notes that `sequence` was handed out before, so it is never handed out again.
+    pub fn observe(&self, sequence: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.last = state.last.max(sequence);
+    }
+
+    /// Highest sequence number below which every write has landed.
+    pub fn stable(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+
+        state
+            .pending
+            .first()
+            .map_or(state.last, |pending| pending - 1)
+    }
+
+    /// Highest pruned sequence number.
+    pub fn horizon(&self) -> u64 {
+        self.state.lock().unwrap().horizon
+    }
+
+    /// Fails with [`ProviderError::StaleCursor`] unless a client that has seen everything up
+    /// to `after` can continue from there: removals after it must all still be known, and it
+    /// cannot be ahead of the log, as after a volatile store restarted.
+    pub fn check_cursor(&self, after: u64) -> ProviderResult<()> {
+        let state = self.state.lock().unwrap();
+
+        match after > state.last || (after > 0 && after < state.horizon) {
+            true => Err(ProviderError::StaleCursor),
+            false => Ok(()),
+        }
+    }
+
+    /// Remembers `tombstone`, unless the key was already removed or written again later, or
+    /// it is older than the horizon.
+    pub fn bury(&self, tombstone: Tombstone) {
+        let mut state = self.state.lock().unwrap();
+        state.last = state.last.max(tombstone.sequence);
+        if tombstone.sequence <= state.horizon {
+            return;
+        }
+
+        if state.change(&tombstone.key, tombstone.sequence) {
+            state.tombstones.insert(tombstone.key.clone(), tombstone);
+        }
+    }
+
+    /// Notes that `key` was written at `sequence`, forgetting its tombstone if it was removed
+    /// before.
+    pub fn written(&self, key: &str, sequence: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.change(key, sequence) {
+            state.tombstones.remove(key);
+        }
+    }
+
+    /// The tombstone of `key`, if it is gone.
+    pub fn tombstone(&self, key: &str) -> Option<Tombstone> {
+        self.state.lock().unwrap().tombstones.get(key).cloned()
+    }
+
+    /// Up to `limit` keys under `prefix` last changed after `after` and up to `until`, in
+    /// sequence order.
+    ///
+    /// Only changes past `after` are looked at. A written key may have been written or removed
+    /// again since without the provider telling the log yet, so its entry is checked there.
+    pub fn changed(&self, prefix: &str, after: u64, until: u64, limit: usize) -> Vec<Changed> {
+        let state = self.state.lock().unwrap();
+        if after >= until {
+            return Vec::new();
+        }
+
+        state
+            .order
+            .range(after + 1..=until)
+            .filter(|(_, key)| key.starts_with(prefix))
+            .take(limit)
+            .map(|(&sequence, key)| match state.tombstones.get(key) {
+                Some(tombstone) => Changed::Removed(tombstone.clone()),
+                None => Changed::Written {
+                    key: key.clone(),
+                    sequence,
+                },
+            })
+            .collect()
+    }
+
+    /// Drops the tombstones older than the retention, raising the horizon past them.
+    ///
+    /// Returns the pruned tombstones.
+    pub fn prune(&self) -> Vec<Tombstone> {
+        let Some(retention) = self.retention else {
+            return Vec::new();
+        };
+        let cutoff = Utc::now() - retention;
+
+        let mut state = self.state.lock().unwrap();
+        let pruned: Vec<Tombstone> = state
+            .tombstones
+            .values()
+            .filter(|tombstone| tombstone.at <= cutoff)
+            .cloned()
+            .collect();
+        for tombstone in &pruned {
+            state.tombstones.remove(&tombstone.key);
+            state.latest.remove(&tombstone.key);
+            state.order.remove(&tombstone.sequence);
+            state.horizon = state.horizon.max(tombstone.sequence);
+        }
+
+        pruned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_stays_below_pending_writes() {
+        let log = SyncLog::default();
+
+        let first = log.next();
+        let second = log.next();
+        assert_eq!((first.sequence(), second.sequence()), (1, 2));
+        assert_eq!(log.stable(), 0);
+
+        drop(second);
+        assert_eq!(log.stable(), 0);
+        drop(first);
+        assert_eq!(log.stable(), 2);
+
+        log.observe(10);
+        assert_eq!(log.next().sequence(), 11);
+    }
+
+    #[test]
+    fn keeps_the_last_tombstone_per_key() {
+        let log = SyncLog::default();
+
+        log.bury(Tombstone::new("a:x", 3, ChangeKind::Remove));
+        log.bury(Tombstone::new("a:x", 2, ChangeKind::Expire));
+        log.bury(Tombstone::new("b:y", 4, ChangeKind::Purge));
+        assert_eq!(log.tombstone("a:x").unwrap().kind, ChangeKind::Remove);
+
+        log.written("a:x", 3);
+        assert!(log.tombstone("a:x").is_some());
+        assert_eq!(log.changed("a:", 0, 10, 10).len(), 1);
+        assert!(log.changed("a:", 3, 10, 10).is_empty());
+        assert!(log.changed("a:", 0, 2, 10).is_empty());
+
+        log.written("a:x", 5);
+        assert_eq!(log.tombstone("a:x"), None);
+        assert_eq!(
+            log.changed("a:", 0, 10, 10),
+            [Changed::Written {
+                key: "a:x".into(),
+                sequence: 5
+            }]
+        );
+        assert_eq!(log.next().sequence(), 6);
+    }
+
+    #[test]
+    fn lists_the_last_change_of_each_key_in_order() {
+        let log = SyncLog::default();
+
+        log.written("a:x", 1);
+        log.written("b:y", 2);
+        log.written("a:z", 3);
+        log.bury(Tombstone::new("a:z", 5, ChangeKind::Remove));
+        log.written("a:x", 4);
+        // a late report of an older write changes nothing
+        log.written("a:x", 2);
+
+        let sequences =
+            |changes: Vec<Changed>| changes.iter().map(Changed::sequence).collect::<Vec<_>>();
+        assert_eq!(sequences(log.changed("a:", 0, 5, 10)), [4, 5]);
+        assert_eq!(sequences(log.changed("a:", 0, 5, 1)), [4]);
+        assert_eq!(sequences(log.changed("a:", 4, 5, 10)), [5]);
+        assert_eq!(sequences(log.changed("", 0, 4, 10)), [2, 4]);
+        assert!(matches!(
+            log.changed("a:z", 0, 5, 10)[0],
+            Changed::Removed(_)
+        ));
+    }
+
+    #[test]
+    fn pruning_refuses_older_cursors() {
+        let log = SyncLog::default().with_retention(Some(TimeDelta::zero()));
+
+        log.bury(Tombstone::new("a", 2, ChangeKind::Remove));
+        log.observe(5);
+        assert!(log.check_cursor(1).is_ok());
+        assert!(matches!(
+            log.check_cursor(6),
+            Err(ProviderError::StaleCursor)
+        ));
+
+        assert_eq!(log.prune().len(), 1);
+        assert_eq!(log.horizon(), 2);
+        assert!(matches!(
+            log.check_cursor(1),
+            Err(ProviderError::StaleCursor)
+        ));
+        assert!(log.check_cursor(0).is_ok());
+        assert!(log.check_cursor(2).is_ok());
+
+        // the pruned removal does not come back
+        log.bury(Tombstone::new("a", 2, ChangeKind::Remove));
+        assert_eq!(log.tombstone("a"), None);
+
+        let restored = SyncLog::restore(log.saved());
+        assert_eq!(restored.horizon(), 2);
+        assert_eq!(restored.next().sequence(), 3);
+    }
+
+    #[test]
+    fn pages_end_at_the_stable_sequence() {
+        let removed = |key: &str, sequence| {
+            SyncChange::<u32>::Removed(Tombstone::new(key, sequence, ChangeKind::Remove))
+        };
+
+        let page = ChangePage::from_changes(vec![removed("b", 7), removed("a", 4)], 5, 9);
+        assert_eq!(
+            page.changes
+                .iter()
+                .map(SyncChange::sequence)
+                .collect::<Vec<_>>(),
+            [4, 7]
+        );
+        assert_eq!((page.cursor, page.more), (9, false));
+
+        let page = ChangePage::from_changes(vec![removed("b", 7), removed("a", 4)], 1, 9);
+        assert_eq!((page.cursor, page.more), (4, true));
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tracing::{error, warn};
+
+use super::sync::{SyncState, Tombstone};
+use crate::structs::{entry::Entry, metadata::Metadata};
+
+const LOG_FILE: &str = "wal.log";
+const SNAPSHOT_FILE: &str = "snapshot.jsonl";
+const SNAPSHOT_TMP_FILE: &str = "snapshot.jsonl.tmp";
+const SYNC_FILE: &str = "sync.json";
+const SYNC_TMP_FILE: &str = "sync.json.tmp";
+
+/// A mutation recorded in the write-ahead log.
+///
+/// Writes carry the full resulting entry, so replaying a record twice leaves the same state.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum WalRecord<T> {
+    Add {
+        key: String,
+        value: T,
+        metadata: Metadata,
+    },
+    Update {
+        key: String,
+        value: T,
+        metadata: Metadata,
+    },
+    /// Records written before removals left tombstones have none.
+    Remove {
+        key: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tombstone: Option<Tombstone>,
+    },
+    /// Only found in logs written before purges were logged as one removal per entry.
+    Purge { issuer: String },
+    /// The writes of one transaction, logged as a single record so they replay all or none.
+    Batch { records: Vec<WalRecord<T>> },
+}
+
+/// An entry as stored in a snapshot.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotEntry<T> {
+    pub key: String,
+    pub value: T,
+    pub metadata: Metadata,
+    /// Past revisions of the entry, oldest first.
+    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<Entry<T>>,
+}
+
+/// Where and how often the log is written and compacted.
+#[derive(Debug, Clone)]
+pub struct WalConfig {
+    /// Directory holding `wal.log` and `snapshot.jsonl`.
+    pub dir: PathBuf,
+    /// Number of logged records after which a snapshot is taken and the log truncated.
+    pub snapshot_every: usize,
+    /// Whether every record is flushed to disk with `fsync` before the write is acknowledged.
+    pub fsync: bool,
+}
+
+/// State recovered from disk by [`Wal::open`].
+pub struct Recovered<T> {
+    /// Entries from the last snapshot.
+    pub snapshot: Vec<SnapshotEntry<T>>,
+    /// Records logged after that snapshot, in order.
+    pub records: Vec<WalRecord<T>>,
+    /// Tombstones and horizon saved with the snapshot. May be newer than the snapshot if a
+    /// crash interrupted it, which replaying the log catches up with.
+    pub sync: SyncState,
+}
+
+/// An append-only, line delimited JSON log with periodic snapshots.
+///
+/// Records are appended while the caller holds a [`WalGuard`], which serializes writers so
+/// the log order matches the order mutations were applied in.
+pub struct Wal {
+    config: WalConfig,
+    state: Mutex<WalState>,
+}
+
+struct WalState {
+    log: File,
+    /// Length of the log, where the next record starts.
+    len: u64,
+    pending: usize,
+    /// Set when a failed append could not be cut off again, see [`WalGuard::append`].
+    broken: bool,
+}
+
+impl Wal {
+    /// Opens (or creates) the log in `config.dir` and reads back everything it holds.
+    ///
+    /// A torn record at the tail of the log, left behind by a crash mid-write, is discarded
+    /// and the file truncated to the last complete record. A bad record anywhere else means
+    /// the log is corrupt, and opening it fails rather than dropping the records after it.
+    pub fn open<T: DeserializeOwned>(config: WalConfig) -> Result<(Self, Recovered<T>)> {
+        fs::create_dir_all(&config.dir)
+            .with_context(|| format!("creating wal directory {}", config.dir.display()))?;
+
+        // leftover temp files belong to a snapshot that never got renamed in
+        let _ = fs::remove_file(config.dir.join(SNAPSHOT_TMP_FILE));
+        let _ = fs::remove_file(config.dir.join(SYNC_TMP_FILE));
+
+        let snapshot = read_snapshot(&config.dir.join(SNAPSHOT_FILE))?;
+        let sync = read_sync(&config.dir.join(SYNC_FILE))?;
+
+        let log_path = config.dir.join(LOG_FILE);
+        let mut log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&log_path)
+            .with_context(|| format!("opening {}", log_path.display()))?;
+
+        let mut data = Vec::new();
+        log.read_to_end(&mut data)?;
+
+        let (records, valid_len) =
+            parse_log(&data).with_context(|| format!("reading {}", log_path.display()))?;
+        if valid_len < data.len() {
+            warn!(
+                "discarding {} bytes of torn wal tail in {}",
+                data.len() - valid_len,
+                log_path.display()
+            );
+            log.set_len(valid_len as u64)?;
+            log.sync_all()?;
+        }
+
+        let wal = Self {
+            state: Mutex::new(WalState {
+                log,
+                len: valid_len as u64,
+                pending: records.len(),
+                broken: false,
+            }),
+            config,
+        };
+
+        Ok((
+            wal,
+            Recovered {
+                snapshot,
+                records,
+                sync,
+            },
+        ))
+    }
+
+    /// Takes the writer lock. Hold it from the moment a mutation is checked until it is applied.
+    pub fn lock(&self) -> WalGuard<'_> {
+        WalGuard {
+            config: &self.config,
+            state: self.state.lock().unwrap(),
+        }
+    }
+}
+
+/// Exclusive access to the log for the duration of one mutation.
+pub struct WalGuard<'a> {
+    config: &'a WalConfig,
+    state: MutexGuard<'a, WalState>,
+}
+
+impl WalGuard<'_> {
+    /// Appends `record` as a single line.
+    ///
+    /// When the write or sync fails, the log is cut back to where the record started, so no
+    /// torn bytes are left for the next record to be glued onto. If even that fails, every
+    /// further append is refused.
+    pub fn append<T: Serialize>(&mut self, record: &WalRecord<T>) -> io::Result<()> {
+        if self.state.broken {
+            return Err(io::Error::other(
+                "the wal holds a torn record, restart to recover it",
+            ));
+        }
+
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        if let Err(e) = self.write_line(&line) {
+            let len = self.state.len;
+            if let Err(truncate) = self.state.log.set_len(len) {
+                error!("failed to cut a torn record off the wal: {truncate}");
+                self.state.broken = true;
+            }
+            return Err(e);
+        }
+
+        self.state.len += line.len() as u64;
+        self.state.pending += 1;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        self.state.log.write_all(line)?;
+        if self.config.fsync {
+            self.state.log.sync_data()?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether enough records piled up since the last snapshot.
+    pub fn should_snapshot(&self) -> bool {
+        self.state.pending >= self.config.snapshot_every
+    }
+
+    /// Replaces the snapshot with `entries` and `sync` and empties the log.
+    ///
+    /// The snapshot is written to a temporary file, synced and renamed over the old one, so a
+    /// crash at any point leaves either the old or the new snapshot in place. `sync` goes in
+    /// first the same way.
+    pub fn snapshot<T: Serialize>(
+        &mut self,
+        entries: impl IntoIterator<Item = SnapshotEntry<T>>,
+        sync: &SyncState,
+    ) -> io::Result<()> {
+        let sync_tmp_path = self.config.dir.join(SYNC_TMP_FILE);
+        let file = File::create(&sync_tmp_path)?;
+        serde_json::to_writer(&file, sync)?;
+        file.sync_all()?;
+        fs::rename(&sync_tmp_path, self.config.dir.join(SYNC_FILE))?;
+
+        let tmp_path = self.config.dir.join(SNAPSHOT_TMP_FILE);
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+
+        for entry in entries {
+            serde_json::to_writer(&mut writer, &entry)?;
+            writer.write_all(b"\n")?;
+        }
+
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, self.config.dir.join(SNAPSHOT_FILE))?;
+        File::open(&self.config.dir)?.sync_all()?;
+
+        self.state.log.set_len(0)?;
+        self.state.log.sync_all()?;
+        self.state.len = 0;
+        self.state.pending = 0;
+        self.state.broken = false;
+
+        Ok(())
+    }
+}
+
+fn read_snapshot<T: DeserializeOwned>(path: &Path) -> Result<Vec<SnapshotEntry<T>>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+
+    data.split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            serde_json::from_slice(line)
+                .with_context(|| format!("corrupt snapshot {}", path.display()))
+        })
+        .collect()
+}
+
+fn read_sync(path: &Path) -> Result<SyncState> {
+    match fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .with_context(|| format!("corrupt sync state {}", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(SyncState::default()),
+        Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+    }
+}
+
+/// Parses complete records from `data`, returning them with the length of the valid prefix.
+///
+/// Only the last line may be torn, as a crash mid-append leaves at most one partial record
+/// and it is the last one. A bad line with more records after it fails instead.
+fn parse_log<T: DeserializeOwned>(data: &[u8]) -> Result<(Vec<WalRecord<T>>, usize)> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+
+    while let Some(end) = data[offset..].iter().position(|&b| b == b'\n') {
+        let next = offset + end + 1;
+        match serde_json::from_slice(&data[offset..offset + end]) {
+            Ok(record) => records.push(record),
+            Err(_) if next == data.len() => break,
+            Err(e) => bail!("corrupt record at byte {offset}: {e}"),
+        }
+        offset = next;
+    }
+
+    // anything past the last complete record is torn
+    Ok((records, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_log_stops_at_torn_tail() {
+        let data = b"{\"op\":\"remove\",\"key\":\"a\"}\n{\"op\":\"purge\",\"issuer\":\"b\"}\n{\"op\":\"rem";
+        let (records, valid) = parse_log::<serde_json::Value>(data).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(&data[valid..], b"{\"op\":\"rem");
+
+        let data = b"{\"op\":\"remove\",\"key\":\"a\"}\n{\"op\":\"rem\n";
+        let (records, valid) = parse_log::<serde_json::Value>(data).unwrap();
+        assert_eq!((records.len(), valid), (1, 26));
+    }
+
+    #[test]
+    fn parse_log_refuses_corruption_before_the_tail() {
+        let data = b"{\"op\":\"remove\",\"key\":\"a\"}\n{\"op\":\"rem\n{\"op\":\"remove\",\"key\":\"b\"}\n";
+
+        assert!(parse_log::<serde_json::Value>(data).is_err());
+    }
+}
@@ -1,12 +1,15 @@
 use actix_web::{
-    HttpResponse, Responder, post,
+    HttpResponse, Responder, ResponseError, post,
     web::{Data, Json, Path},
 };
 use serde::Deserialize;
 use serde_json::json;
 
 use crate::{
-    AppState, providers::CacheProvider, routes::auth::generate_user_token, structs::user::User,
+    AppState,
+    providers::{CacheProvider, WriteOptions},
+    routes::auth::generate_user_token,
+    structs::user::User,
 };
 
 macros_utils::routes! {
@@ -30,14 +33,18 @@ pub async fn route_user(
     let users = state.users.clone();
     let password_hash = generate_user_token(&username, &password);
 
-    if users.list().await.iter().any(|v| v.1.name == username) {
+    let existing = match users.list().await {
+        Ok(existing) => existing,
+        Err(e) => return e.error_response(),
+    };
+    if existing.iter().any(|v| v.1.name == username) {
         return HttpResponse::BadRequest().json(json!({
             "ok": false,
             "message": "user already exists"
         }));
     }
 
-    users
+    let added = users
         .add(
             username.clone(),
             User {
@@ -45,8 +52,12 @@ pub async fn route_user(
                 password_hash: password_hash.clone(),
             },
             String::from("system"),
+            WriteOptions::default(),
         )
         .await;
+    if let Err(e) = added {
+        return e.error_response();
+    }
 
     HttpResponse::Created().json(json!({
         "ok": true,
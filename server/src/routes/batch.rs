@@ -0,0 +1,174 @@
+use actix_web::{
+    HttpResponse, Responder, ResponseError, post,
+    web::{Data, Json},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{
+    AppState,
+    guards::{auth::AuthUser, bad_request, ttl::parse_ttl},
+    providers::{
+        Precondition, VersionMatch, WriteOptions,
+        batch::{BatchOperation, BatchOutcome},
+    },
+};
+
+macros_utils::routes! {
+    route route_batch,
+}
+
+/// Most operations one batch may hold.
+pub const MAX_OPERATIONS: usize = 1000;
+
+/// One operation of a batch. Keys are written like in store paths, with `/`.
+///
+/// `version` makes the operation require the entry to be at that version; without it,
+/// `check` only requires the entry to exist.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BatchStep {
+    Get {
+        key: String,
+    },
+    Add {
+        key: String,
+        value: Value,
+        ttl: Option<i64>,
+    },
+    Update {
+        key: String,
+        value: Value,
+        ttl: Option<i64>,
+        version: Option<u64>,
+    },
+    Remove {
+        key: String,
+        version: Option<u64>,
+    },
+    Check {
+        key: String,
+        version: Option<u64>,
+    },
+}
+
+/// Runs a list of operations as one transaction: all of them apply, or none do
+///
+/// Answers with one result per operation, in order. When an operation fails, the status is
+/// the one it would have answered on its own, and `data.index` names it.
+#[post("/batch")]
+pub async fn route_batch(
+    steps: Json<Vec<BatchStep>>,
+    state: Data<AppState>,
+    user: AuthUser,
+) -> impl Responder {
+    let steps = steps.into_inner();
+    if steps.len() > MAX_OPERATIONS {
+        return bad_request(&format!(
+            "a batch holds at most {MAX_OPERATIONS} operations"
+        ));
+    }
+
+    let operations = match steps
+        .into_iter()
+        .map(BatchStep::into_operation)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(operations) => operations,
+        Err(msg) => return bad_request(msg),
+    };
+    let count = operations.len();
+
+    match state.provider.transact(operations, user.0.name).await {
+        Ok(Ok(outcomes)) => HttpResponse::Ok().json(json!({
+            "ok": true,
+            "message": "Applied batch",
+            "data": {
+                "results": outcomes.into_iter().map(result).collect::<Vec<_>>()
+            }
+        })),
+        Ok(Err(e)) => {
+            let results: Vec<Value> = (0..count)
+                .map(|index| match index == e.index {
+                    true => json!({ "ok": false, "message": e.error.message() }),
+                    false => json!({ "ok": false, "message": "Not applied" }),
+                })
+                .collect();
+
+            HttpResponse::build(e.error.status_code()).json(json!({
+                "ok": false,
+                "message": format!("Batch operation {} failed: {}", e.index, e.error.message()),
+                "data": {
+                    "index": e.index,
+                    "results": results
+                }
+            }))
+        }
+        Err(e) => e.error_response(),
+    }
+}
+
+impl BatchStep {
+    fn into_operation(self) -> Result<BatchOperation<Value>, &'static str> {
+        Ok(match self {
+            Self::Get { key } => BatchOperation::Get { key: sanitize(key) },
+            Self::Add { key, value, ttl } => BatchOperation::Add {
+                key: sanitize(key),
+                value,
+                options: WriteOptions {
+                    ttl: ttl.map(parse_ttl).transpose()?,
+                    precondition: Precondition::default(),
+                },
+            },
+            Self::Update {
+                key,
+                value,
+                ttl,
+                version,
+            } => BatchOperation::Update {
+                key: sanitize(key),
+                value,
+                options: WriteOptions {
+                    ttl: ttl.map(parse_ttl).transpose()?,
+                    precondition: at_version(version),
+                },
+            },
+            Self::Remove { key, version } => BatchOperation::Remove {
+                key: sanitize(key),
+                precondition: at_version(version),
+            },
+            Self::Check { key, version } => BatchOperation::Check {
+                key: sanitize(key),
+                precondition: Precondition {
+                    if_match: Some(version.map_or(VersionMatch::Any, |version| {
+                        VersionMatch::Versions(vec![version])
+                    })),
+                    if_none_match: None,
+                },
+            },
+        })
+    }
+}
+
+fn sanitize(key: String) -> String {
+    key.replace("/", ":")
+}
+
+fn at_version(version: Option<u64>) -> Precondition {
+    Precondition {
+        if_match: version.map(|version| VersionMatch::Versions(vec![version])),
+        if_none_match: None,
+    }
+}
+
+fn result(outcome: BatchOutcome<Value>) -> Value {
+    match outcome {
+        BatchOutcome::Entry(entry) => json!({
+            "ok": true,
+            "value": entry.value,
+            "version": entry.metadata.version
+        }),
+        BatchOutcome::Written(version) => json!({ "ok": true, "version": version }),
+        BatchOutcome::Removed(_) | BatchOutcome::Checked => json!({ "ok": true }),
+    }
+}
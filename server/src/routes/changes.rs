@@ -0,0 +1,102 @@
+use std::{sync::Arc, time::Duration};
+
+use actix_web::{
+    Error, HttpResponse, Responder, get,
+    http::header::{CacheControl, CacheDirective},
+    web::{Bytes, Data, Query},
+};
+use futures::stream;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+
+use crate::{AppState, guards::path::SanitizedKey, providers::changes::Change};
+
+macros_utils::routes! {
+    route route_changes,
+}
+
+/// How long a quiet stream waits before sending a comment, so dead connections are noticed.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Deserialize)]
+pub struct ChangesQuery {
+    /// Whether events carry the written value along with the metadata.
+    #[serde(default)]
+    values: bool,
+}
+
+/// Streams changes to entries under a prefix as Server-Sent Events
+///
+/// Each event is named after the kind of change (`add`, `update`, `remove`, `purge`,
+/// `expire`, `evict`) and carries `{sequence, key, metadata}` as data, plus `value` with
+/// `?values=true`. A subscriber too slow to keep up gets a `lagged` event telling how many
+/// changes it missed.
+#[get("/changes/{key:.*}")]
+pub async fn route_changes(
+    key: SanitizedKey,
+    query: Query<ChangesQuery>,
+    state: Data<AppState>,
+) -> impl Responder {
+    let feed = Feed {
+        changes: state.provider.changes(),
+        prefix: key.0,
+        values: query.values,
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(stream::unfold(feed, |mut feed| async move {
+            let event = feed.next().await?;
+            Some((Ok::<_, Error>(event), feed))
+        }))
+}
+
+/// One subscriber's view of the change feed.
+struct Feed {
+    changes: Receiver<Arc<Change<Value>>>,
+    prefix: String,
+    values: bool,
+}
+
+impl Feed {
+    /// The next event to send, or `None` once the provider is gone.
+    async fn next(&mut self) -> Option<Bytes> {
+        loop {
+            let received = tokio::select! {
+                received = self.changes.recv() => received,
+                () = tokio::time::sleep(KEEP_ALIVE) => return Some(Bytes::from_static(b":\n\n")),
+            };
+
+            match received {
+                Ok(change) if change.key.starts_with(&self.prefix) => {
+                    return Some(self.event(&change));
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    return Some(sse("lagged", &json!({ "missed": missed })));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    fn event(&self, change: &Change<Value>) -> Bytes {
+        let mut data = json!({
+            "sequence": change.sequence,
+            "key": change.key,
+            "metadata": change.metadata
+        });
+        if self.values {
+            data["value"] = change.value.clone().unwrap_or_default();
+        }
+
+        sse(change.kind.name(), &data)
+    }
+}
+
+/// A Server-Sent Event named `name`. Compact JSON never spans lines.
+fn sse(name: &str, data: &Value) -> Bytes {
+    Bytes::from(format!("event: {name}\ndata: {data}\n\n"))
+}
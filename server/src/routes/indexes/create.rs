@@ -0,0 +1,57 @@
+use actix_web::{
+    HttpResponse, Responder, ResponseError, put,
+    web::{Data, Json, Path},
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    AppState,
+    guards::{auth::AuthUser, bad_request, is_valid_name, pointer::check_pointer},
+    providers::index::IndexDefinition,
+};
+
+macros_utils::routes! {
+    route route_create
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateIndexPayload {
+    /// Key prefix of the indexed entries, with `/` like in store paths.
+    prefix: String,
+    /// JSON Pointer to the indexed value.
+    pointer: String,
+}
+
+/// Declares a secondary index and builds it from the stored entries
+#[put("/{name}")]
+pub async fn route_create(
+    name: Path<String>,
+    payload: Json<CreateIndexPayload>,
+    state: Data<AppState>,
+    _user: AuthUser,
+) -> impl Responder {
+    let name = name.into_inner();
+    let payload = payload.into_inner();
+    if !is_valid_name(&name) {
+        return bad_request("index names are 1 to 64 letters, digits, '-' or '_'");
+    }
+    if let Err(msg) = check_pointer(&payload.pointer) {
+        return bad_request(msg);
+    }
+
+    let index = IndexDefinition {
+        name,
+        prefix: payload.prefix.replace("/", ":"),
+        pointer: payload.pointer,
+    };
+
+    match state.provider.create_index(index).await {
+        Ok(()) => HttpResponse::Created().json(json!({
+            "ok": true,
+            "message": "Created index",
+            "data": {}
+        })),
+        Err(e) => e.error_response(),
+    }
+}
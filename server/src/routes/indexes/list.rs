@@ -0,0 +1,16 @@
+use actix_web::{HttpResponse, Responder, ResponseError, get, web::Data};
+
+use crate::AppState;
+
+macros_utils::routes! {
+    route route_list
+}
+
+/// Lists the declared secondary indexes
+#[get("")]
+pub async fn route_list(state: Data<AppState>) -> impl Responder {
+    match state.provider.indexes().await {
+        Ok(indexes) => HttpResponse::Ok().json(indexes),
+        Err(e) => e.error_response(),
+    }
+}
@@ -0,0 +1,13 @@
+pub mod create;
+pub mod list;
+pub mod rebuild;
+pub mod remove;
+
+macros_utils::routes! {
+    load list,
+    load create, // protected
+    load rebuild, // protected
+    load remove, // protected
+
+    on "/indexes"
+}
@@ -0,0 +1,28 @@
+use actix_web::{
+    HttpResponse, Responder, ResponseError, post,
+    web::{Data, Path},
+};
+use serde_json::json;
+
+use crate::{AppState, guards::auth::AuthUser};
+
+macros_utils::routes! {
+    route route_rebuild
+}
+
+/// Builds an index again from the stored entries
+#[post("/{name}")]
+pub async fn route_rebuild(
+    name: Path<String>,
+    state: Data<AppState>,
+    _user: AuthUser,
+) -> impl Responder {
+    match state.provider.rebuild_index(name.into_inner()).await {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "ok": true,
+            "message": "Rebuilt index",
+            "data": {}
+        })),
+        Err(e) => e.error_response(),
+    }
+}
@@ -0,0 +1,28 @@
+use actix_web::{
+    HttpResponse, Responder, ResponseError, delete,
+    web::{Data, Path},
+};
+use serde_json::json;
+
+use crate::{AppState, guards::auth::AuthUser};
+
+macros_utils::routes! {
+    route route_remove
+}
+
+/// Drops an index. Entries are left untouched
+#[delete("/{name}")]
+pub async fn route_remove(
+    name: Path<String>,
+    state: Data<AppState>,
+    _user: AuthUser,
+) -> impl Responder {
+    match state.provider.drop_index(name.into_inner()).await {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "ok": true,
+            "message": "Dropped index",
+            "data": {}
+        })),
+        Err(e) => e.error_response(),
+    }
+}
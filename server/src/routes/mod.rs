@@ -1,9 +1,21 @@
 pub mod auth;
+pub mod batch;
+pub mod changes;
+pub mod indexes;
 pub mod root;
+pub mod stats;
 pub mod store;
+pub mod sync;
+pub mod webhooks;
 
 macros_utils::routes! {
     load root,
+    load stats,
     load auth,
+    load batch,
+    load changes,
+    load indexes,
+    load sync,
+    load webhooks,
     load store
 }
@@ -0,0 +1,18 @@
+use actix_web::{HttpResponse, Responder, get, web::Data};
+use serde_json::json;
+
+use crate::AppState;
+
+macros_utils::routes! {
+    route route_stats,
+}
+
+/// Reports usage figures of the active cache provider.
+#[get("/stats")]
+pub async fn route_stats(state: Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(json!({
+        "ok": true,
+        "message": "provider stats",
+        "data": state.provider.stats().await
+    }))
+}
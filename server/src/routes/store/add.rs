@@ -1,13 +1,18 @@
 use actix_web::{
-    HttpResponse, Responder, put,
+    HttpResponse, Responder, ResponseError, put,
     web::{Data, Json},
 };
 use serde_json::{Value, json};
 
 use crate::{
     AppState,
-    guards::{auth::AuthUser, path::SanitizedKey},
-    providers::CacheProvider,
+    guards::{
+        auth::AuthUser,
+        path::SanitizedKey,
+        precondition::{Conditional, etag},
+        ttl::Ttl,
+    },
+    providers::WriteOptions,
 };
 
 macros_utils::routes! {
@@ -18,23 +23,28 @@ macros_utils::routes! {
 pub async fn route_add(
     key: SanitizedKey,
     value: Json<Value>,
+    ttl: Ttl,
+    conditional: Conditional,
     state: Data<AppState>,
     user: AuthUser,
 ) -> impl Responder {
+    let options = WriteOptions {
+        ttl: ttl.0,
+        precondition: conditional.0,
+    };
+
     match state
         .provider
-        .add(key.0.clone(), value.into_inner(), user.0.name)
+        .add(key.0.clone(), value.into_inner(), user.0.name, options)
         .await
     {
-        Some(_) => HttpResponse::Created().json(json!({
-            "ok": true,
-            "message": "Created cache entry",
-            "data": {}
-        })),
-        None => HttpResponse::BadRequest().json(json!({
-            "ok": false,
-            "message": "This entry already exists",
-            "data": {}
-        })),
+        Ok(version) => HttpResponse::Created()
+            .insert_header(etag(version))
+            .json(json!({
+                "ok": true,
+                "message": "Created cache entry",
+                "data": {}
+            })),
+        Err(e) => e.error_response(),
     }
 }
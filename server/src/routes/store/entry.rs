@@ -1,20 +1,30 @@
-use actix_web::{HttpResponse, Responder, get, web::Data};
-use serde_json::json;
+use actix_web::{HttpResponse, Responder, ResponseError, get, web::Data};
 
-use crate::{AppState, guards::path::SanitizedKey, providers::CacheProvider};
+use crate::{
+    AppState,
+    guards::{path::SanitizedKey, pointer::Selection, precondition::etag},
+};
 
 macros_utils::routes! {
     route route_entry
 }
 
+/// Fetches a value, or the parts of it picked with `?pointer=` or `?field=`
 #[get("/{key:.*}")]
-pub async fn route_entry(key: SanitizedKey, state: Data<AppState>) -> impl Responder {
-    match state.provider.entry(key.0).await {
-        Some(entry) => HttpResponse::Ok().json(entry),
-        None => HttpResponse::NotFound().json(json!({
-            "ok": false,
-            "message": "This entry does not exist",
-            "data": {}
-        })),
+pub async fn route_entry(
+    key: SanitizedKey,
+    selection: Selection,
+    state: Data<AppState>,
+) -> impl Responder {
+    let entry = match state.provider.entry(key.0).await {
+        Ok(entry) => entry,
+        Err(e) => return e.error_response(),
+    };
+
+    match selection.select(entry.value) {
+        Ok(value) => HttpResponse::Ok()
+            .insert_header(etag(entry.metadata.version))
+            .json(value),
+        Err(e) => e.error_response(),
     }
 }
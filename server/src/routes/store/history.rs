@@ -0,0 +1,49 @@
+use actix_web::{
+    HttpResponse, Responder, ResponseError, get,
+    web::{Data, Path},
+};
+
+use crate::{
+    AppState,
+    guards::{
+        path::{Revision, SanitizedKey},
+        pointer::Selection,
+        precondition::etag,
+    },
+};
+
+macros_utils::routes! {
+    route route_history,
+    route route_revision
+}
+
+/// Lists the metadata of every kept revision, newest first
+#[get("/{key:.*}@")]
+pub async fn route_history(key: SanitizedKey, state: Data<AppState>) -> impl Responder {
+    match state.provider.history(key.0).await {
+        Ok(revisions) => HttpResponse::Ok().json(revisions),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Fetches the value of a past revision, or the parts of it picked with `?pointer=` or
+/// `?field=`
+#[get("/{key:.*}@{version:\\d+}")]
+pub async fn route_revision(
+    key: SanitizedKey,
+    revision: Path<Revision>,
+    selection: Selection,
+    state: Data<AppState>,
+) -> impl Responder {
+    let entry = match state.provider.revision(key.0, revision.version).await {
+        Ok(entry) => entry,
+        Err(e) => return e.error_response(),
+    };
+
+    match selection.select(entry.value) {
+        Ok(value) => HttpResponse::Ok()
+            .insert_header(etag(entry.metadata.version))
+            .json(value),
+        Err(e) => e.error_response(),
+    }
+}
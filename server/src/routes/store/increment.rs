@@ -0,0 +1,82 @@
+use actix_web::{
+    HttpResponse, Responder, ResponseError, post,
+    web::{Data, Json},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{
+    AppState,
+    guards::{
+        auth::AuthUser,
+        bad_request,
+        path::SanitizedKey,
+        pointer::check_pointer,
+        precondition::{Conditional, etag},
+        ttl::Ttl,
+    },
+    providers::{WriteOptions, increment},
+    structs::counter::Increment,
+};
+
+macros_utils::routes! {
+    route route_increment
+}
+
+/// Body of an increment, such as `{"pointer": "/downloads", "by": 1}`.
+#[derive(Debug, Deserialize)]
+pub struct IncrementBody {
+    #[serde(flatten)]
+    pub increment: Increment,
+    /// Value to create the entry with if it does not exist, before the increment applies.
+    pub initial: Option<Value>,
+}
+
+/// Atomically adds to the number at a pointer inside an entry and returns the new number
+///
+/// Answers `201` when the entry was created from `initial`.
+#[post("/{key:.*}")]
+pub async fn route_increment(
+    key: SanitizedKey,
+    body: Json<IncrementBody>,
+    ttl: Ttl,
+    conditional: Conditional,
+    state: Data<AppState>,
+    user: AuthUser,
+) -> impl Responder {
+    let body = body.into_inner();
+    let pointer = body.increment.pointer.clone();
+    if let Err(msg) = check_pointer(&pointer) {
+        return bad_request(msg);
+    }
+
+    let options = WriteOptions {
+        ttl: ttl.0,
+        precondition: conditional.0,
+    };
+    let incremented = increment(
+        &*state.provider,
+        key.0,
+        user.0.name,
+        options,
+        &body.increment,
+        body.initial.as_ref(),
+    )
+    .await;
+
+    match incremented {
+        Ok(Ok((version, value))) => {
+            let mut response = match version {
+                0 => HttpResponse::Created(),
+                _ => HttpResponse::Ok(),
+            };
+            response.insert_header(etag(version)).json(json!({
+                "ok": true,
+                "message": "Incremented cache entry",
+                "data": value.pointer(&pointer)
+            }))
+        }
+        Ok(Err(e)) => e.error_response(),
+        Err(e) => e.error_response(),
+    }
+}
@@ -1,23 +1,35 @@
-use actix_web::{HttpResponse, Responder, get, web::Data};
+use actix_web::{HttpResponse, Responder, ResponseError, get, web::Data};
 
-use crate::{AppState, guards::path::SanitizedKey, providers::CacheProvider};
+use crate::{
+    AppState,
+    guards::{
+        page::{NEXT_CURSOR_HEADER, Page, cursor},
+        path::SanitizedKey,
+    },
+};
 
 macros_utils::routes! {
     route route_list
 }
 
-/// If the cache route ends in `/`, returns a list of entries starting with the key
+/// If the cache route ends in `/`, returns a sorted list of keys starting with the key
+///
+/// With `?limit=`, the cursor of the next page is sent in the `X-Next-Cursor` header.
 #[get("/{key:.*}/")]
-pub async fn route_list(key: SanitizedKey, state: Data<AppState>) -> impl Responder {
-    let list = state.provider.list().await;
+pub async fn route_list(key: SanitizedKey, page: Page, state: Data<AppState>) -> impl Responder {
+    let listed = state
+        .provider
+        .list_keys(key.0, page.after, page.limit)
+        .await;
 
-    let entries: Vec<&String> = if key.0.is_empty() {
-        list.iter().map(|(k, _)| k).collect()
-    } else {
-        list.iter()
-            .filter_map(|(k, _)| k.starts_with(&key.0).then_some(k))
-            .collect()
-    };
-
-    HttpResponse::Ok().json(entries)
+    match listed {
+        Ok(listed) => {
+            let mut response = HttpResponse::Ok();
+            if let Some(next) = &listed.next {
+                response.insert_header((NEXT_CURSOR_HEADER, cursor(next)));
+            }
+            response.json(listed.keys)
+        }
+        Err(e) => e.error_response(),
+    }
 }
@@ -1,7 +1,9 @@
-use actix_web::{HttpResponse, Responder, get, web::Data};
-use serde_json::json;
+use actix_web::{HttpResponse, Responder, ResponseError, get, web::Data};
 
-use crate::{AppState, guards::path::SanitizedKey, providers::CacheProvider};
+use crate::{
+    AppState,
+    guards::{path::SanitizedKey, precondition::etag},
+};
 
 macros_utils::routes! {
     route route_metadata,
@@ -10,11 +12,9 @@ macros_utils::routes! {
 #[get("/{key:.*}$")]
 pub async fn route_metadata(key: SanitizedKey, state: Data<AppState>) -> impl Responder {
     match state.provider.metadata(key.0).await {
-        Some(metadata) => HttpResponse::Ok().json(metadata),
-        None => HttpResponse::NotFound().json(json!({
-            "ok": false,
-            "message": "This entry does not exist",
-            "data": {}
-        })),
+        Ok(metadata) => HttpResponse::Ok()
+            .insert_header(etag(metadata.version))
+            .json(metadata),
+        Err(e) => e.error_response(),
     }
 }
@@ -1,17 +1,25 @@
 pub mod add;
 pub mod entry;
+pub mod history;
+pub mod increment;
 pub mod list;
 pub mod metadata;
 pub mod purge;
+pub mod query;
 pub mod remove;
+pub mod rollback;
 pub mod update;
 
 macros_utils::routes! {
     load purge, // protected
     load update, // protected
     load add, // protected
+    load rollback, // protected
     load list,
+    load query,
+    load increment, // protected
     load metadata,
+    load history,
     load entry,
     load remove, // protected
 
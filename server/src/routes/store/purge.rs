@@ -1,7 +1,7 @@
-use actix_web::{HttpResponse, Responder, delete, web::Data};
+use actix_web::{HttpResponse, Responder, ResponseError, delete, web::Data};
 use serde_json::json;
 
-use crate::{AppState, guards::auth::AuthUser, providers::CacheProvider};
+use crate::{AppState, guards::auth::AuthUser};
 
 macros_utils::routes! {
     route route_purge
@@ -10,11 +10,13 @@ macros_utils::routes! {
 #[delete("/!")]
 pub async fn route_purge(state: Data<AppState>, user: AuthUser) -> impl Responder {
     let cache = state.provider.clone();
-    cache.purge(user.0.name).await;
 
-    HttpResponse::Ok().json(json!({
-        "ok": true,
-        "message": "purged all entries owned by this user",
-        "data": {}
-    }))
+    match cache.purge(user.0.name).await {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "ok": true,
+            "message": "purged all entries owned by this user",
+            "data": {}
+        })),
+        Err(e) => e.error_response(),
+    }
 }
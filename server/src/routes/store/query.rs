@@ -0,0 +1,197 @@
+use std::{cmp::Ordering, ops::Bound};
+
+use actix_web::{
+    HttpResponse, Responder, ResponseError, post,
+    web::{Data, Json},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{
+    AppState,
+    guards::{bad_request, page::MAX_PAGE_SIZE, path::SanitizedKey},
+    providers::{
+        CacheProvider, ProviderError, ProviderResult,
+        index::{IndexRange, IndexValue},
+        scan,
+    },
+    structs::{
+        entry::Entry,
+        filter::{Comparison, Filter, SortKey},
+    },
+};
+
+macros_utils::routes! {
+    route route_query
+}
+
+/// Body of a query. Every field is optional; an empty body returns every entry.
+#[derive(Debug, Deserialize)]
+pub struct EntryQuery {
+    /// Filter expression values must pass, see [`Filter`].
+    #[serde(rename = "where")]
+    pub filter: Option<String>,
+    /// Sort keys, most significant first, such as `-/stars`. Ties keep key order.
+    #[serde(default)]
+    pub sort: Vec<String>,
+    /// Most entries to return, up to [`MAX_PAGE_SIZE`].
+    pub limit: Option<usize>,
+}
+
+/// Returns the entries under a prefix whose values pass a filter, as `{key, value}` objects
+///
+/// When an index covers the prefix and the filter compares its pointer, only the entries the
+/// index returns are read. Otherwise values are read one by one, at the cost of a full scan of
+/// the prefix.
+#[post("/{key:.*}/")]
+pub async fn route_query(
+    key: SanitizedKey,
+    query: Json<EntryQuery>,
+    state: Data<AppState>,
+) -> impl Responder {
+    let query = query.into_inner();
+
+    let filter = match query.filter.as_deref().map(Filter::parse).transpose() {
+        Ok(filter) => filter,
+        Err(e) => return e.error_response(),
+    };
+    let Some(sort) = query
+        .sort
+        .iter()
+        .map(|raw| SortKey::parse(raw))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return bad_request("sort keys must be pointers, optionally prefixed by '-'");
+    };
+    if query
+        .limit
+        .is_some_and(|limit| !(1..=MAX_PAGE_SIZE).contains(&limit))
+    {
+        return bad_request(&format!("limit must be between 1 and {MAX_PAGE_SIZE}"));
+    }
+
+    // unsorted queries are done once the limit is reached
+    let stop_at = query.limit.filter(|_| sort.is_empty());
+    let mut matches = Vec::new();
+    let mut visit = |key, entry: Entry<Value>| {
+        if filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(&entry.value))
+        {
+            matches.push((key, entry.value));
+        }
+        stop_at.is_none_or(|limit| matches.len() < limit)
+    };
+
+    let indexed = match &filter {
+        Some(filter) => indexed_scan(&*state.provider, &key.0, filter, &mut visit).await,
+        None => Ok(false),
+    };
+    let scanned = match indexed {
+        Ok(true) => Ok(()),
+        Ok(false) => scan(&*state.provider, key.0, visit).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = scanned {
+        return e.error_response();
+    }
+
+    matches.sort_by(|(_, a), (_, b)| {
+        sort.iter()
+            .map(|key| key.compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+    if let Some(limit) = query.limit {
+        matches.truncate(limit);
+    }
+
+    HttpResponse::Ok().json(
+        matches
+            .into_iter()
+            .map(|(key, value)| json!({ "key": key, "value": value }))
+            .collect::<Vec<Value>>(),
+    )
+}
+
+/// Visits the entries under `prefix` an index returns for `filter`, in key order.
+///
+/// Returns `false`, having visited nothing, when no ready index can narrow the query down.
+async fn indexed_scan(
+    provider: &dyn CacheProvider<Value>,
+    prefix: &str,
+    filter: &Filter,
+    mut visit: impl FnMut(String, Entry<Value>) -> bool,
+) -> ProviderResult<bool> {
+    let conjuncts = filter.conjuncts();
+    let usable = provider.indexes().await?.into_iter().filter_map(|index| {
+        if !prefix.starts_with(&index.prefix) {
+            return None;
+        }
+        index_range(&conjuncts, &index.pointer).map(|range| (index.name, range))
+    });
+
+    let mut candidates = None;
+    for (name, range) in usable {
+        match provider.lookup(name, range).await {
+            Ok(keys) => {
+                candidates = Some(keys);
+                break;
+            }
+            // being built or dropped meanwhile, another index or a scan will do
+            Err(ProviderError::IndexBuilding | ProviderError::IndexNotFound) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    let Some(mut keys) = candidates else {
+        return Ok(false);
+    };
+
+    keys.retain(|key| key.starts_with(prefix));
+    keys.sort_unstable();
+    for key in keys {
+        match provider.entry(key.clone()).await {
+            Ok(entry) => {
+                if !visit(key, entry) {
+                    break;
+                }
+            }
+            Err(ProviderError::NotFound) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(true)
+}
+
+/// The values at `pointer` the conjuncts allow, or `None` if they do not bound it.
+///
+/// The range may hold more than the filter does, values of another type for one; callers still
+/// check every entry against the filter.
+fn index_range(conjuncts: &[(&str, Comparison, &Value)], pointer: &str) -> Option<IndexRange> {
+    let mut range = IndexRange {
+        lower: Bound::Unbounded,
+        upper: Bound::Unbounded,
+    };
+
+    for (_, op, value) in conjuncts.iter().filter(|(p, ..)| *p == pointer) {
+        let Some(value) = IndexValue::from_json(value) else {
+            continue;
+        };
+        match op {
+            Comparison::Eq => return Some(IndexRange::eq(value)),
+            Comparison::Gt => range.lower = Bound::Excluded(value),
+            Comparison::Ge => range.lower = Bound::Included(value),
+            Comparison::Lt => range.upper = Bound::Excluded(value),
+            Comparison::Le => range.upper = Bound::Included(value),
+            Comparison::Ne => {}
+        }
+    }
+
+    let bounded = !matches!(
+        (&range.lower, &range.upper),
+        (Bound::Unbounded, Bound::Unbounded)
+    );
+    bounded.then_some(range)
+}
@@ -1,10 +1,9 @@
-use actix_web::{HttpResponse, Responder, delete, web::Data};
+use actix_web::{HttpResponse, Responder, ResponseError, delete, web::Data};
 use serde_json::json;
 
 use crate::{
     AppState,
-    guards::{auth::AuthUser, path::SanitizedKey},
-    providers::CacheProvider,
+    guards::{auth::AuthUser, path::SanitizedKey, precondition::Conditional},
 };
 
 macros_utils::routes! {
@@ -12,19 +11,20 @@ macros_utils::routes! {
 }
 
 #[delete("/{key:.*}")]
-pub async fn route_remove(key: SanitizedKey, state: Data<AppState>, _: AuthUser) -> impl Responder {
+pub async fn route_remove(
+    key: SanitizedKey,
+    conditional: Conditional,
+    state: Data<AppState>,
+    _: AuthUser,
+) -> impl Responder {
     let cache = &state.provider;
 
-    match cache.remove(key.0).await {
-        Some(_) => HttpResponse::Ok().json(json!({
+    match cache.remove(key.0, conditional.0).await {
+        Ok(_) => HttpResponse::Ok().json(json!({
             "ok": true,
             "message": "Deleted cache entry",
             "data": {}
         })),
-        None => HttpResponse::NotFound().json(json!({
-            "ok": false,
-            "message": "This entry does not exist",
-            "data": {}
-        })),
+        Err(e) => e.error_response(),
     }
 }
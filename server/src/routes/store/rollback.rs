@@ -0,0 +1,41 @@
+use actix_web::{
+    HttpResponse, Responder, ResponseError, post,
+    web::{Data, Path},
+};
+use serde_json::json;
+
+use crate::{
+    AppState,
+    guards::{
+        auth::AuthUser,
+        path::{Revision, SanitizedKey},
+        precondition::{Conditional, etag},
+    },
+};
+
+macros_utils::routes! {
+    route route_rollback
+}
+
+/// Writes the value of a past revision again, as a new revision
+#[post("/{key:.*}@{version:\\d+}")]
+pub async fn route_rollback(
+    key: SanitizedKey,
+    revision: Path<Revision>,
+    conditional: Conditional,
+    state: Data<AppState>,
+    user: AuthUser,
+) -> impl Responder {
+    match state
+        .provider
+        .rollback(key.0, revision.version, user.0.name, conditional.0)
+        .await
+    {
+        Ok(version) => HttpResponse::Ok().insert_header(etag(version)).json(json!({
+            "ok": true,
+            "message": "Rolled back cache entry",
+            "data": {}
+        })),
+        Err(e) => e.error_response(),
+    }
+}
@@ -1,39 +1,70 @@
-use actix_web::{
-    HttpResponse, Responder, patch,
-    web::{Data, Json},
-};
-use serde_json::{Value, json};
+use actix_web::{HttpResponse, Responder, ResponseError, patch, web::Data};
+use serde_json::json;
 
 use crate::{
     AppState,
-    guards::{auth::AuthUser, path::SanitizedKey},
-    providers::CacheProvider,
+    guards::{
+        auth::AuthUser,
+        patch::PatchBody,
+        path::SanitizedKey,
+        precondition::{Conditional, etag},
+        ttl::Ttl,
+    },
+    providers::{WriteOptions, modify},
+    structs::patch::{PatchError, merge_patch},
 };
 
 macros_utils::routes! {
     route route_upsert
 }
 
+/// Updates an entry, replacing its value or patching it depending on the `Content-Type`
+///
+/// Patches apply to the value as stored when the write happens; see [`PatchBody`].
 #[patch("/{key:.*}")]
 pub async fn route_upsert(
     key: SanitizedKey,
-    value: Json<Value>,
+    body: PatchBody,
+    ttl: Ttl,
+    conditional: Conditional,
     state: Data<AppState>,
     user: AuthUser,
 ) -> impl Responder {
     let cache = state.provider.clone();
     let username = user.0.name;
+    let options = WriteOptions {
+        ttl: ttl.0,
+        precondition: conditional.0,
+    };
+
+    let updated = match body {
+        PatchBody::Replace(value) => cache
+            .update(key.0, value.clone(), username, options)
+            .await
+            .map(|version| Ok((version, value))),
+        PatchBody::Merge(patch) => {
+            modify(&*cache, key.0, username, options, |current| {
+                let mut value = current.clone();
+                merge_patch(&mut value, &patch);
+                Ok::<_, PatchError>(value)
+            })
+            .await
+        }
+        PatchBody::Operations(patch) => {
+            modify(&*cache, key.0, username, options, |current| {
+                patch.apply(current)
+            })
+            .await
+        }
+    };
 
-    return match cache.update(key.0, value.0, username).await {
-        Some(value) => HttpResponse::Ok().json(json!({
+    match updated {
+        Ok(Ok((version, value))) => HttpResponse::Ok().insert_header(etag(version)).json(json!({
             "ok": true,
             "message": "updated entry",
             "data": value
         })),
-        None => HttpResponse::BadRequest().json(json!({
-            "ok": false,
-            "message": "entry does not exist",
-            "data": {}
-        })),
-    };
+        Ok(Err(e)) => e.error_response(),
+        Err(e) => e.error_response(),
+    }
 }
@@ -0,0 +1,80 @@
+use actix_web::{
+    HttpResponse, Responder, ResponseError, get,
+    web::{Data, Query},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{
+    AppState,
+    guards::{bad_request, page::MAX_PAGE_SIZE, path::SanitizedKey},
+    providers::sync::SyncChange,
+};
+
+macros_utils::routes! {
+    route route_sync,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncQuery {
+    /// Sequence number the client has seen everything up to, 0 to start over.
+    #[serde(default)]
+    after: u64,
+    limit: Option<usize>,
+}
+
+/// Returns the changes to entries under a prefix after a sequence number, in sequence order
+///
+/// Each key appears once with its last change: `{sequence, key, event, value, metadata}` for
+/// a written entry, `{sequence, key, event, deleted_at}` for a removed one. Ask again with
+/// `?after=<cursor>` to continue, right away while `more` is set. A cursor older than the
+/// kept tombstones answers 410, and the client syncs again from 0.
+#[get("/sync/{key:.*}")]
+pub async fn route_sync(
+    key: SanitizedKey,
+    query: Query<SyncQuery>,
+    state: Data<AppState>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(MAX_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return bad_request(&format!("limit must be between 1 and {MAX_PAGE_SIZE}"));
+    }
+
+    match state
+        .provider
+        .changes_since(key.0, query.after, limit)
+        .await
+    {
+        Ok(page) => HttpResponse::Ok().json(json!({
+            "ok": true,
+            "message": "Changes found",
+            "data": {
+                "changes": page.changes.into_iter().map(change).collect::<Vec<_>>(),
+                "cursor": page.cursor,
+                "more": page.more
+            }
+        })),
+        Err(e) => e.error_response(),
+    }
+}
+
+fn change(change: SyncChange<Value>) -> Value {
+    match change {
+        SyncChange::Written { key, entry } => json!({
+            "sequence": entry.metadata.sequence,
+            "key": key,
+            "event": match entry.metadata.version {
+                0 => "add",
+                _ => "update",
+            },
+            "value": entry.value,
+            "metadata": entry.metadata
+        }),
+        SyncChange::Removed(tombstone) => json!({
+            "sequence": tombstone.sequence,
+            "key": tombstone.key,
+            "event": tombstone.kind.name(),
+            "deleted_at": tombstone.at
+        }),
+    }
+}
@@ -0,0 +1,69 @@
+use actix_web::{
+    HttpResponse, Responder, ResponseError, put,
+    web::{Data, Json, Path},
+};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::describe;
+use crate::{
+    AppState,
+    guards::{auth::AuthUser, bad_request, is_valid_name},
+    structs::webhook::{Webhook, is_valid_secret},
+    webhooks::client::Endpoint,
+};
+
+macros_utils::routes! {
+    route route_create
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookPayload {
+    /// Key prefix of the watched entries, with `/` like in store paths.
+    prefix: String,
+    /// Plain `http://` URL events are posted to.
+    url: String,
+    /// Key deliveries are signed with.
+    secret: String,
+}
+
+/// Registers a webhook told about every change to entries under a prefix
+#[put("/{name}")]
+pub async fn route_create(
+    name: Path<String>,
+    payload: Json<CreateWebhookPayload>,
+    state: Data<AppState>,
+    user: AuthUser,
+) -> impl Responder {
+    let name = name.into_inner();
+    let payload = payload.into_inner();
+    if !is_valid_name(&name) {
+        return bad_request("webhook names are 1 to 64 letters, digits, '-' or '_'");
+    }
+    if Endpoint::parse(&payload.url).is_none() {
+        return bad_request("webhook urls must be plain http:// urls");
+    }
+    if !is_valid_secret(&payload.secret) {
+        return bad_request("webhook secrets are 1 to 64 bytes long");
+    }
+
+    let hook = Webhook {
+        name,
+        prefix: payload.prefix.replace("/", ":"),
+        url: payload.url,
+        secret: payload.secret,
+        created_by: user.0.name,
+        created_at: Utc::now(),
+    };
+    let data = describe(&hook);
+
+    match state.webhooks.register(hook).await {
+        Ok(()) => HttpResponse::Created().json(json!({
+            "ok": true,
+            "message": "Created webhook",
+            "data": data
+        })),
+        Err(e) => e.error_response(),
+    }
+}
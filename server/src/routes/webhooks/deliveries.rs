@@ -0,0 +1,23 @@
+use actix_web::{
+    HttpResponse, Responder, ResponseError, get,
+    web::{Data, Path},
+};
+
+use crate::{AppState, guards::auth::AuthUser};
+
+macros_utils::routes! {
+    route route_deliveries
+}
+
+/// Lists the recent delivery attempts of a webhook, newest first
+#[get("/{name}/deliveries")]
+pub async fn route_deliveries(
+    name: Path<String>,
+    state: Data<AppState>,
+    _user: AuthUser,
+) -> impl Responder {
+    match state.webhooks.deliveries(&name) {
+        Ok(attempts) => HttpResponse::Ok().json(attempts),
+        Err(e) => e.error_response(),
+    }
+}
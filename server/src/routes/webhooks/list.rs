@@ -0,0 +1,21 @@
+use actix_web::{HttpResponse, Responder, get, web::Data};
+
+use super::describe;
+use crate::{AppState, guards::auth::AuthUser};
+
+macros_utils::routes! {
+    route route_list
+}
+
+/// Lists the registered webhooks, without their secrets
+#[get("")]
+pub async fn route_list(state: Data<AppState>, _user: AuthUser) -> impl Responder {
+    let hooks: Vec<_> = state
+        .webhooks
+        .list()
+        .iter()
+        .map(|hook| describe(hook))
+        .collect();
+
+    HttpResponse::Ok().json(hooks)
+}
@@ -0,0 +1,28 @@
+use serde_json::{Value, json};
+
+use crate::structs::webhook::Webhook;
+
+pub mod create;
+pub mod deliveries;
+pub mod list;
+pub mod remove;
+
+macros_utils::routes! {
+    load list, // protected
+    load create, // protected
+    load deliveries, // protected
+    load remove, // protected
+
+    on "/webhooks"
+}
+
+/// What clients get to see of a webhook: everything but its secret.
+fn describe(hook: &Webhook) -> Value {
+    json!({
+        "name": hook.name,
+        "prefix": hook.prefix,
+        "url": hook.url,
+        "created_by": hook.created_by,
+        "created_at": hook.created_at
+    })
+}
@@ -0,0 +1,28 @@
+use actix_web::{
+    HttpResponse, Responder, ResponseError, delete,
+    web::{Data, Path},
+};
+use serde_json::json;
+
+use crate::{AppState, guards::auth::AuthUser};
+
+macros_utils::routes! {
+    route route_remove
+}
+
+/// Removes a webhook. Deliveries still being retried are dropped
+#[delete("/{name}")]
+pub async fn route_remove(
+    name: Path<String>,
+    state: Data<AppState>,
+    _user: AuthUser,
+) -> impl Responder {
+    match state.webhooks.remove(&name).await {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "ok": true,
+            "message": "Removed webhook",
+            "data": {}
+        })),
+        Err(e) => e.error_response(),
+    }
+}
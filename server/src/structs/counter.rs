@@ -0,0 +1,165 @@
+use std::fmt;
+
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use serde::Deserialize;
+use serde_json::{Number, Value, json};
+
+/// Adds `by` to the number at `pointer` (RFC 6901) inside a value. A negative `by` decrements.
+///
+/// Integers stay integers as long as both sides are; anything else is added as `f64`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Increment {
+    pub pointer: String,
+    #[serde(default = "one")]
+    pub by: Number,
+}
+
+/// Why an increment does not apply to a value. Each case names the pointer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IncrementError {
+    /// Nothing exists at the pointer.
+    PathNotFound(String),
+    /// What the pointer points at is not a number.
+    NotANumber(String),
+    /// The sum does not fit a JSON number the server can represent.
+    Overflow(String),
+}
+
+fn one() -> Number {
+    Number::from(1)
+}
+
+impl Increment {
+    /// `document` with the increment applied, and the number it now holds at the pointer.
+    pub fn apply(&self, document: &Value) -> Result<(Value, Number), IncrementError> {
+        let mut document = document.clone();
+        let target = document
+            .pointer_mut(&self.pointer)
+            .ok_or_else(|| IncrementError::PathNotFound(self.pointer.clone()))?;
+        let Value::Number(current) = target else {
+            return Err(IncrementError::NotANumber(self.pointer.clone()));
+        };
+
+        let sum =
+            add(current, &self.by).ok_or_else(|| IncrementError::Overflow(self.pointer.clone()))?;
+        *target = Value::Number(sum.clone());
+
+        Ok((document, sum))
+    }
+}
+
+/// `a + b`, or `None` if the sum cannot be represented.
+fn add(a: &Number, b: &Number) -> Option<Number> {
+    let integer = |n: &Number| n.as_i64().map(i128::from).or(n.as_u64().map(i128::from));
+
+    match (integer(a), integer(b)) {
+        (Some(a), Some(b)) => {
+            let sum = a.checked_add(b)?;
+            i64::try_from(sum)
+                .map(Number::from)
+                .or(u64::try_from(sum).map(Number::from))
+                .ok()
+        }
+        _ => Number::from_f64(a.as_f64()? + b.as_f64()?),
+    }
+}
+
+impl IncrementError {
+    fn pointer(&self) -> &str {
+        match self {
+            Self::PathNotFound(pointer) | Self::NotANumber(pointer) | Self::Overflow(pointer) => {
+                pointer
+            }
+        }
+    }
+}
+
+impl fmt::Display for IncrementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PathNotFound(pointer) => write!(f, "path {pointer:?} does not exist"),
+            Self::NotANumber(pointer) => write!(f, "path {pointer:?} does not hold a number"),
+            Self::Overflow(pointer) => write!(f, "number at {pointer:?} would overflow"),
+        }
+    }
+}
+
+impl std::error::Error for IncrementError {}
+
+/// A missing path answers `404` like a missing entry, a value that cannot be incremented
+/// `409`. The pointer is in `data.pointer`.
+impl ResponseError for IncrementError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::PathNotFound(_) => StatusCode::NOT_FOUND,
+            Self::NotANumber(_) | Self::Overflow(_) => StatusCode::CONFLICT,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let message = match self {
+            Self::PathNotFound(_) => "This path does not exist in the entry",
+            Self::NotANumber(_) => "This path does not hold a number",
+            Self::Overflow(_) => "The result is out of range",
+        };
+
+        HttpResponse::build(self.status_code()).json(json!({
+            "ok": false,
+            "message": message,
+            "data": { "pointer": self.pointer() }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn increment(pointer: &str, by: Value) -> Increment {
+        serde_json::from_value(json!({ "pointer": pointer, "by": by })).unwrap()
+    }
+
+    #[test]
+    fn adds_at_pointer() {
+        let document = json!({"downloads": 41, "rate": {"bucket": 0.5}});
+
+        let (document, sum) = increment("/downloads", json!(1)).apply(&document).unwrap();
+        assert_eq!(sum, Number::from(42));
+        assert_eq!(document["downloads"], json!(42));
+
+        let (document, sum) = increment("/rate/bucket", json!(-1.5))
+            .apply(&document)
+            .unwrap();
+        assert_eq!(sum.as_f64(), Some(-1.0));
+        assert_eq!(document, json!({"downloads": 42, "rate": {"bucket": -1.0}}));
+
+        let by_default: Increment = serde_json::from_value(json!({"pointer": ""})).unwrap();
+        assert_eq!(by_default.apply(&json!(7)).unwrap().0, json!(8));
+        assert_eq!(
+            increment("", json!(1)).apply(&json!(i64::MAX)).unwrap().0,
+            json!(i64::MAX as u64 + 1)
+        );
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_add_to() {
+        let document = json!({"name": "x", "max": u64::MAX, "floats": [f64::MAX]});
+
+        assert_eq!(
+            increment("/missing", json!(1)).apply(&document),
+            Err(IncrementError::PathNotFound("/missing".into()))
+        );
+        assert_eq!(
+            increment("/name", json!(1)).apply(&document),
+            Err(IncrementError::NotANumber("/name".into()))
+        );
+        assert_eq!(
+            increment("/max", json!(1)).apply(&document),
+            Err(IncrementError::Overflow("/max".into()))
+        );
+        assert_eq!(
+            increment("/floats/0", json!(f64::MAX)).apply(&document),
+            Err(IncrementError::Overflow("/floats/0".into()))
+        );
+    }
+}
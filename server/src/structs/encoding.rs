@@ -0,0 +1,11 @@
+use std::fmt::Write;
+
+/// Lowercase hex encoding of `bytes`, two digits per byte.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut out, byte| {
+            let _ = write!(out, "{byte:02x}");
+            out
+        })
+}
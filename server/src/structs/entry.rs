@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+use super::metadata::Metadata;
+
+/// A cached value together with its metadata.
+///
+/// Providers store, read and delete the two as one record, so a value is never seen with
+/// the metadata of another write.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry<T> {
+    pub value: T,
+    pub metadata: Metadata,
+}
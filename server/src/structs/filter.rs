@@ -0,0 +1,481 @@
+use std::{cmp::Ordering, fmt};
+
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use serde_json::{Value, json};
+
+use super::json::equal;
+
+/// A filter over JSON values, parsed from expressions such as
+/// `/stars > 100 and (/lang == "rust" or not /archived)`.
+///
+/// Operands are JSON Pointers (RFC 6901) into the value, compared with JSON literals by
+/// `==`, `!=`, `<`, `<=`, `>` and `>=`. A pointer on its own holds when it exists.
+/// Comparisons against a missing path never hold.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    /// The pointer exists in the value.
+    Exists(String),
+    /// What the pointer points at compares to `value` as `op` says.
+    Compare {
+        pointer: String,
+        op: Comparison,
+        value: Value,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// One key of a sort, written `/pointer` for ascending or `-/pointer` for descending order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub pointer: String,
+    pub descending: bool,
+}
+
+/// Longest filter expression parsed, in bytes.
+pub const MAX_LENGTH: usize = 4096;
+
+/// Deepest nesting of `not` and parentheses a filter expression may use.
+pub const MAX_DEPTH: usize = 64;
+
+/// Why a filter expression could not be parsed. `position` is a byte offset into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    pub position: usize,
+    pub reason: &'static str,
+}
+
+impl Filter {
+    /// Parses `expression`, refusing ones longer than [`MAX_LENGTH`] or nested deeper than
+    /// [`MAX_DEPTH`], which keeps parsing and matching off the bottom of the stack.
+    pub fn parse(expression: &str) -> Result<Self, FilterError> {
+        if expression.len() > MAX_LENGTH {
+            return Err(FilterError {
+                position: MAX_LENGTH,
+                reason: "filter is too long",
+            });
+        }
+
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            next: 0,
+            end: expression.len(),
+        };
+
+        let filter = parser.or(0)?;
+        match parser.tokens.get(parser.next) {
+            Some((position, _)) => Err(FilterError {
+                position: *position,
+                reason: "expected `and`, `or` or the end of the filter",
+            }),
+            None => Ok(filter),
+        }
+    }
+
+    /// Comparisons every matching value passes: those joined to the whole filter by `&&`.
+    pub fn conjuncts(&self) -> Vec<(&str, Comparison, &Value)> {
+        match self {
+            Self::And(left, right) => {
+                let mut conjuncts = left.conjuncts();
+                conjuncts.extend(right.conjuncts());
+                conjuncts
+            }
+            Self::Compare { pointer, op, value } => vec![(pointer.as_str(), *op, value)],
+            Self::Or(..) | Self::Not(_) | Self::Exists(_) => Vec::new(),
+        }
+    }
+
+    /// Whether `value` passes the filter.
+    pub fn matches(&self, value: &Value) -> bool {
+        match self {
+            Self::And(left, right) => left.matches(value) && right.matches(value),
+            Self::Or(left, right) => left.matches(value) || right.matches(value),
+            Self::Not(inner) => !inner.matches(value),
+            Self::Exists(pointer) => value.pointer(pointer).is_some(),
+            Self::Compare {
+                pointer,
+                op,
+                value: expected,
+            } => value
+                .pointer(pointer)
+                .is_some_and(|actual| op.holds(actual, expected)),
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(self, actual: &Value, expected: &Value) -> bool {
+        match self {
+            Self::Eq => equal(actual, expected),
+            Self::Ne => !equal(actual, expected),
+            _ => {
+                let ordering = match (actual, expected) {
+                    (Value::Number(a), Value::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+                    (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                    _ => None,
+                };
+                ordering.is_some_and(|ordering| match self {
+                    Self::Lt => ordering.is_lt(),
+                    Self::Le => ordering.is_le(),
+                    Self::Gt => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                })
+            }
+        }
+    }
+}
+
+impl SortKey {
+    pub fn parse(raw: &str) -> Option<Self> {
+        let (pointer, descending) = match raw.strip_prefix('-') {
+            Some(pointer) => (pointer, true),
+            None => (raw, false),
+        };
+
+        (pointer.is_empty() || pointer.starts_with('/')).then(|| Self {
+            pointer: pointer.to_owned(),
+            descending,
+        })
+    }
+
+    /// Orders two values by what this key points at.
+    ///
+    /// Values of different types order as null, booleans, numbers, strings, arrays, objects;
+    /// arrays and objects tie. Values missing the path come last in either direction.
+    pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        match (a.pointer(&self.pointer), b.pointer(&self.pointer)) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(a), Some(b)) if self.descending => order(b, a),
+            (Some(a), Some(b)) => order(a, b),
+        }
+    }
+}
+
+fn order(a: &Value, b: &Value) -> Ordering {
+    let rank = |value: &Value| match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    };
+
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Op(Comparison),
+    Pointer(String),
+    Literal(Value),
+}
+
+/// Splits an expression into tokens along with their byte offsets.
+fn tokenize(expression: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    let error = |position, reason| FilterError { position, reason };
+    let is_delimiter = |c: char| c.is_whitespace() || "()=!<>".contains(c);
+    let mut tokens = Vec::new();
+    let mut rest = expression.char_indices().peekable();
+
+    while let Some(&(start, c)) = rest.peek() {
+        let tail = &expression[start..];
+        let (token, len) = match c {
+            c if c.is_whitespace() => (None, c.len_utf8()),
+            '(' => (Some(Token::Open), 1),
+            ')' => (Some(Token::Close), 1),
+            '=' | '!' | '<' | '>' => {
+                let (op, len) = match tail.get(..2) {
+                    Some("==") => (Comparison::Eq, 2),
+                    Some("!=") => (Comparison::Ne, 2),
+                    Some("<=") => (Comparison::Le, 2),
+                    Some(">=") => (Comparison::Ge, 2),
+                    _ if c == '<' => (Comparison::Lt, 1),
+                    _ if c == '>' => (Comparison::Gt, 1),
+                    _ => return Err(error(start, "unknown operator")),
+                };
+                (Some(Token::Op(op)), len)
+            }
+            '/' => {
+                let len = tail.find(is_delimiter).unwrap_or(tail.len());
+                (Some(Token::Pointer(tail[..len].to_owned())), len)
+            }
+            '"' => {
+                let mut escaped = false;
+                let len = tail
+                    .char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| {
+                        let closes = c == '"' && !escaped;
+                        escaped = c == '\\' && !escaped;
+                        closes
+                    })
+                    .map(|(at, _)| at + 1)
+                    .ok_or(error(start, "unterminated string"))?;
+                let value = serde_json::from_str(&tail[..len])
+                    .map_err(|_| error(start, "invalid string"))?;
+                (Some(Token::Literal(value)), len)
+            }
+            _ => {
+                let len = tail.find(is_delimiter).unwrap_or(tail.len());
+                let token = match &tail[..len] {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    word => match serde_json::from_str::<Value>(word) {
+                        Ok(value) if !value.is_array() && !value.is_object() => {
+                            Token::Literal(value)
+                        }
+                        _ => return Err(error(start, "expected a pointer, literal or keyword")),
+                    },
+                };
+                (Some(token), len)
+            }
+        };
+
+        tokens.extend(token.map(|token| (start, token)));
+        while rest.next_if(|&(at, _)| at < start + len).is_some() {}
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent over the grammar
+///
+/// ```text
+/// or         = and { "or" and }
+/// and        = unary { "and" unary }
+/// unary      = "not" unary | "(" or ")" | pointer [ operator literal ]
+/// ```
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    end: usize,
+}
+
+impl Parser {
+    /// `depth` counts the `not`s and parentheses around the rule.
+    fn or(&mut self, depth: usize) -> Result<Filter, FilterError> {
+        let mut filter = self.and(depth)?;
+        while self.eat(&Token::Or) {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and(depth)?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self, depth: usize) -> Result<Filter, FilterError> {
+        let mut filter = self.unary(depth)?;
+        while self.eat(&Token::And) {
+            filter = Filter::And(Box::new(filter), Box::new(self.unary(depth)?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self, depth: usize) -> Result<Filter, FilterError> {
+        let (position, token) = self.advance("expected a pointer, `not` or `(`")?;
+        if matches!(token, Token::Not | Token::Open) && depth == MAX_DEPTH {
+            return Err(FilterError {
+                position,
+                reason: "filter is nested too deeply",
+            });
+        }
+
+        match token {
+            Token::Not => Ok(Filter::Not(Box::new(self.unary(depth + 1)?))),
+            Token::Open => {
+                let filter = self.or(depth + 1)?;
+                match self.advance("expected `)`")? {
+                    (_, Token::Close) => Ok(filter),
+                    (position, _) => Err(FilterError {
+                        position,
+                        reason: "expected `)`",
+                    }),
+                }
+            }
+            Token::Pointer(pointer) => {
+                let Some((_, Token::Op(op))) = self.tokens.get(self.next).cloned() else {
+                    return Ok(Filter::Exists(pointer));
+                };
+                self.next += 1;
+
+                match self.advance("expected a literal")? {
+                    (_, Token::Literal(value)) => Ok(Filter::Compare { pointer, op, value }),
+                    (position, _) => Err(FilterError {
+                        position,
+                        reason: "expected a literal",
+                    }),
+                }
+            }
+            _ => Err(FilterError {
+                position,
+                reason: "expected a pointer, `not` or `(`",
+            }),
+        }
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        let found = self
+            .tokens
+            .get(self.next)
+            .is_some_and(|(_, token)| token == expected);
+        self.next += found as usize;
+        found
+    }
+
+    fn advance(&mut self, reason: &'static str) -> Result<(usize, Token), FilterError> {
+        let token = self.tokens.get(self.next).cloned().ok_or(FilterError {
+            position: self.end,
+            reason,
+        })?;
+        self.next += 1;
+        Ok(token)
+    }
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid filter at {}: {}", self.position, self.reason)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// Answers `400`, with the offset parsing stopped at in `data.position`.
+impl ResponseError for FilterError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::BadRequest().json(json!({
+            "ok": false,
+            "message": self.to_string(),
+            "data": { "position": self.position }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_filters() {
+        let filter =
+            Filter::parse(r#"/stars > 100 and (/lang == "rust" or not /archived)"#).unwrap();
+
+        assert!(filter.matches(&json!({"stars": 101, "lang": "rust", "archived": true})));
+        assert!(filter.matches(&json!({"stars": 150.5, "lang": "go"})));
+        assert!(!filter.matches(&json!({"stars": 150, "lang": "go", "archived": false})));
+        assert!(!filter.matches(&json!({"stars": "many"})));
+        assert!(!filter.matches(&json!({"lang": "rust"})));
+        assert_eq!(
+            filter.conjuncts(),
+            vec![("/stars", Comparison::Gt, &json!(100))]
+        );
+
+        let escaped = Filter::parse(r#"/a~1b/0 != "x \" y" or /n<=1.0"#).unwrap();
+        assert!(escaped.matches(&json!({"a/b": ["z"]})));
+        assert!(!escaped.matches(&json!({"a/b": ["x \" y"]})));
+        assert!(escaped.matches(&json!({"n": 1})));
+        assert!(
+            Filter::parse("/ok == true")
+                .unwrap()
+                .matches(&json!({"ok": true}))
+        );
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        for (expression, position) in [
+            ("", 0),
+            ("/a >", 4),
+            ("/a > 1 /b", 7),
+            ("(/a", 3),
+            ("/a = 1", 3),
+            ("stars > 1", 0),
+            ("/a == \"open", 6),
+            ("/a > [1]", 5),
+        ] {
+            assert_eq!(
+                Filter::parse(expression).unwrap_err().position,
+                position,
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_deep_or_long_filters() {
+        let nested = |depth| format!("{}/a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Filter::parse(&nested(MAX_DEPTH)).is_ok());
+
+        let too_deep = Filter::parse(&nested(200_000)).unwrap_err();
+        assert_eq!(too_deep.position, MAX_LENGTH);
+        let too_deep = Filter::parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!(too_deep.position, MAX_DEPTH);
+        assert_eq!(too_deep.status_code(), StatusCode::BAD_REQUEST);
+
+        let negated = format!("{}/a", "not ".repeat(MAX_DEPTH + 1));
+        assert_eq!(
+            Filter::parse(&negated).unwrap_err().reason,
+            "filter is nested too deeply"
+        );
+    }
+
+    #[test]
+    fn sorts_by_pointer() {
+        let mut values = vec![
+            json!({"n": 2}),
+            json!({}),
+            json!({"n": "a"}),
+            json!({"n": 10}),
+            json!({"n": null}),
+        ];
+
+        let ascending = SortKey::parse("/n").unwrap();
+        values.sort_by(|a, b| ascending.compare(a, b));
+        assert_eq!(
+            values,
+            vec![
+                json!({"n": null}),
+                json!({"n": 2}),
+                json!({"n": 10}),
+                json!({"n": "a"}),
+                json!({})
+            ]
+        );
+
+        let descending = SortKey::parse("-/n").unwrap();
+        values.sort_by(|a, b| descending.compare(a, b));
+        assert_eq!(values[0], json!({"n": "a"}));
+        assert_eq!(values[4], json!({}));
+        assert_eq!(SortKey::parse("n"), None);
+    }
+}
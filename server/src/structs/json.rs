@@ -0,0 +1,37 @@
+use serde_json::Value;
+
+/// JSON equality, except that numbers are equal when their values are (`1 == 1.0`), also
+/// inside arrays and objects.
+pub fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a == b || a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(name, a)| b.get(name).is_some_and(|b| equal(a, b)))
+        }
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn compares_numbers_by_value() {
+        assert!(equal(&json!(1), &json!(1.0)));
+        assert!(equal(
+            &json!({"a": [1, {"b": 2}]}),
+            &json!({"a": [1.0, {"b": 2.0}]})
+        ));
+        assert!(!equal(&json!([1]), &json!([1, 1])));
+        assert!(!equal(&json!({"a": 1}), &json!({"b": 1})));
+        assert!(!equal(&json!(1), &json!("1")));
+    }
+}
@@ -1,12 +1,176 @@
+use chrono::{DateTime, Utc};
+use ciphers::sha256::SHA256;
 use serde::{Deserialize, Serialize};
 
-/// Stores optional metadata for cache entries.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use super::encoding::hex;
+
+/// Stores metadata for cache entries.
+///
+/// `size` and `digest` describe the value serialized as JSON, so they are the same whichever
+/// provider holds the entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredMetadata")]
 pub struct Metadata {
-    /// Timestamp for when the entry was created.
-    pub created_at: String,
-    /// Version of the cache server
-    pub version: u8,
-    /// Owner of the cache value
-    pub issuer: String,
+    /// When the entry was created. Never changes afterwards.
+    pub created_at: DateTime<Utc>,
+    /// Who created the entry. Never changes afterwards.
+    pub created_by: String,
+    /// When the value was last written.
+    pub updated_at: DateTime<Utc>,
+    /// Who last wrote the value.
+    pub updated_by: String,
+    /// Revision of the entry, starting at 0 and bumped on every update. Served as the `ETag`.
+    pub version: u64,
+    /// Sequence number of the last write, counted across all entries by the provider storing
+    /// the entry. 0 for entries written before writes were numbered.
+    pub sequence: u64,
+    /// Size of the value in bytes.
+    pub size: u64,
+    /// Hex encoded SHA-256 digest of the value.
+    pub digest: String,
+    /// Instant after which the entry is considered gone, if it has a TTL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// [`Metadata`] as read back, which also accepts what older versions wrote: a single
+/// `issuer`, no update fields and no size or digest.
+#[derive(Deserialize)]
+struct StoredMetadata {
+    created_at: DateTime<Utc>,
+    #[serde(alias = "issuer")]
+    created_by: String,
+    updated_at: Option<DateTime<Utc>>,
+    updated_by: Option<String>,
+    version: u64,
+    #[serde(default)]
+    sequence: u64,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    digest: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl From<StoredMetadata> for Metadata {
+    fn from(stored: StoredMetadata) -> Self {
+        Self {
+            created_at: stored.created_at,
+            updated_at: stored.updated_at.unwrap_or(stored.created_at),
+            updated_by: stored
+                .updated_by
+                .unwrap_or_else(|| stored.created_by.clone()),
+            created_by: stored.created_by,
+            version: stored.version,
+            sequence: stored.sequence,
+            size: stored.size,
+            digest: stored.digest,
+            expires_at: stored.expires_at,
+        }
+    }
+}
+
+impl Metadata {
+    /// Metadata of `value`, created by `issuer` just now.
+    pub fn new<T: Serialize>(
+        value: &T,
+        issuer: String,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> serde_json::Result<Self> {
+        let now = Utc::now();
+        let mut metadata = Self {
+            created_at: now,
+            created_by: issuer.clone(),
+            updated_at: now,
+            updated_by: issuer,
+            version: 0,
+            sequence: 0,
+            size: 0,
+            digest: String::new(),
+            expires_at,
+        };
+        metadata.describe(value)?;
+
+        Ok(metadata)
+    }
+
+    /// Records that `issuer` just replaced the value with `value`, bumping the version.
+    ///
+    /// Without a new `expires_at` the current expiry is kept.
+    pub fn update<T: Serialize>(
+        &mut self,
+        value: &T,
+        issuer: String,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> serde_json::Result<()> {
+        self.describe(value)?;
+        self.version += 1;
+        self.updated_at = Utc::now();
+        self.updated_by = issuer;
+        if expires_at.is_some() {
+            self.expires_at = expires_at;
+        }
+
+        Ok(())
+    }
+
+    /// Sets `size` and `digest` from `value` serialized as JSON.
+    pub fn describe<T: Serialize>(&mut self, value: &T) -> serde_json::Result<()> {
+        let json = serde_json::to_vec(value)?;
+
+        let mut hasher = SHA256::new_default();
+        hasher.update(&json);
+
+        self.size = json.len() as u64;
+        self.digest = hex(&hasher.get_hash());
+        Ok(())
+    }
+
+    /// Whether the entry has outlived its TTL.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= Utc::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn describes_values_and_keeps_creation() {
+        let mut metadata = Metadata::new(&json!("abc"), "alice".into(), None).unwrap();
+        assert_eq!(metadata.size, 5);
+        // echo -n '"abc"' | sha256sum
+        assert_eq!(
+            metadata.digest,
+            "6cc43f858fbb763301637b5af970e2a46b46f461f27e5a0f41e009c59b827b25"
+        );
+
+        let created_at = metadata.created_at;
+        metadata.update(&json!(1), "bob".into(), None).unwrap();
+        assert_eq!(metadata.version, 1);
+        assert_eq!(metadata.size, 1);
+        assert_eq!(
+            (metadata.created_at, metadata.created_by.as_str()),
+            (created_at, "alice")
+        );
+        assert_eq!(metadata.updated_by, "bob");
+        assert!(metadata.updated_at >= created_at);
+    }
+
+    #[test]
+    fn reads_legacy_metadata() {
+        let metadata: Metadata = serde_json::from_str(
+            r#"{"created_at":"2025-06-20T12:00:00+00:00","version":7,"issuer":"t"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(metadata.created_by, "t");
+        assert_eq!(metadata.updated_by, "t");
+        assert_eq!(metadata.updated_at, metadata.created_at);
+        assert_eq!(metadata.version, 7);
+        assert!(!metadata.is_expired());
+    }
 }
@@ -1,2 +1,10 @@
+pub mod counter;
+pub mod encoding;
+pub mod entry;
+pub mod filter;
+pub mod json;
 pub mod metadata;
+pub mod patch;
+pub mod stats;
 pub mod user;
+pub mod webhook;
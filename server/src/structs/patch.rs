@@ -0,0 +1,392 @@
+use std::fmt;
+
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use super::json::equal;
+
+/// A JSON Patch document (RFC 6902): operations applied in order, all or nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPatch(pub Vec<Operation>);
+
+/// One JSON Patch operation. Paths are JSON Pointers (RFC 6901).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// Why a patch could not be read or applied. Operations are counted from 0.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    /// The JSON Patch document is not a list of operations.
+    NotAList,
+    /// Operation `index` is not a valid JSON Patch operation.
+    Invalid { index: usize, reason: String },
+    /// Operation `index` does not apply to the document, or its `test` does not hold.
+    Failed { index: usize, reason: String },
+}
+
+impl JsonPatch {
+    /// Reads a JSON Patch document, reporting which operation is malformed.
+    pub fn from_value(value: Value) -> Result<Self, PatchError> {
+        let Value::Array(operations) = value else {
+            return Err(PatchError::NotAList);
+        };
+
+        operations
+            .into_iter()
+            .enumerate()
+            .map(|(index, operation)| {
+                let operation: Operation =
+                    serde_json::from_value(operation).map_err(|e| PatchError::Invalid {
+                        index,
+                        reason: e.to_string(),
+                    })?;
+                operation.validate().map_err(|reason| PatchError::Invalid {
+                    index,
+                    reason: reason.into(),
+                })?;
+                Ok(operation)
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// Applies every operation to a copy of `document`. On failure nothing is kept.
+    pub fn apply(&self, document: &Value) -> Result<Value, PatchError> {
+        let mut patched = document.clone();
+
+        for (index, operation) in self.0.iter().enumerate() {
+            operation
+                .apply(&mut patched)
+                .map_err(|reason| PatchError::Failed {
+                    index,
+                    reason: reason.into(),
+                })?;
+        }
+
+        Ok(patched)
+    }
+}
+
+impl Operation {
+    /// Checks that every pointer of the operation is well formed.
+    fn validate(&self) -> Result<(), &'static str> {
+        let (path, from) = match self {
+            Self::Add { path, .. }
+            | Self::Remove { path }
+            | Self::Replace { path, .. }
+            | Self::Test { path, .. } => (path, None),
+            Self::Move { from, path } | Self::Copy { from, path } => (path, Some(from)),
+        };
+
+        for pointer in std::iter::once(path).chain(from) {
+            tokens(pointer).ok_or("paths must be empty or start with '/'")?;
+        }
+        if let Self::Move { from, path } = self
+            && path.starts_with(&format!("{from}/"))
+        {
+            return Err("a value cannot be moved into one of its children");
+        }
+
+        Ok(())
+    }
+
+    fn apply(&self, document: &mut Value) -> Result<(), &'static str> {
+        match self {
+            Self::Add { path, value } => add(document, path, value.clone()),
+            Self::Remove { path } => remove(document, path).map(drop),
+            Self::Replace { path, value } => {
+                *lookup(document, path).ok_or("path does not exist")? = value.clone();
+                Ok(())
+            }
+            Self::Move { from, path } => {
+                let value = remove(document, from)?;
+                add(document, path, value)
+            }
+            Self::Copy { from, path } => {
+                let value = lookup(document, from).ok_or("from does not exist")?.clone();
+                add(document, path, value)
+            }
+            Self::Test { path, value } => match lookup(document, path) {
+                Some(current) if equal(current, value) => Ok(()),
+                Some(_) => Err("test did not match"),
+                None => Err("path does not exist"),
+            },
+        }
+    }
+}
+
+/// Applies a JSON Merge Patch (RFC 7396) to `document`: objects merge recursively, `null`
+/// removes a member and anything else replaces the target.
+pub fn merge_patch(document: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *document = patch.clone();
+        return;
+    };
+    if !document.is_object() {
+        *document = Value::Object(Map::new());
+    }
+
+    let Value::Object(members) = document else {
+        unreachable!("document was just made an object");
+    };
+    for (name, value) in patch {
+        match value {
+            Value::Null => {
+                members.remove(name);
+            }
+            value => merge_patch(members.entry(name).or_insert(Value::Null), value),
+        }
+    }
+}
+
+/// Unescaped reference tokens of a JSON Pointer, or `None` if it is malformed.
+///
+/// The empty pointer refers to the whole document and has no tokens.
+pub fn tokens(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let rest = pointer.strip_prefix('/')?;
+    Some(
+        rest.split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect(),
+    )
+}
+
+/// Position `token` names in an array of `len` items. `-` is one past the end.
+fn array_index(token: &str, len: usize) -> Option<usize> {
+    match token {
+        "-" => Some(len),
+        "0" => Some(0),
+        token if token.starts_with('0') || !token.bytes().all(|b| b.is_ascii_digit()) => None,
+        token => token.parse().ok(),
+    }
+}
+
+fn lookup<'a>(document: &'a mut Value, pointer: &str) -> Option<&'a mut Value> {
+    tokens(pointer)?
+        .iter()
+        .try_fold(document, |value, token| match value {
+            Value::Object(members) => members.get_mut(token),
+            Value::Array(items) => {
+                let index = array_index(token, items.len())?;
+                items.get_mut(index)
+            }
+            _ => None,
+        })
+}
+
+/// The container holding the target of `pointer`, and the target's last token.
+fn parent<'a>(document: &'a mut Value, pointer: &str) -> Option<(&'a mut Value, String)> {
+    let (parent, last) = pointer.rsplit_once('/')?;
+    let last = tokens(&format!("/{last}"))?.pop()?;
+    Some((lookup(document, parent)?, last))
+}
+
+fn add(document: &mut Value, pointer: &str, value: Value) -> Result<(), &'static str> {
+    if pointer.is_empty() {
+        *document = value;
+        return Ok(());
+    }
+
+    match parent(document, pointer).ok_or("parent of path does not exist")? {
+        (Value::Object(members), name) => {
+            members.insert(name, value);
+            Ok(())
+        }
+        (Value::Array(items), token) => {
+            let index = array_index(&token, items.len())
+                .filter(|&index| index <= items.len())
+                .ok_or("array index is out of bounds")?;
+            items.insert(index, value);
+            Ok(())
+        }
+        _ => Err("parent of path is not an object or array"),
+    }
+}
+
+fn remove(document: &mut Value, pointer: &str) -> Result<Value, &'static str> {
+    if pointer.is_empty() {
+        return Err("the whole document cannot be removed");
+    }
+
+    match parent(document, pointer).ok_or("path does not exist")? {
+        (Value::Object(members), name) => members.remove(&name).ok_or("path does not exist"),
+        (Value::Array(items), token) => {
+            let index = array_index(&token, items.len())
+                .filter(|&index| index < items.len())
+                .ok_or("array index is out of bounds")?;
+            Ok(items.remove(index))
+        }
+        _ => Err("path does not exist"),
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAList => write!(f, "a JSON Patch must be a list of operations"),
+            Self::Invalid { index, reason } => {
+                write!(f, "patch operation {index} is invalid: {reason}")
+            }
+            Self::Failed { index, reason } => write!(f, "patch operation {index} failed: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Malformed patches answer `422`, patches that do not apply to the entry `409`. Both name
+/// the failing operation in `data.index`.
+impl ResponseError for PatchError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotAList | Self::Invalid { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Failed { .. } => StatusCode::CONFLICT,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let data = match self {
+            Self::NotAList => json!({}),
+            Self::Invalid { index, .. } | Self::Failed { index, .. } => json!({ "index": index }),
+        };
+
+        HttpResponse::build(self.status_code()).json(json!({
+            "ok": false,
+            "message": self.to_string(),
+            "data": data
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(operations: Value) -> JsonPatch {
+        JsonPatch::from_value(operations).unwrap()
+    }
+
+    #[test]
+    fn applies_json_patch() {
+        let document = json!({"a/b": 1, "list": ["x", "z"], "m": {"n": 2}});
+        let patched = patch(json!([
+            {"op": "test", "path": "/a~1b", "value": 1},
+            {"op": "add", "path": "/list/1", "value": "y"},
+            {"op": "add", "path": "/list/-", "value": "end"},
+            {"op": "remove", "path": "/a~1b"},
+            {"op": "replace", "path": "/m/n", "value": null},
+            {"op": "copy", "from": "/m", "path": "/c"},
+            {"op": "move", "from": "/list/0", "path": "/first"}
+        ]))
+        .apply(&document)
+        .unwrap();
+
+        assert_eq!(
+            patched,
+            json!({
+                "list": ["y", "z", "end"],
+                "m": {"n": null},
+                "c": {"n": null},
+                "first": "x"
+            })
+        );
+    }
+
+    #[test]
+    fn tests_numbers_by_value() {
+        let document = json!({"n": 1.0, "list": [2.0, {"m": 3}]});
+
+        let tested = patch(json!([
+            {"op": "test", "path": "/n", "value": 1},
+            {"op": "test", "path": "/list", "value": [2, {"m": 3.0}]}
+        ]))
+        .apply(&document);
+        assert_eq!(tested, Ok(document.clone()));
+        assert!(
+            patch(json!([{"op": "test", "path": "/n", "value": 1.5}]))
+                .apply(&document)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn reports_the_failing_operation() {
+        let document = json!({"a": [1]});
+
+        let failed = patch(json!([
+            {"op": "add", "path": "/b", "value": 2},
+            {"op": "test", "path": "/a/0", "value": 2}
+        ]))
+        .apply(&document);
+        assert!(matches!(failed, Err(PatchError::Failed { index: 1, .. })));
+
+        for out_of_bounds in ["/a/2", "/a/01", "/missing/x"] {
+            let failed =
+                patch(json!([{"op": "add", "path": out_of_bounds, "value": 0}])).apply(&document);
+            assert!(matches!(failed, Err(PatchError::Failed { index: 0, .. })));
+        }
+
+        let invalid = JsonPatch::from_value(json!([
+            {"op": "remove", "path": "/a"},
+            {"op": "frobnicate", "path": "/a"}
+        ]));
+        assert!(matches!(invalid, Err(PatchError::Invalid { index: 1, .. })));
+        assert!(matches!(
+            JsonPatch::from_value(json!([{"op": "move", "from": "/a", "path": "/a/0"}])),
+            Err(PatchError::Invalid { index: 0, .. })
+        ));
+        assert!(matches!(
+            JsonPatch::from_value(json!([{"op": "remove", "path": "a"}])),
+            Err(PatchError::Invalid { index: 0, .. })
+        ));
+        assert_eq!(JsonPatch::from_value(json!({})), Err(PatchError::NotAList));
+    }
+
+    #[test]
+    fn applies_merge_patch() {
+        // RFC 7396, section 3
+        let mut document = json!({
+            "title": "Goodbye!",
+            "author": {"givenName": "John", "familyName": "Doe"},
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged"
+        });
+        merge_patch(
+            &mut document,
+            &json!({
+                "title": "Hello!",
+                "phoneNumber": "+01-123-456-7890",
+                "author": {"familyName": null},
+                "tags": ["example"]
+            }),
+        );
+
+        assert_eq!(
+            document,
+            json!({
+                "title": "Hello!",
+                "author": {"givenName": "John"},
+                "tags": ["example"],
+                "content": "This will be unchanged",
+                "phoneNumber": "+01-123-456-7890"
+            })
+        );
+
+        let mut scalar = json!([1, 2]);
+        merge_patch(&mut scalar, &json!({"a": {"b": null, "c": 1}}));
+        assert_eq!(scalar, json!({"a": {"c": 1}}));
+    }
+}
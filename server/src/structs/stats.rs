@@ -0,0 +1,18 @@
+use serde::Serialize;
+
+/// Usage figures reported by a cache provider.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProviderStats {
+    /// Number of entries currently stored.
+    pub entries: usize,
+    /// Maximum number of entries, if the provider enforces one.
+    pub max_entries: Option<usize>,
+    /// Approximate serialized size of all entries, if the provider tracks it.
+    pub bytes: Option<usize>,
+    /// Maximum number of bytes, if the provider enforces one.
+    pub max_bytes: Option<usize>,
+    /// Policy used to pick which entries to evict.
+    pub eviction_policy: Option<String>,
+    /// Entries dropped to stay within the limits since startup.
+    pub evictions: u64,
+}
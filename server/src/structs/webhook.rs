@@ -0,0 +1,70 @@
+use chrono::{DateTime, Utc};
+use ciphers::{hashing_traits::HMAC, sha256::SHA256};
+use serde::{Deserialize, Serialize};
+
+use super::encoding::hex;
+use crate::providers::changes::ChangeKind;
+
+/// Longest secret a webhook accepts, the block size of SHA-256.
+pub const MAX_SECRET_BYTES: usize = 64;
+
+/// An HTTP endpoint told about every change to entries under a prefix.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Webhook {
+    pub name: String,
+    /// Key prefix of the watched entries, with `:` like stored keys.
+    pub prefix: String,
+    /// Plain `http://` URL events are posted to.
+    pub url: String,
+    /// Key deliveries are signed with. Never shown back to clients.
+    pub secret: String,
+    /// Who registered the webhook.
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Whether `secret` can sign deliveries: 1 to [`MAX_SECRET_BYTES`] bytes long.
+pub fn is_valid_secret(secret: &str) -> bool {
+    (1..=MAX_SECRET_BYTES).contains(&secret.len())
+}
+
+/// One attempt at delivering an event to a webhook, as kept in its delivery log.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeliveryAttempt {
+    /// Shared by every attempt at delivering the same event.
+    pub delivery: u64,
+    /// Starts at 1; 0 marks a delivery dropped because too many were underway.
+    pub attempt: u32,
+    pub event: ChangeKind,
+    pub key: String,
+    pub at: DateTime<Utc>,
+    /// Status the endpoint answered, if it answered at all.
+    pub status: Option<u16>,
+    /// Why the attempt failed, `None` if the endpoint accepted the event.
+    pub error: Option<String>,
+}
+
+/// Hex encoded HMAC-SHA256 of `body` under `secret`, sent as `X-Objekt-Signature: sha256=...`.
+///
+/// `secret` must pass [`is_valid_secret`], which registration and loading enforce.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut hmac: HMAC<MAX_SECRET_BYTES, 32, SHA256> = HMAC::new_default();
+    hmac.add_key(secret.as_bytes())
+        .expect("webhook secrets are checked on registration and loading");
+    hmac.update(body);
+    hex(&hmac.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // echo -n "Hello World" | openssl sha256 -hex -mac HMAC -macopt key:"key"
+        assert_eq!(
+            sign("key", b"Hello World"),
+            "42274f72808392e58b57d728d7e3c0e5163eaf07d165dc03105f62abbe1a7d11"
+        );
+    }
+}
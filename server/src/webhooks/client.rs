@@ -0,0 +1,131 @@
+use std::io;
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+/// Where a plain `http://` URL points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    host: String,
+    port: u16,
+    /// Path and query, starting with `/`.
+    target: String,
+}
+
+impl Endpoint {
+    /// Parses `http://host[:port][/path][?query]`, or `None` for anything else.
+    pub fn parse(url: &str) -> Option<Self> {
+        let rest = url.strip_prefix("http://")?;
+        let (authority, target) = match rest.find(['/', '?']) {
+            Some(at) => (&rest[..at], &rest[at..]),
+            None => (rest, "/"),
+        };
+        let target = match target.starts_with('?') {
+            true => format!("/{target}"),
+            false => target.to_owned(),
+        };
+        if authority.contains('@') || target.contains('#') || target.contains(char::is_whitespace) {
+            return None;
+        }
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (host, port.parse().ok()?),
+            _ => (authority, 80),
+        };
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        if host.is_empty() || host.contains(['[', ']']) {
+            return None;
+        }
+
+        Some(Self {
+            host: host.to_owned(),
+            port,
+            target,
+        })
+    }
+
+    /// Posts `body` with `headers` over a fresh connection and returns the response status.
+    pub async fn post(&self, headers: &[(&str, String)], body: &[u8]) -> io::Result<u16> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+            self.target,
+            self.authority(),
+            body.len()
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+        stream.write_all(body).await?;
+
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line).await?;
+
+        status_line
+            .split(' ')
+            .nth(1)
+            .filter(|_| status_line.starts_with("HTTP/"))
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an HTTP response"))
+    }
+
+    fn authority(&self) -> String {
+        let host = match self.host.contains(':') {
+            true => format!("[{}]", self.host),
+            false => self.host.clone(),
+        };
+        match self.port {
+            80 => host,
+            port => format!("{host}:{port}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(host: &str, port: u16, target: &str) -> Option<Endpoint> {
+        Some(Endpoint {
+            host: host.into(),
+            port,
+            target: target.into(),
+        })
+    }
+
+    #[test]
+    fn parses_plain_http_urls() {
+        assert_eq!(
+            Endpoint::parse("http://example.com"),
+            endpoint("example.com", 80, "/")
+        );
+        assert_eq!(
+            Endpoint::parse("http://127.0.0.1:9000/hooks/objekt?v=1"),
+            endpoint("127.0.0.1", 9000, "/hooks/objekt?v=1")
+        );
+        assert_eq!(
+            Endpoint::parse("http://[::1]:9000?v=1"),
+            endpoint("::1", 9000, "/?v=1")
+        );
+
+        for url in [
+            "https://example.com/",
+            "example.com",
+            "http://",
+            "http://:80/",
+            "http://user@example.com/",
+            "http://example.com:http/",
+            "http://example.com/a b",
+        ] {
+            assert_eq!(Endpoint::parse(url), None, "{url}");
+        }
+    }
+}
@@ -0,0 +1,726 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use anyhow::Result;
+use chrono::Utc;
+use client::Endpoint;
+use serde_json::{Value, json};
+use tokio::{
+    sync::{Semaphore, broadcast::error::RecvError},
+    task::JoinHandle,
+    time,
+};
+use tracing::warn;
+
+use crate::{
+    providers::{
+        CacheProvider, Precondition, WriteOptions, changes::Change, error::ProviderError,
+        fs::FileSystemProvider, registry::DynProvider,
+    },
+    structs::webhook::{DeliveryAttempt, Webhook, is_valid_secret, sign},
+};
+
+pub mod client;
+
+/// Most attempts kept in the delivery log of each webhook.
+pub const DELIVERY_LOG: usize = 100;
+
+/// Most deliveries underway at once for each webhook, retries included. Changes beyond are
+/// dropped rather than queued behind an endpoint that cannot keep up.
+pub const MAX_PENDING: usize = 256;
+
+/// How long an endpoint gets to answer one attempt.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often, and how patiently, a failed delivery is tried again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts per delivery, the first one included.
+    pub attempts: u32,
+    /// Wait after the first failed attempt, doubled after each further one.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait after the failed attempt number `attempt`, counted from 1.
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(1 << (attempt - 1).min(16))
+    }
+}
+
+/// Why a webhook call failed.
+#[derive(Debug)]
+pub enum WebhookError {
+    /// No webhook has the given name.
+    NotFound,
+    /// A webhook with the given name already exists.
+    Exists,
+    /// Saving or removing the registration failed.
+    Provider(ProviderError),
+}
+
+impl From<ProviderError> for WebhookError {
+    fn from(e: ProviderError) -> Self {
+        Self::Provider(e)
+    }
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "webhook not found"),
+            Self::Exists => write!(f, "webhook already exists"),
+            Self::Provider(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Provider(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Exists => StatusCode::CONFLICT,
+            Self::Provider(e) => e.status_code(),
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let message = match self {
+            Self::NotFound => "This webhook does not exist",
+            Self::Exists => "This webhook already exists",
+            Self::Provider(e) => return e.error_response(),
+        };
+
+        HttpResponse::build(self.status_code()).json(json!({
+            "ok": false,
+            "message": message,
+            "data": {}
+        }))
+    }
+}
+
+/// A registered webhook and the deliveries it has underway.
+struct Registered {
+    hook: Arc<Webhook>,
+    /// Holds [`MAX_PENDING`] permits, one taken by each delivery until it is done.
+    pending: Arc<Semaphore>,
+}
+
+impl Registered {
+    fn new(hook: Arc<Webhook>) -> Self {
+        Self {
+            hook,
+            pending: Arc::new(Semaphore::new(MAX_PENDING)),
+        }
+    }
+}
+
+/// The registered webhooks and what happened to their recent deliveries.
+///
+/// Registrations are saved the same way users are and survive restarts. Delivery logs are
+/// kept in memory, the last [`DELIVERY_LOG`] attempts per webhook.
+pub struct Webhooks {
+    store: FileSystemProvider<Webhook>,
+    hooks: RwLock<HashMap<String, Registered>>,
+    log: Mutex<HashMap<String, VecDeque<DeliveryAttempt>>>,
+    next_delivery: AtomicU64,
+    retry: RetryPolicy,
+}
+
+impl Webhooks {
+    /// Opens the registrations saved under `path`.
+    ///
+    /// Registrations that would be refused today, such as one with an overlong secret saved
+    /// by hand, are skipped with a warning and stay on disk until removed.
+    pub async fn open(path: PathBuf) -> Result<Self> {
+        let store = FileSystemProvider::<Webhook>::new(path).await?;
+        let hooks = store
+            .list()
+            .await?
+            .into_iter()
+            .filter(|(name, hook)| {
+                let usable = is_valid_secret(&hook.secret) && Endpoint::parse(&hook.url).is_some();
+                if !usable {
+                    warn!("skipping webhook {name:?}, its secret or url is invalid");
+                }
+                usable
+            })
+            .map(|(name, hook)| (name, Registered::new(Arc::new(hook))))
+            .collect::<HashMap<_, _>>();
+        let log = hooks.keys().map(|name| (name.clone(), VecDeque::new()));
+
+        Ok(Self {
+            store,
+            log: Mutex::new(log.collect()),
+            hooks: RwLock::new(hooks),
+            next_delivery: AtomicU64::new(1),
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Every registered webhook, sorted by name.
+    pub fn list(&self) -> Vec<Arc<Webhook>> {
+        let hooks = self.hooks.read().unwrap();
+        let mut hooks: Vec<_> = hooks.values().map(|entry| entry.hook.clone()).collect();
+        hooks.sort_by(|a, b| a.name.cmp(&b.name));
+        hooks
+    }
+
+    /// Saves `hook` and starts delivering changes to it.
+    ///
+    /// Fails with [`WebhookError::Exists`] if a webhook already has that name.
+    pub async fn register(&self, hook: Webhook) -> Result<(), WebhookError> {
+        let name = hook.name.clone();
+        let added = self
+            .store
+            .add(
+                name.clone(),
+                hook.clone(),
+                hook.created_by.clone(),
+                WriteOptions::default(),
+            )
+            .await;
+        match added {
+            Ok(_) => {}
+            Err(ProviderError::AlreadyExists) => return Err(WebhookError::Exists),
+            Err(e) => return Err(e.into()),
+        }
+
+        self.log
+            .lock()
+            .unwrap()
+            .insert(name.clone(), VecDeque::new());
+        self.hooks
+            .write()
+            .unwrap()
+            .insert(name, Registered::new(Arc::new(hook)));
+        Ok(())
+    }
+
+    /// Stops delivering to the webhook `name`, including retries underway, and forgets it.
+    pub async fn remove(&self, name: &str) -> Result<Arc<Webhook>, WebhookError> {
+        match self
+            .store
+            .remove(name.to_owned(), Precondition::default())
+            .await
+        {
+            Ok(_) => {}
+            Err(ProviderError::NotFound) => return Err(WebhookError::NotFound),
+            Err(e) => return Err(e.into()),
+        }
+
+        self.log.lock().unwrap().remove(name);
+        self.hooks
+            .write()
+            .unwrap()
+            .remove(name)
+            .map(|entry| entry.hook)
+            .ok_or(WebhookError::NotFound)
+    }
+
+    /// The delivery log of the webhook `name`, newest attempt first.
+    pub fn deliveries(&self, name: &str) -> Result<Vec<DeliveryAttempt>, WebhookError> {
+        let log = self.log.lock().unwrap();
+        let attempts = log.get(name).ok_or(WebhookError::NotFound)?;
+
+        Ok(attempts.iter().rev().cloned().collect())
+    }
+
+    /// Webhooks watching `key`, with the permits their deliveries take.
+    fn matching(&self, key: &str) -> Vec<(Arc<Webhook>, Arc<Semaphore>)> {
+        self.hooks
+            .read()
+            .unwrap()
+            .values()
+            .filter(|entry| key.starts_with(&entry.hook.prefix))
+            .map(|entry| (entry.hook.clone(), entry.pending.clone()))
+            .collect()
+    }
+
+    /// Whether `hook` is still registered, and not replaced by another one of the same name.
+    fn is_registered(&self, hook: &Arc<Webhook>) -> bool {
+        self.hooks
+            .read()
+            .unwrap()
+            .get(&hook.name)
+            .is_some_and(|current| Arc::ptr_eq(&current.hook, hook))
+    }
+
+    fn record(&self, hook: &Arc<Webhook>, attempt: DeliveryAttempt) {
+        if !self.is_registered(hook) {
+            return;
+        }
+
+        let mut log = self.log.lock().unwrap();
+        if let Some(attempts) = log.get_mut(&hook.name) {
+            if attempts.len() == DELIVERY_LOG {
+                attempts.pop_front();
+            }
+            attempts.push_back(attempt);
+        }
+    }
+}
+
+/// Posts every change of `provider` to the webhooks watching its key, in a background task.
+///
+/// Each delivery runs on its own and is retried according to the [`RetryPolicy`], so events
+/// for one key may reach an endpoint out of order; `metadata.version` tells them apart. A
+/// webhook with [`MAX_PENDING`] deliveries underway misses further changes until one is done;
+/// each miss is logged and kept in its delivery log as attempt 0.
+pub fn spawn_webhooks(provider: DynProvider, webhooks: Arc<Webhooks>) -> JoinHandle<()> {
+    let mut changes = provider.changes();
+
+    tokio::spawn(async move {
+        loop {
+            match changes.recv().await {
+                Ok(change) => {
+                    for (hook, pending) in webhooks.matching(&change.key) {
+                        let delivery = webhooks.next_delivery.fetch_add(1, Ordering::Relaxed);
+                        let Ok(permit) = pending.try_acquire_owned() else {
+                            drop_delivery(&webhooks, &hook, &change, delivery);
+                            continue;
+                        };
+
+                        let webhooks = webhooks.clone();
+                        let change = change.clone();
+                        tokio::spawn(async move {
+                            deliver(webhooks, hook, change, delivery).await;
+                            drop(permit);
+                        });
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("webhooks fell behind and missed {missed} changes");
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    })
+}
+
+/// Records that `change` is not delivered to `hook`, which has too many deliveries underway.
+fn drop_delivery(webhooks: &Webhooks, hook: &Arc<Webhook>, change: &Change<Value>, delivery: u64) {
+    warn!(
+        "dropped {} of {:?} for webhook {:?}, {MAX_PENDING} deliveries are underway",
+        change.kind.name(),
+        change.key,
+        hook.name
+    );
+    webhooks.record(
+        hook,
+        DeliveryAttempt {
+            delivery,
+            attempt: 0,
+            event: change.kind,
+            key: change.key.clone(),
+            at: Utc::now(),
+            status: None,
+            error: Some(format!(
+                "dropped, {MAX_PENDING} deliveries were already underway"
+            )),
+        },
+    );
+}
+
+/// Posts `change` to `hook` until the endpoint accepts it or the attempts run out.
+async fn deliver(
+    webhooks: Arc<Webhooks>,
+    hook: Arc<Webhook>,
+    change: Arc<Change<Value>>,
+    delivery: u64,
+) {
+    let Some(endpoint) = Endpoint::parse(&hook.url) else {
+        // checked on registration
+        return;
+    };
+    let body = json!({
+        "webhook": hook.name,
+        "delivery": delivery,
+        "sequence": change.sequence,
+        "event": change.kind,
+        "key": change.key,
+        "metadata": change.metadata,
+        "value": change.value
+    })
+    .to_string();
+    let headers = [
+        ("Content-Type", "application/json".to_owned()),
+        ("X-Objekt-Event", change.kind.name().to_owned()),
+        ("X-Objekt-Delivery", delivery.to_string()),
+        (
+            "X-Objekt-Signature",
+            format!("sha256={}", sign(&hook.secret, body.as_bytes())),
+        ),
+    ];
+
+    let retry = webhooks.retry;
+    for attempt in 1..=retry.attempts {
+        let (status, error) =
+            match time::timeout(ATTEMPT_TIMEOUT, endpoint.post(&headers, body.as_bytes())).await {
+                Ok(Ok(status)) if (200..300).contains(&status) => (Some(status), None),
+                Ok(Ok(status)) => (Some(status), Some(format!("endpoint answered {status}"))),
+                Ok(Err(e)) => (None, Some(e.to_string())),
+                Err(_) => (None, Some("endpoint did not answer in time".to_owned())),
+            };
+        let delivered = error.is_none();
+
+        webhooks.record(
+            &hook,
+            DeliveryAttempt {
+                delivery,
+                attempt,
+                event: change.kind,
+                key: change.key.clone(),
+                at: Utc::now(),
+                status,
+                error,
+            },
+        );
+        if delivered {
+            return;
+        }
+        if attempt == retry.attempts {
+            break;
+        }
+
+        time::sleep(retry.delay(attempt)).await;
+        if !webhooks.is_registered(&hook) {
+            return;
+        }
+    }
+
+    warn!(
+        "gave up delivering {} of {:?} to webhook {:?}",
+        change.kind.name(),
+        change.key,
+        hook.name
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use super::*;
+    use crate::{
+        providers::{changes::ChangeKind, eviction::MemoryLimits, memory::MemoryProvider},
+        structs::webhook::MAX_SECRET_BYTES,
+    };
+
+    /// A request as the test listener received it.
+    struct Received {
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    /// Listens on a local port and answers each request with the next of `statuses`, then
+    /// `200`. Returns the URL to post to and the requests received.
+    async fn listener(statuses: Vec<u16>) -> (String, mpsc::UnboundedReceiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut statuses = statuses.into_iter();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+
+                let mut headers = HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    match line.trim_end().split_once(": ") {
+                        Some((name, value)) => {
+                            headers.insert(name.to_lowercase(), value.to_owned());
+                        }
+                        None if line.trim_end().is_empty() => break,
+                        None => {}
+                    }
+                }
+                let mut body = vec![0; headers["content-length"].parse().unwrap()];
+                stream.read_exact(&mut body).await.unwrap();
+
+                let status = statuses.next().unwrap_or(200);
+                let response = format!("HTTP/1.1 {status} Whatever\r\nContent-Length: 0\r\n\r\n");
+                stream.write_all(response.as_bytes()).await.unwrap();
+                let _ = sender.send(Received { headers, body });
+            }
+        });
+
+        (url, receiver)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("objekt-webhooks-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn hook(name: &str, prefix: &str, url: &str) -> Webhook {
+        Webhook {
+            name: name.into(),
+            prefix: prefix.into(),
+            url: url.into(),
+            secret: "s3cret".into(),
+            created_by: "t".into(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Waits until the delivery log of `name` holds `count` attempts.
+    async fn wait_for_log(webhooks: &Webhooks, name: &str, count: usize) -> Vec<DeliveryAttempt> {
+        for _ in 0..200 {
+            let log = webhooks.deliveries(name).unwrap();
+            if log.len() >= count {
+                return log;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("delivery log of {name} never reached {count} attempts");
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_events_under_prefix() {
+        let dir = temp_dir("deliver");
+        let provider: DynProvider = Arc::new(MemoryProvider::<Value>::new(MemoryLimits::default()));
+        let webhooks = Arc::new(Webhooks::open(dir.clone()).await.unwrap());
+        let (url, mut received) = listener(vec![]).await;
+        webhooks.register(hook("app", "app:", &url)).await.unwrap();
+        spawn_webhooks(provider.clone(), webhooks.clone());
+
+        provider
+            .add("other".into(), json!(0), "t".into(), Default::default())
+            .await
+            .unwrap();
+        provider
+            .add(
+                "app:x".into(),
+                json!({"a": 1}),
+                "t".into(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+
+        let request = received.recv().await.unwrap();
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["event"], "add");
+        assert_eq!(body["key"], "app:x");
+        assert_eq!(body["value"], json!({"a": 1}));
+        assert_eq!(body["metadata"]["version"], 0);
+        assert_eq!(request.headers["x-objekt-event"], "add");
+        assert_eq!(
+            request.headers["x-objekt-signature"],
+            format!("sha256={}", sign("s3cret", &request.body))
+        );
+
+        let log = wait_for_log(&webhooks, "app", 1).await;
+        assert_eq!(log[0].key, "app:x");
+        assert_eq!(log[0].event, ChangeKind::Add);
+        assert_eq!(log[0].status, Some(200));
+        assert!(log[0].error.is_none());
+
+        provider
+            .remove("app:x".into(), Precondition::default())
+            .await
+            .unwrap();
+        let request = received.recv().await.unwrap();
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["event"], "remove");
+        assert_eq!(body["metadata"], Value::Null);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn retries_failed_deliveries_with_backoff() {
+        let dir = temp_dir("retry");
+        let provider: DynProvider = Arc::new(MemoryProvider::<Value>::new(MemoryLimits::default()));
+        let webhooks = Webhooks::open(dir.clone())
+            .await
+            .unwrap()
+            .with_retry(RetryPolicy {
+                attempts: 3,
+                backoff: Duration::from_millis(5),
+            });
+        let webhooks = Arc::new(webhooks);
+        let (url, mut received) = listener(vec![500, 503]).await;
+        webhooks.register(hook("app", "", &url)).await.unwrap();
+        spawn_webhooks(provider.clone(), webhooks.clone());
+
+        provider
+            .add("x".into(), json!(1), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        let log = wait_for_log(&webhooks, "app", 3).await;
+        let statuses: Vec<_> = log.iter().map(|attempt| attempt.status).collect();
+        assert_eq!(statuses, vec![Some(200), Some(503), Some(500)]);
+        assert_eq!(
+            log.iter()
+                .map(|attempt| attempt.attempt)
+                .collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+        assert!(
+            log.iter()
+                .all(|attempt| attempt.delivery == log[0].delivery)
+        );
+        assert!(log[0].error.is_none() && log[1].error.is_some());
+
+        let mut requests = Vec::new();
+        for _ in 0..3 {
+            requests.push(received.recv().await.unwrap());
+        }
+        assert!(
+            requests
+                .iter()
+                .all(|request| request.body == requests[0].body)
+        );
+        assert!(requests.iter().all(|request| {
+            request.headers["x-objekt-delivery"] == requests[0].headers["x-objekt-delivery"]
+        }));
+
+        // nothing listens on a port once its listener is gone
+        let unreachable = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}/", listener.local_addr().unwrap())
+        };
+        webhooks.remove("app").await.unwrap();
+        webhooks
+            .register(hook("down", "", &unreachable))
+            .await
+            .unwrap();
+        provider
+            .add("y".into(), json!(1), "t".into(), Default::default())
+            .await
+            .unwrap();
+
+        let log = wait_for_log(&webhooks, "down", 3).await;
+        assert!(log.iter().all(|attempt| attempt.status.is_none()));
+        assert!(log.iter().all(|attempt| attempt.error.is_some()));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn drops_deliveries_beyond_the_pending_limit() {
+        let dir = temp_dir("pending");
+        let provider: DynProvider = Arc::new(MemoryProvider::<Value>::new(MemoryLimits::default()));
+        let webhooks = Arc::new(Webhooks::open(dir.clone()).await.unwrap());
+        // connections are queued but never answered, so every delivery stays underway
+        let silent = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", silent.local_addr().unwrap());
+        webhooks.register(hook("slow", "", &url)).await.unwrap();
+        spawn_webhooks(provider.clone(), webhooks.clone());
+
+        for i in 0..=MAX_PENDING {
+            provider
+                .add(format!("k{i}"), json!(i), "t".into(), Default::default())
+                .await
+                .unwrap();
+        }
+
+        let log = wait_for_log(&webhooks, "slow", 1).await;
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].attempt, 0);
+        assert_eq!(log[0].key, format!("k{MAX_PENDING}"));
+        assert!(log[0].error.as_ref().unwrap().starts_with("dropped"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn registrations_survive_restarts() {
+        let dir = temp_dir("restart");
+        let webhooks = Webhooks::open(dir.clone()).await.unwrap();
+        webhooks
+            .register(hook("b", "app:", "http://127.0.0.1:1/"))
+            .await
+            .unwrap();
+        webhooks
+            .register(hook("a", "", "http://127.0.0.1:1/"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            webhooks
+                .register(hook("a", "", "http://127.0.0.1:1/"))
+                .await,
+            Err(WebhookError::Exists)
+        ));
+        webhooks.remove("b").await.unwrap();
+        drop(webhooks);
+
+        let webhooks = Webhooks::open(dir.clone()).await.unwrap();
+        let names: Vec<_> = webhooks
+            .list()
+            .iter()
+            .map(|hook| hook.name.clone())
+            .collect();
+        assert_eq!(names, vec!["a"]);
+        assert!(webhooks.deliveries("a").unwrap().is_empty());
+        assert!(matches!(
+            webhooks.remove("b").await,
+            Err(WebhookError::NotFound)
+        ));
+        drop(webhooks);
+
+        // saved by hand, its secret could never sign a delivery
+        let store = FileSystemProvider::new(dir.clone()).await.unwrap();
+        let mut overlong = hook("long", "", "http://127.0.0.1:1/");
+        overlong.secret = "s".repeat(MAX_SECRET_BYTES + 1);
+        store
+            .add("long".into(), overlong, "t".into(), Default::default())
+            .await
+            .unwrap();
+        drop(store);
+
+        let webhooks = Webhooks::open(dir.clone()).await.unwrap();
+        assert_eq!(webhooks.list().len(), 1);
+        assert!(matches!(
+            webhooks.deliveries("long"),
+            Err(WebhookError::NotFound)
+        ));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}